@@ -19,6 +19,12 @@ pub fn rng_gen_range(range: Range<f64>) -> f64 {
     RNG.with(|rng| rng.borrow_mut().gen_range(range))
 }
 
+// Reseeds the sensor and battery noise of the current thread. Simulations running on the same
+// thread share this generator, so reseed before each run to make it reproducible.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed))
+}
+
 fn interpolate(a: f64, b: f64, i: f64) -> f64 {
     a + ((b - a) * i)
 }
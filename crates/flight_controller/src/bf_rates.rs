@@ -1,4 +1,4 @@
-use flight_controller::Channels;
+use crate::Channels;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
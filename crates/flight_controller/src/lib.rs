@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::{ops::Index, time::Duration};
 
+pub mod bf_rates;
 pub mod controllers;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -33,7 +34,7 @@ pub struct GyroUpdate {
 }
 
 // each channel between -1 and 1
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Channels {
     pub throttle: f64,
     pub roll: f64,
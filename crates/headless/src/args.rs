@@ -0,0 +1,297 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use loggers::format::LogFormat;
+use sim_context::{ControllerType, LoaderType, LoggerType, RerunSink};
+use simulator::schedule::MotorHold;
+use std::{path::PathBuf, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "headless",
+    about = "Run drone simulations without the visualizer"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+    /// Root of the stored drones, replays, data sets and controllers, defaults to
+    /// `$QUAD_DATA_ROOT` or `~/.local/share/quad`
+    #[arg(long, global = true)]
+    pub data_root: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Simulate a drone with the selected controller and stick input, then print a summary
+    Simulate(SimulateArgs),
+    /// Convert a flight log between the json, binary and stream formats
+    Convert(ConvertArgs),
+    /// Export a flight log to csv, one row per snapshot
+    Export(ExportArgs),
+    /// Import a csv flight log, e.g. an externally recorded flight
+    Import(ImportArgs),
+    /// Export every episode of a data set to a single csv with a `split` column
+    ExportDataset(ExportDatasetArgs),
+    /// Import a csv data set and store it through the selected loader
+    ImportDataset(ImportDatasetArgs),
+    /// Simulate the episodes of a toml data set spec and store them with the spec
+    BuildDataset(BuildDatasetArgs),
+    /// Concatenate the splits of several data sets into a new one
+    MergeDatasets(MergeDatasetsArgs),
+    /// Store the first episodes of each split of a data set as a new one
+    SubsetDataset(SubsetDatasetArgs),
+    /// Copy the drones, replays, controllers, data sets and stabilization dbs stored as files
+    /// into the sqlite database under the same data root
+    ImportFiles,
+}
+
+#[derive(Args, Debug)]
+pub struct SimulateArgs {
+    /// Drone config id, resolved through the selected loader
+    #[arg(long, default_value = "7in_4s_drone")]
+    pub config: String,
+    /// `betaflight`, `null`, `reservoir:<id>`, `izhikevich:<id>` or any other registered
+    /// controller kind as `<kind>[:<id>]`
+    #[arg(long, default_value = "betaflight")]
+    pub controller: ControllerType,
+    /// Controller that sees the same updates as `--controller` without flying, its motor inputs
+    /// are logged next to the applied ones
+    #[arg(long)]
+    pub shadow: Option<ControllerType>,
+    /// `idle`, `brownian` or `script:<path>` (a JSON array of channels, one entry per ms)
+    #[arg(long, default_value = "idle", value_parser = parse_input)]
+    pub input: InputSource,
+    /// Simulated duration in seconds
+    #[arg(long, default_value = "5", value_parser = parse_seconds)]
+    pub duration: Duration,
+    /// `empty`, `file:<simulation id>`, `binary:<simulation id>`, `stream:<simulation id>`,
+    /// `rerun:<simulation id>` (spawns a viewer), `rrd:<path>` (saves a recording) or
+    /// `rerun-connect:<simulation id>[@<url>]` (streams to a running viewer)
+    #[arg(long, default_value = "empty", value_parser = parse_logger)]
+    pub logger: LoggerType,
+    #[arg(long, value_enum, default_value_t = LoaderArg::Default)]
+    pub loader: LoaderArg,
+    /// Seeds the generated stick input and the drone noise
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Controller period in milliseconds, defaults to the controller's own
+    #[arg(long, value_parser = parse_period)]
+    pub control_period: Option<Duration>,
+    /// Delay in milliseconds between a controller tick and its motor input reaching the rotors
+    #[arg(long, default_value = "0", value_parser = parse_millis)]
+    pub actuator_latency: Duration,
+    /// How the motor input is held between controller ticks
+    #[arg(long, value_enum, default_value_t = HoldArg::ZeroOrder)]
+    pub motor_hold: HoldArg,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// Flight log to convert, the current format is detected from the file
+    pub input: PathBuf,
+    /// Where to write the converted log, defaults to overwriting the input
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = FormatArg::Binary)]
+    pub to: FormatArg,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Flight log to export, in any of the log formats
+    pub input: PathBuf,
+    /// Defaults to the input path with a `csv` extension
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Csv file with the columns written by `export`, only the first flight log is imported
+    pub input: PathBuf,
+    /// Defaults to the input path without the `csv` extension
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = FormatArg::Binary)]
+    pub to: FormatArg,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportDatasetArgs {
+    pub dataset_id: String,
+    #[arg(long)]
+    pub output: PathBuf,
+    #[arg(long, value_enum, default_value_t = LoaderArg::File)]
+    pub loader: LoaderArg,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportDatasetArgs {
+    /// Csv file with the columns written by `export-dataset`, rows without a split are training data
+    pub input: PathBuf,
+    #[arg(long)]
+    pub dataset_id: String,
+    #[arg(long, value_enum, default_value_t = LoaderArg::File)]
+    pub loader: LoaderArg,
+}
+
+#[derive(Args, Debug)]
+pub struct BuildDatasetArgs {
+    /// Toml spec with the drone, teacher controller, seed, test ratio and episode groups
+    pub spec: PathBuf,
+    /// Loader resolving the drone and controller and storing the data set
+    #[arg(long, value_enum, default_value_t = LoaderArg::File)]
+    pub loader: LoaderArg,
+    /// Worker threads, 0 uses all cores
+    #[arg(long, default_value_t = 0)]
+    pub workers: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct MergeDatasetsArgs {
+    #[arg(required = true)]
+    pub datasets: Vec<String>,
+    /// Id of the merged data set
+    #[arg(long)]
+    pub dataset_id: String,
+    #[arg(long, value_enum, default_value_t = LoaderArg::File)]
+    pub loader: LoaderArg,
+}
+
+#[derive(Args, Debug)]
+pub struct SubsetDatasetArgs {
+    pub source: String,
+    /// Id of the subset
+    #[arg(long)]
+    pub dataset_id: String,
+    /// Training episodes to keep, all if omitted
+    #[arg(long)]
+    pub train: Option<usize>,
+    /// Validation episodes to keep, all if omitted
+    #[arg(long)]
+    pub validation: Option<usize>,
+    /// Test episodes to keep, all if omitted
+    #[arg(long)]
+    pub test: Option<usize>,
+    /// Move the last kept training episodes to the validation set, e.g. for data sets built
+    /// without one
+    #[arg(long, default_value_t = 0)]
+    pub hold_out: usize,
+    #[arg(long, value_enum, default_value_t = LoaderArg::File)]
+    pub loader: LoaderArg,
+}
+
+#[derive(Debug, Clone)]
+pub enum InputSource {
+    Idle,
+    Brownian,
+    Script(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LoaderArg {
+    File,
+    Sqlite,
+    Default,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum HoldArg {
+    ZeroOrder,
+    FirstOrder,
+}
+
+impl HoldArg {
+    pub fn to_motor_hold(self) -> MotorHold {
+        match self {
+            Self::ZeroOrder => MotorHold::ZeroOrder,
+            Self::FirstOrder => MotorHold::FirstOrder,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FormatArg {
+    Json,
+    Binary,
+    Stream,
+}
+
+impl FormatArg {
+    pub fn to_log_format(self) -> LogFormat {
+        match self {
+            Self::Json => LogFormat::Json,
+            Self::Binary => LogFormat::Binary,
+            Self::Stream => LogFormat::Stream,
+        }
+    }
+}
+
+impl LoaderArg {
+    pub fn to_loader_type(self) -> LoaderType {
+        match self {
+            Self::File => LoaderType::File,
+            Self::Sqlite => LoaderType::Sqlite,
+            Self::Default => LoaderType::DefaultLoader,
+        }
+    }
+}
+
+fn parse_input(s: &str) -> Result<InputSource, String> {
+    match s.split_once(':') {
+        None if s == "idle" => Ok(InputSource::Idle),
+        None if s == "brownian" => Ok(InputSource::Brownian),
+        Some(("script", path)) => Ok(InputSource::Script(path.into())),
+        _ => Err(format!("unknown input source `{s}`")),
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|_| format!("`{s}` is not a number of seconds"))?;
+    // rejects negative, infinite and NaN durations
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration `{s}`: {e}"))
+}
+
+fn parse_millis(s: &str) -> Result<Duration, String> {
+    let millis: f64 = s
+        .parse()
+        .map_err(|_| format!("`{s}` is not a number of milliseconds"))?;
+    // rejects negative, infinite and NaN durations
+    Duration::try_from_secs_f64(millis / 1000.).map_err(|e| format!("invalid duration `{s}`: {e}"))
+}
+
+fn parse_period(s: &str) -> Result<Duration, String> {
+    let period = parse_millis(s)?;
+    if period.is_zero() {
+        return Err(format!("the period must be positive, got `{s}`"));
+    }
+    Ok(period)
+}
+
+fn parse_logger(s: &str) -> Result<LoggerType, String> {
+    match s.split_once(':') {
+        None if s == "empty" => Ok(LoggerType::Empty),
+        Some(("file", id)) => Ok(LoggerType::File(id.into())),
+        Some(("binary", id)) => Ok(LoggerType::BinaryFile(id.into())),
+        Some(("stream", id)) => Ok(LoggerType::StreamingFile(id.into())),
+        Some(("rerun", id)) => Ok(LoggerType::Rerun(id.into(), RerunSink::Spawn)),
+        Some(("rrd", path)) => {
+            let path = PathBuf::from(path);
+            let id = path
+                .file_stem()
+                .ok_or(format!("invalid recording path `{}`", path.display()))?;
+            Ok(LoggerType::Rerun(
+                id.to_string_lossy().into_owned(),
+                RerunSink::File(path),
+            ))
+        }
+        Some(("rerun-connect", rest)) => {
+            let (id, url) = match rest.split_once('@') {
+                Some((id, url)) => (id, Some(url.to_owned())),
+                None => (rest, None),
+            };
+            Ok(LoggerType::Rerun(id.into(), RerunSink::Connect(url)))
+        }
+        _ => Err(format!("unknown logger `{s}`")),
+    }
+}
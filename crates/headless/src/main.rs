@@ -0,0 +1,296 @@
+//! Headless counterpart of the visualizer. It wires a `SimContext` from the command line and runs
+//! the simulation to completion without a window, so that batch jobs don't need a display or the
+//! test harness.
+mod args;
+mod summary;
+
+use args::{
+    BuildDatasetArgs, Cli, Command, ConvertArgs, ExportArgs, ExportDatasetArgs, ImportArgs,
+    ImportDatasetArgs, InputSource, MergeDatasetsArgs, SimulateArgs, SubsetDatasetArgs,
+};
+use clap::Parser;
+use flight_controller::Channels;
+use loaders::{FlDataSet, Split, file_loader::FileLoader, sqlite_loader::SqliteLoader};
+use loggers::{
+    format::{decode, encode},
+    storage, tabular,
+};
+use sim_context::{
+    SimContext,
+    dataset_spec::DataSetSpec,
+    input_gen::{InputGenerationMethod, InputGenerator},
+};
+use simulator::schedule::Actuation;
+use std::{
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use summary::Summary;
+
+// Same fixed step as the dataset generation. Scripts and generators provide one input per step.
+const STEP: Duration = Duration::from_millis(1);
+
+fn stick_inputs(
+    input: &InputSource,
+    duration: Duration,
+    seed: u64,
+) -> Result<Vec<Channels>, Box<dyn Error>> {
+    let channels = match input {
+        InputSource::Idle => InputGenerator::default().generate(duration),
+        InputSource::Brownian => InputGenerator::default()
+            .set_seed(seed)
+            .set_throttle(InputGenerationMethod::Brownian)
+            .set_yaw(InputGenerationMethod::Brownian)
+            .set_pitch(InputGenerationMethod::Brownian)
+            .set_roll(InputGenerationMethod::Brownian)
+            .generate(duration),
+        InputSource::Script(path) => {
+            let content = fs::read_to_string(path)?;
+            let mut script: Vec<Channels> = serde_json::from_str(&content)?;
+            let steps = duration.as_millis() as usize;
+            // hold the last stick position if the script is shorter than the run
+            let last = script.last().copied().unwrap_or_default();
+            script.resize(steps, last);
+            script
+        }
+    };
+    Ok(channels)
+}
+
+fn simulate(args: SimulateArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let SimulateArgs {
+        config,
+        controller,
+        shadow,
+        input,
+        duration,
+        logger,
+        loader,
+        seed,
+        control_period,
+        actuator_latency,
+        motor_hold,
+    } = args;
+    let inputs = stick_inputs(&input, duration, seed)?;
+
+    let mut context = SimContext {
+        seed,
+        data_root,
+        ..Default::default()
+    };
+    context.set_loader(&loader.to_loader_type())?;
+    context.config_id = Some(config);
+    context.set_controller(controller)?;
+    context.set_shadow_controller(shadow)?;
+    context.set_logger(logger);
+
+    let mut simulation = context
+        .try_load_simulator()
+        .ok_or("no drone config selected")??;
+    simulation.scheduler_delta = control_period;
+    simulation.actuation = Actuation::new(actuator_latency, motor_hold.to_motor_hold());
+    simulation.init()?;
+
+    let start = Instant::now();
+    let mut summary = Summary::default();
+    for channels in inputs {
+        summary.record(simulation.simulate_delta(STEP, channels));
+    }
+    summary.wall_time = start.elapsed();
+    summary.tick_stats = simulation.tick_stats.clone();
+    println!("{summary}");
+    Ok(())
+}
+
+fn convert(args: ConvertArgs) -> Result<(), Box<dyn Error>> {
+    let ConvertArgs { input, output, to } = args;
+    let flight_log = decode(&fs::read(&input)?)?;
+    let output = output.unwrap_or(input);
+    fs::write(&output, encode(&flight_log, to.to_log_format()))?;
+    println!(
+        "wrote {} steps of {} to {}",
+        flight_log.steps.len(),
+        flight_log.simulation_id,
+        output.display()
+    );
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let ExportArgs { input, output } = args;
+    let flight_log = decode(&fs::read(&input)?)?;
+    let output = output.unwrap_or_else(|| input.with_extension("csv"));
+    tabular::write_flight_log(fs::File::create(&output)?, &flight_log)?;
+    println!(
+        "exported {} steps to {}",
+        flight_log.steps.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn import(args: ImportArgs) -> Result<(), Box<dyn Error>> {
+    let ImportArgs { input, output, to } = args;
+    let flight_log = tabular::read_flight_log(fs::File::open(&input)?)?
+        .ok_or(format!("{} has no rows", input.display()))?;
+    let output = output.unwrap_or_else(|| input.with_extension(""));
+    fs::write(&output, encode(&flight_log, to.to_log_format()))?;
+    println!(
+        "imported {} steps of {} to {}",
+        flight_log.steps.len(),
+        flight_log.simulation_id,
+        output.display()
+    );
+    Ok(())
+}
+
+fn episode_counts(dataset: &FlDataSet) -> String {
+    format!(
+        "{} train, {} validation and {} test episodes",
+        dataset.train_data.len(),
+        dataset.validation_data.len(),
+        dataset.test_data.len(),
+    )
+}
+
+fn export_dataset(args: ExportDatasetArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let ExportDatasetArgs {
+        dataset_id,
+        output,
+        loader,
+    } = args;
+    let mut context = SimContext {
+        data_root,
+        ..Default::default()
+    };
+    context.set_loader(&loader.to_loader_type())?;
+    let dataset = context.loader.lock().unwrap().load_data_set(&dataset_id)?;
+    dataset.write_csv(fs::File::create(&output)?)?;
+    println!(
+        "exported {} to {}",
+        episode_counts(&dataset),
+        output.display()
+    );
+    Ok(())
+}
+
+fn import_dataset(args: ImportDatasetArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let ImportDatasetArgs {
+        input,
+        dataset_id,
+        loader,
+    } = args;
+    let dataset = FlDataSet::read_csv(&dataset_id, fs::File::open(&input)?)?;
+    println!("importing {} as {dataset_id}", episode_counts(&dataset),);
+    let mut context = SimContext {
+        data_root,
+        ..Default::default()
+    };
+    context.set_loader(&loader.to_loader_type())?;
+    context.loader.lock().unwrap().insert_data_set(dataset)?;
+    Ok(())
+}
+
+fn build_dataset(args: BuildDatasetArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let BuildDatasetArgs {
+        spec,
+        loader,
+        workers,
+    } = args;
+    let spec = DataSetSpec::from_toml(&fs::read_to_string(&spec)?)?;
+    let index = spec.build(
+        loader.to_loader_type(),
+        data_root,
+        workers,
+        |simulation_id, stored, total| println!("{simulation_id} done ({stored}/{total})"),
+    )?;
+    let count = |split| index.split(split).count();
+    println!(
+        "stored {} train, {} validation and {} test episodes as {}",
+        count(Split::Train),
+        count(Split::Validation),
+        count(Split::Test),
+        index.dataset_id,
+    );
+    Ok(())
+}
+
+fn merge_datasets(args: MergeDatasetsArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let MergeDatasetsArgs {
+        datasets,
+        dataset_id,
+        loader,
+    } = args;
+    let mut context = SimContext {
+        data_root,
+        ..Default::default()
+    };
+    context.set_loader(&loader.to_loader_type())?;
+    let mut loader = context.loader.lock().unwrap();
+    let datasets = datasets
+        .iter()
+        .map(|dataset_id| loader.load_data_set(dataset_id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let merged = FlDataSet::merge(&dataset_id, datasets);
+    println!("storing {} as {dataset_id}", episode_counts(&merged));
+    loader.insert_data_set(merged)?;
+    Ok(())
+}
+
+fn subset_dataset(args: SubsetDatasetArgs, data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let SubsetDatasetArgs {
+        source,
+        dataset_id,
+        train,
+        validation,
+        test,
+        hold_out,
+        loader,
+    } = args;
+    let mut context = SimContext {
+        data_root,
+        ..Default::default()
+    };
+    context.set_loader(&loader.to_loader_type())?;
+    let mut loader = context.loader.lock().unwrap();
+    let limit = |split| match split {
+        Split::Train => train,
+        Split::Validation => validation,
+        Split::Test => test,
+    };
+    let mut subset = loader
+        .load_data_set(&source)?
+        .subset(&dataset_id, |split, position, _| {
+            limit(split).is_none_or(|limit| position < limit)
+        });
+    subset.hold_out_validation(hold_out);
+    println!("storing {} as {dataset_id}", episode_counts(&subset));
+    loader.insert_data_set(subset)?;
+    Ok(())
+}
+
+fn import_files(data_root: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut files = FileLoader::new(data_root.clone());
+    let counts = SqliteLoader::open_in_root(&data_root)?.import_files(&mut files)?;
+    println!("imported {counts}");
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Cli { command, data_root } = Cli::parse();
+    let data_root = data_root.unwrap_or_else(storage::data_root);
+    match command {
+        Command::Simulate(args) => simulate(args, data_root),
+        Command::Convert(args) => convert(args),
+        Command::Export(args) => export(args),
+        Command::Import(args) => import(args),
+        Command::ExportDataset(args) => export_dataset(args, data_root),
+        Command::ImportDataset(args) => import_dataset(args, data_root),
+        Command::BuildDataset(args) => build_dataset(args, data_root),
+        Command::MergeDatasets(args) => merge_datasets(args, data_root),
+        Command::SubsetDataset(args) => subset_dataset(args, data_root),
+        Command::ImportFiles => import_files(data_root),
+    }
+}
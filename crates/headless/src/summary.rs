@@ -0,0 +1,65 @@
+use simulator::{SimulationObservation, schedule::TickStats};
+use std::{fmt, time::Duration};
+
+/// Aggregated statistics of a headless run, printed once the simulation is done.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub steps: usize,
+    pub simulated_time: Duration,
+    pub wall_time: Duration,
+    pub final_observation: SimulationObservation,
+    pub max_angular_velocity: f64,
+    pub min_bat_voltage_sag: Option<f64>,
+    pub tick_stats: TickStats,
+}
+
+impl Summary {
+    pub fn record(&mut self, observation: SimulationObservation) {
+        self.steps += 1;
+        self.simulated_time = observation.simulation_time;
+        self.max_angular_velocity = f64::max(
+            self.max_angular_velocity,
+            observation.angular_velocity.norm(),
+        );
+        let sag = observation.bat_voltage_sag;
+        self.min_bat_voltage_sag = Some(self.min_bat_voltage_sag.map_or(sag, |m| m.min(sag)));
+        self.final_observation = observation;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let obs = &self.final_observation;
+        let real_time_factor = self.simulated_time.as_secs_f64() / self.wall_time.as_secs_f64();
+        writeln!(f, "--- Simulation summary ---")?;
+        writeln!(f, "steps: {}", self.steps)?;
+        writeln!(f, "simulated time: {:?}", self.simulated_time)?;
+        writeln!(
+            f,
+            "wall time: {:?} ({real_time_factor:.2}x real time)",
+            self.wall_time
+        )?;
+        writeln!(
+            f,
+            "final position: [{:.3}, {:.3}, {:.3}]",
+            obs.position.x, obs.position.y, obs.position.z
+        )?;
+        writeln!(f, "final speed: {:.3} m/s", obs.linear_velocity.norm())?;
+        writeln!(
+            f,
+            "max angular velocity: {:.3} rad/s",
+            self.max_angular_velocity
+        )?;
+        writeln!(
+            f,
+            "controller ticks: {} ({} catch-up, max lag {:?})",
+            self.tick_stats.ticks, self.tick_stats.catch_up_ticks, self.tick_stats.max_lag
+        )?;
+        writeln!(f, "final battery voltage: {:.3} V", obs.bat_voltage)?;
+        write!(
+            f,
+            "min battery voltage sag: {:.3} V",
+            self.min_bat_voltage_sag.unwrap_or_default()
+        )
+    }
+}
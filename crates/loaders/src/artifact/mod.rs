@@ -0,0 +1,181 @@
+// Saved controllers are wrapped in an envelope that records how they were made: the controller
+// kind, the input features the readout was trained on, the training parameters, the data set,
+// evaluation scores and when it was saved. Loading checks the version, the kind and the input
+// features, so a controller trained on a different `ReservoirInput` layout is rejected instead of
+// silently producing garbage. Controllers saved before the envelope existed are loaded as is.
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::LoaderError;
+
+pub const ARTIFACT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerArtifact {
+    pub format_version: u32,
+    pub kind: String,
+    // names of the reservoir inputs in order, empty if the controller doesn't use them
+    pub input_features: Vec<String>,
+    pub parameters: Option<Value>,
+    pub dataset_id: Option<String>,
+    pub scores: BTreeMap<String, f64>,
+    // unix time in seconds
+    pub created_at: u64,
+    pub controller: Value,
+}
+
+impl ControllerArtifact {
+    pub fn new<T: Serialize>(kind: &str, controller: &T) -> Result<Self, LoaderError> {
+        Ok(Self {
+            format_version: ARTIFACT_VERSION,
+            kind: kind.into(),
+            input_features: vec![],
+            parameters: None,
+            dataset_id: None,
+            scores: BTreeMap::new(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            controller: serde_json::to_value(controller)?,
+        })
+    }
+
+    pub fn with_input_features(mut self, input_features: &[&str]) -> Self {
+        self.input_features = input_features.iter().map(|&f| f.into()).collect();
+        self
+    }
+
+    pub fn with_parameters<P: Serialize>(mut self, parameters: &P) -> Result<Self, LoaderError> {
+        self.parameters = Some(serde_json::to_value(parameters)?);
+        Ok(self)
+    }
+
+    pub fn with_dataset_id(mut self, dataset_id: &str) -> Self {
+        self.dataset_id = Some(dataset_id.into());
+        self
+    }
+
+    pub fn with_score(mut self, name: &str, score: f64) -> Self {
+        self.scores.insert(name.into(), score);
+        self
+    }
+
+    // Legacy files are a bare controller, they become an artifact without metadata
+    pub fn decode(content: &str, kind: &str) -> Result<Self, LoaderError> {
+        let value: Value = serde_json::from_str(content)?;
+        if value.get("format_version").is_none() {
+            return Ok(Self {
+                format_version: ARTIFACT_VERSION,
+                kind: kind.into(),
+                input_features: vec![],
+                parameters: None,
+                dataset_id: None,
+                scores: BTreeMap::new(),
+                created_at: 0,
+                controller: value,
+            });
+        }
+        let artifact: Self = serde_json::from_value(value)?;
+        if artifact.format_version != ARTIFACT_VERSION {
+            return Err(LoaderError::VersionMismatch {
+                expected: ARTIFACT_VERSION,
+                found: artifact.format_version,
+            });
+        }
+        if artifact.kind != kind {
+            return Err(LoaderError::KindMismatch {
+                expected: kind.into(),
+                found: artifact.kind,
+            });
+        }
+        Ok(artifact)
+    }
+
+    pub fn encode(&self) -> Result<String, LoaderError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    // Legacy artifacts don't record their features and are trusted
+    pub fn check_input_features(&self, expected: &[&str]) -> Result<(), LoaderError> {
+        if self.input_features.is_empty() || self.input_features == expected {
+            return Ok(());
+        }
+        Err(LoaderError::InputFeatureMismatch {
+            expected: expected.iter().map(|&f| f.into()).collect(),
+            found: self.input_features.clone(),
+        })
+    }
+
+    pub fn controller<T: DeserializeOwned>(&self) -> Result<T, LoaderError> {
+        Ok(T::deserialize(&self.controller)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        LoaderError, RESERVOIR_KIND,
+        artifact::{ARTIFACT_VERSION, ControllerArtifact},
+    };
+    use res_controller::controllers::esn::NonAdaptingDroneRc;
+
+    #[test]
+    fn artifact_is_validated_on_load() {
+        let artifact = ControllerArtifact::new("reservoir", &[1., 2.])
+            .unwrap()
+            .with_input_features(&["throttle", "roll_rate"])
+            .with_dataset_id("10_len")
+            .with_score("mean_mse", 0.25);
+        let encoded = artifact.encode().unwrap();
+
+        let decoded = ControllerArtifact::decode(&encoded, "reservoir").unwrap();
+        assert_eq!(decoded.dataset_id.as_deref(), Some("10_len"));
+        assert_eq!(decoded.controller::<[f64; 2]>().unwrap(), [1., 2.]);
+        assert!(
+            decoded
+                .check_input_features(&["throttle", "roll_rate"])
+                .is_ok()
+        );
+        assert!(matches!(
+            decoded.check_input_features(&["throttle"]),
+            Err(LoaderError::InputFeatureMismatch { .. })
+        ));
+        assert!(matches!(
+            ControllerArtifact::decode(&encoded, "izhikevich"),
+            Err(LoaderError::KindMismatch { .. })
+        ));
+
+        let newer = encoded.replace(
+            &format!("\"format_version\":{ARTIFACT_VERSION}"),
+            "\"format_version\":99",
+        );
+        assert!(matches!(
+            ControllerArtifact::decode(&newer, "reservoir"),
+            Err(LoaderError::VersionMismatch { found: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn pre_trained_controller_still_loads() {
+        let content = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../pre_trained_controllers/internal_50"
+        ))
+        .unwrap();
+        let artifact = ControllerArtifact::decode(&content, RESERVOIR_KIND).unwrap();
+        assert!(artifact.controller::<NonAdaptingDroneRc>().is_ok());
+    }
+
+    #[test]
+    fn bare_controllers_still_load() {
+        let decoded = ControllerArtifact::decode("[1.0,2.0]", "reservoir").unwrap();
+        assert!(decoded.input_features.is_empty());
+        assert_eq!(decoded.controller::<[f64; 2]>().unwrap(), [1., 2.]);
+    }
+}
@@ -1,59 +1,87 @@
 // NOTE: only loads the default drone config. This is for debugging and stuff
 
 use drone::default_drone::default_7in_4s_drone;
-use res_controller::controllers::{
-    esn::NonAdaptingDroneRc, izhikevich_controller::IzhikevichController,
-};
 
-use crate::{FlDataSet, LoaderTrait};
+use crate::{
+    AngularRateStabilizationDb, DataSetIndex, EpisodeRef, LoaderError, LoaderTrait, Split,
+};
+use loggers::FlightLog;
 
 #[derive(Debug, Default)]
 pub struct DefaultLoader {}
 
 impl LoaderTrait for DefaultLoader {
-    fn load_drone(&mut self, _config_id: &str) -> drone::Drone {
-        default_7in_4s_drone()
+    fn load_drone(&mut self, _config_id: &str) -> Result<drone::Drone, LoaderError> {
+        Ok(default_7in_4s_drone())
     }
 
-    fn load_flight_log(&mut self, _sim_id: &str) -> loggers::FlightLog {
-        unreachable!()
+    fn load_flight_log(&mut self, sim_id: &str) -> Result<loggers::FlightLog, LoaderError> {
+        Err(LoaderError::NotFound(sim_id.into()))
     }
 
-    fn get_replay_ids(&mut self) -> Vec<String> {
-        vec![]
+    fn get_replay_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        Ok(vec![])
     }
 
-    fn get_reservoir_controller_ids(&mut self) -> Vec<String> {
-        vec![]
+    fn get_controller_ids(&mut self, _kind: &str) -> Result<Vec<String>, LoaderError> {
+        Ok(vec![])
     }
 
-    fn get_izhikevich_controller_ids(&mut self) -> Vec<String> {
-        vec![]
+    // nothing is persisted by the default loader
+    fn insert_controller(
+        &mut self,
+        _kind: &str,
+        _controller_id: &str,
+        _controller: &str,
+    ) -> Result<(), LoaderError> {
+        Ok(())
     }
 
-    fn load_res_controller(&mut self, _controller_id: &str) -> NonAdaptingDroneRc {
-        unreachable!()
+    fn load_controller(&mut self, _kind: &str, controller_id: &str) -> Result<String, LoaderError> {
+        Err(LoaderError::NotFound(controller_id.into()))
     }
 
-    fn insert_rc_controller(&mut self, _controller_id: &str, _controller: NonAdaptingDroneRc) {
-        todo!()
+    fn data_set_index(&mut self, _dataset_id: &str) -> Result<DataSetIndex, LoaderError> {
+        Ok(DataSetIndex::default())
     }
 
-    fn load_data_set(&mut self, _dataset_id: &str) -> FlDataSet {
-        FlDataSet::default()
+    fn load_episode(
+        &mut self,
+        _dataset_id: &str,
+        episode: &EpisodeRef,
+    ) -> Result<FlightLog, LoaderError> {
+        Err(LoaderError::NotFound(episode.key.clone()))
     }
 
-    fn insert_data_set(&mut self, _dataset: FlDataSet) {}
+    fn insert_episode(
+        &mut self,
+        _dataset_id: &str,
+        _split: Split,
+        position: usize,
+        _flight_log: &FlightLog,
+    ) -> Result<String, LoaderError> {
+        Ok(position.to_string())
+    }
 
-    fn insert_izhikevich_controller(
+    fn insert_data_set_index(&mut self, _index: &DataSetIndex) -> Result<(), LoaderError> {
+        Ok(())
+    }
+
+    fn get_stabilization_db_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        Ok(vec![])
+    }
+
+    fn insert_stabilization_db(
         &mut self,
-        _controller_id: &str,
-        _controller: &IzhikevichController,
-    ) {
-        todo!()
+        _db: &AngularRateStabilizationDb,
+    ) -> Result<(), LoaderError> {
+        Ok(())
     }
 
-    fn load_izhikevich_controller(&mut self, _controller_id: &str) -> IzhikevichController {
-        todo!()
+    fn load_stabilization_db(
+        &mut self,
+        db_name: &str,
+    ) -> Result<AngularRateStabilizationDb, LoaderError> {
+        Err(LoaderError::NotFound(db_name.into()))
     }
 }
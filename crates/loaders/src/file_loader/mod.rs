@@ -1,201 +1,468 @@
 use drone::Drone;
-use res_controller::controllers::{
-    esn::NonAdaptingDroneRc, izhikevich_controller::IzhikevichController,
+use loggers::{FlightLog, format::decode, storage::data_root};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
 };
-use std::{fs, path::PathBuf};
 
-use crate::{FlDataSet, LoaderTrait};
+use crate::{
+    AngularRateStabilizationDb, DataSetIndex, EpisodeMetadata, EpisodeRef, IZHIKEVICH_KIND,
+    LoaderError, LoaderTrait, RESERVOIR_KIND, Split,
+};
+
+// Written next to the episodes of a data set
+const SPEC_FILE: &str = "spec.toml";
+const MANIFEST_FILE: &str = "manifest.json";
+
+// The episodes of a data set in order, with their split and metadata
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    parents: Vec<String>,
+    episodes: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    file: String,
+    split: Split,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<EpisodeMetadata>,
+}
+
+impl Manifest {
+    // Data sets written before the manifest, the split comes from the file name prefix and the
+    // episodes are ordered by the number after it
+    fn from_file_names(dataset_dir: &Path) -> Result<Self, LoaderError> {
+        let mut episodes = vec![];
+        for entry in fs::read_dir(dataset_dir)? {
+            let file = entry?.file_name().to_string_lossy().into_owned();
+            let episode = Split::ALL.into_iter().find_map(|split| {
+                let position = file
+                    .strip_prefix(split.file_prefix())?
+                    .parse::<u64>()
+                    .ok()?;
+                Some((split, position))
+            });
+            if let Some((split, position)) = episode {
+                episodes.push((split, position, file));
+            }
+        }
+        episodes.sort();
+        let episodes = episodes
+            .into_iter()
+            .map(|(split, _, file)| ManifestEntry {
+                file,
+                split,
+                metadata: None,
+            })
+            .collect();
+        Ok(Self {
+            parents: vec![],
+            episodes,
+        })
+    }
+}
 
+// The default root, see `loggers::storage`
 pub fn loader_path() -> PathBuf {
-    PathBuf::from(std::env::var("HOME").unwrap()).join(".local/share/quad")
+    data_root()
+}
+
+// Reports a missing file as `NotFound` rather than an i/o error
+fn not_found(err: io::Error, id: &str) -> LoaderError {
+    match err.kind() {
+        io::ErrorKind::NotFound => LoaderError::NotFound(id.into()),
+        _ => LoaderError::Io(err),
+    }
+}
+
+fn read_file(path: &Path, id: &str) -> Result<String, LoaderError> {
+    fs::read_to_string(path).map_err(|err| not_found(err, id))
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, LoaderError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn remove_optional(path: &Path) -> Result<(), LoaderError> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct FileLoader {}
+fn read_bytes(path: &Path, id: &str) -> Result<Vec<u8>, LoaderError> {
+    fs::read(path).map_err(|err| not_found(err, id))
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<String>, LoaderError> {
+    fs::create_dir_all(dir)?;
+    let ids = fs::read_dir(dir)?
+        .map(|res| res.map(|e| e.path()))
+        .filter_map(|res| res.ok().map(|t| t.to_str().unwrap().to_owned()))
+        .collect::<Vec<_>>();
+    Ok(ids)
+}
+
+// File or directory names in `dir`, sorted, none if it doesn't exist
+fn entry_names(dir: &Path, dirs: bool) -> Result<Vec<String>, LoaderError> {
+    let mut names = vec![];
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(names),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() == dirs {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[derive(Debug)]
+pub struct FileLoader {
+    root: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    // The reservoir and Izhikevich controllers keep the directories they had before controllers
+    // were stored by kind
+    fn controller_dir(&self, kind: &str) -> PathBuf {
+        match kind {
+            RESERVOIR_KIND => self.root.join("reservoirs"),
+            IZHIKEVICH_KIND => self.root.join("izhikevich_controllers"),
+            kind => self.root.join("controllers").join(kind),
+        }
+    }
+
+    // The listings below are names, as opposed to the paths of `get_replay_ids` and
+    // `get_controller_ids`, for copying everything to another loader
+
+    pub fn drone_ids(&self) -> Result<Vec<String>, LoaderError> {
+        let files = entry_names(&self.root.join("drones"), false)?;
+        Ok(files
+            .iter()
+            .filter_map(|file| file.strip_suffix(".json"))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    // The replays outside of data sets
+    pub fn replay_names(&self) -> Result<Vec<String>, LoaderError> {
+        entry_names(&self.root.join("replays"), false)
+    }
+
+    // Data sets are the directories next to the replays
+    pub fn data_set_ids(&self) -> Result<Vec<String>, LoaderError> {
+        entry_names(&self.root.join("replays"), true)
+    }
+
+    pub fn controller_kinds(&self) -> Result<Vec<String>, LoaderError> {
+        let mut kinds = vec![RESERVOIR_KIND.to_owned(), IZHIKEVICH_KIND.to_owned()];
+        kinds.extend(entry_names(&self.root.join("controllers"), true)?);
+        Ok(kinds)
+    }
+
+    pub fn controller_names(&self, kind: &str) -> Result<Vec<String>, LoaderError> {
+        entry_names(&self.controller_dir(kind), false)
+    }
+}
+
+impl Default for FileLoader {
+    fn default() -> Self {
+        Self::new(data_root())
+    }
+}
 
 impl LoaderTrait for FileLoader {
-    fn load_drone(&mut self, config_id: &str) -> Drone {
-        let mut drone_path = loader_path();
+    fn load_drone(&mut self, config_id: &str) -> Result<Drone, LoaderError> {
+        let mut drone_path = self.root.clone();
         drone_path.push("drones/");
-        fs::create_dir_all(&drone_path).unwrap();
+        fs::create_dir_all(&drone_path)?;
         drone_path.push(format!("{config_id}.json"));
-        let content = fs::read_to_string(drone_path).unwrap();
-        serde_json::from_slice(content.as_bytes()).unwrap()
+        let content = read_file(&drone_path, config_id)?;
+        Ok(serde_json::from_str(&content)?)
     }
 
-    fn load_flight_log(&mut self, sim_id: &str) -> loggers::FlightLog {
-        let mut replay = loader_path();
+    fn load_flight_log(&mut self, sim_id: &str) -> Result<loggers::FlightLog, LoaderError> {
+        let mut replay = self.root.clone();
         replay.push("replays/");
-        fs::create_dir_all(&replay).unwrap();
+        fs::create_dir_all(&replay)?;
         replay.push(sim_id);
-        let content = fs::read_to_string(replay).unwrap();
-        serde_json::from_slice(content.as_bytes()).unwrap()
+        // json or binary, detected from the header
+        let content = read_bytes(&replay, sim_id)?;
+        Ok(decode(&content)?)
     }
 
     // just list the file names in the loader.
-    fn get_replay_ids(&mut self) -> Vec<String> {
-        let mut replays_dir = loader_path();
+    fn get_replay_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        let mut replays_dir = self.root.clone();
         replays_dir.push("replays/");
-        fs::create_dir_all(&replays_dir).unwrap();
-        fs::read_dir(replays_dir)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .filter_map(|res| res.ok().map(|t| t.to_str().unwrap().to_owned()))
-            .collect::<Vec<_>>()
-    }
-
-    fn get_reservoir_controller_ids(&mut self) -> Vec<String> {
-        let mut reservoir_dir = loader_path();
-        reservoir_dir.push("reservoirs/");
-        fs::create_dir_all(&reservoir_dir).unwrap();
-        fs::read_dir(reservoir_dir)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .filter_map(|res| res.ok().map(|t| t.to_str().unwrap().to_owned()))
-            .collect::<Vec<_>>()
-    }
-
-    fn get_izhikevich_controller_ids(&mut self) -> Vec<String> {
-        let mut controller_dir = loader_path();
-        controller_dir.push("izhikevich_controllers/");
-        fs::create_dir_all(&controller_dir).unwrap();
-        fs::read_dir(controller_dir)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .filter_map(|res| res.ok().map(|t| t.to_str().unwrap().to_owned()))
-            .collect::<Vec<_>>()
-    }
-
-    fn insert_rc_controller(&mut self, controller_id: &str, controller: NonAdaptingDroneRc) {
-        let mut reservoir_dir = loader_path();
-        reservoir_dir.push("reservoirs/");
-        fs::create_dir_all(&reservoir_dir).unwrap();
-        reservoir_dir.push(controller_id);
-        let serialized = serde_json::to_string(&controller).unwrap();
-        fs::write(reservoir_dir, serialized).unwrap();
-    }
-
-    fn load_res_controller(&mut self, controller_id: &str) -> NonAdaptingDroneRc {
-        let mut reservoir_dir = loader_path();
-        reservoir_dir.push("reservoirs/");
-        fs::create_dir_all(&reservoir_dir).unwrap();
-        reservoir_dir.push(controller_id);
-        let content = fs::read_to_string(reservoir_dir).unwrap();
-        serde_json::from_slice(content.as_bytes()).unwrap()
-    }
-
-    fn load_data_set(&mut self, dataset_id: &str) -> FlDataSet {
-        let mut dataset_dir = loader_path();
+        list_dir(&replays_dir)
+    }
+
+    fn get_controller_ids(&mut self, kind: &str) -> Result<Vec<String>, LoaderError> {
+        list_dir(&self.controller_dir(kind))
+    }
+
+    fn insert_controller(
+        &mut self,
+        kind: &str,
+        controller_id: &str,
+        controller: &str,
+    ) -> Result<(), LoaderError> {
+        let mut controller_path = self.controller_dir(kind);
+        fs::create_dir_all(&controller_path)?;
+        controller_path.push(controller_id);
+        fs::write(controller_path, controller)?;
+        Ok(())
+    }
+
+    fn load_controller(&mut self, kind: &str, controller_id: &str) -> Result<String, LoaderError> {
+        let mut controller_path = self.controller_dir(kind);
+        fs::create_dir_all(&controller_path)?;
+        controller_path.push(controller_id);
+        read_file(&controller_path, controller_id)
+    }
+
+    // The key of an episode is its file name
+    fn data_set_index(&mut self, dataset_id: &str) -> Result<DataSetIndex, LoaderError> {
+        let mut dataset_dir = self.root.clone();
         dataset_dir.push(format!("replays/{dataset_id}/"));
-        fs::create_dir_all(&dataset_dir).unwrap();
-        let entries = fs::read_dir(dataset_dir)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-        let train_set = entries
-            .iter()
-            .filter(|e| {
-                // file name
-                let filename = e.file_name().unwrap();
-                filename
-                    .as_encoded_bytes()
-                    .starts_with("training_".as_bytes())
-            })
-            .collect::<Vec<_>>();
-        let test_set = entries
-            .iter()
-            .filter(|e| {
-                let filename = e.file_name().unwrap();
-                filename
-                    .as_encoded_bytes()
-                    .starts_with("testing_".as_bytes())
-            })
-            .collect::<Vec<_>>();
-        let train_data = train_set
-            .iter()
-            .map(|path| {
-                let content = fs::read_to_string(path).unwrap();
-                serde_json::from_slice(content.as_bytes()).unwrap()
-            })
-            .collect();
-        let test_data = test_set
-            .iter()
-            .map(|path| {
-                let content = fs::read_to_string(path).unwrap();
-                serde_json::from_slice(content.as_bytes()).unwrap()
+        if !dataset_dir.is_dir() {
+            return Err(LoaderError::NotFound(dataset_id.into()));
+        }
+        let manifest = match read_optional(&dataset_dir.join(MANIFEST_FILE))? {
+            Some(manifest) => serde_json::from_str(&manifest)?,
+            None => Manifest::from_file_names(&dataset_dir)?,
+        };
+        let episodes = manifest
+            .episodes
+            .into_iter()
+            .map(|entry| EpisodeRef {
+                split: entry.split,
+                key: entry.file,
+                metadata: entry.metadata,
             })
             .collect();
-        FlDataSet {
+        Ok(DataSetIndex {
             dataset_id: dataset_id.into(),
-            train_data,
-            test_data,
-        }
+            episodes,
+            parents: manifest.parents,
+            spec: read_optional(&dataset_dir.join(SPEC_FILE))?,
+        })
     }
 
-    fn insert_data_set(&mut self, dataset: FlDataSet) {
-        let FlDataSet {
-            dataset_id,
-            train_data,
-            test_data,
-        } = dataset;
-        let mut dataset_dir = loader_path();
+    fn load_episode(
+        &mut self,
+        dataset_id: &str,
+        episode: &EpisodeRef,
+    ) -> Result<FlightLog, LoaderError> {
+        let mut episode_path = self.root.clone();
+        episode_path.push(format!("replays/{dataset_id}/"));
+        episode_path.push(&episode.key);
+        let content = read_bytes(&episode_path, &episode.key)?;
+        Ok(decode(&content)?)
+    }
+
+    // Episodes are named after their split and position
+    fn insert_episode(
+        &mut self,
+        dataset_id: &str,
+        split: Split,
+        position: usize,
+        flight_log: &FlightLog,
+    ) -> Result<String, LoaderError> {
+        let mut dataset_dir = self.root.clone();
         dataset_dir.push(format!("replays/{dataset_id}/"));
-        fs::create_dir_all(&dataset_dir).unwrap();
-        for fl in train_data {
-            let mut fl_path = dataset_dir.clone();
-            fl_path.push(&fl.simulation_id);
-            let contents = serde_json::to_string(&fl).unwrap();
-            fs::write(fl_path, contents).unwrap();
+        fs::create_dir_all(&dataset_dir)?;
+        let file = format!("{}{position}", split.file_prefix());
+        fs::write(dataset_dir.join(&file), serde_json::to_string(flight_log)?)?;
+        Ok(file)
+    }
+
+    // The manifest lists the episodes in order. Overwriting a data set removes the episodes of
+    // the previous version.
+    fn insert_data_set_index(&mut self, index: &DataSetIndex) -> Result<(), LoaderError> {
+        let mut dataset_dir = self.root.clone();
+        dataset_dir.push(format!("replays/{}/", index.dataset_id));
+        fs::create_dir_all(&dataset_dir)?;
+        for file in Manifest::from_file_names(&dataset_dir)?.episodes {
+            if !index
+                .episodes
+                .iter()
+                .any(|episode| episode.key == file.file)
+            {
+                fs::remove_file(dataset_dir.join(file.file))?;
+            }
         }
-        for fl in test_data {
-            let mut fl_path = dataset_dir.clone();
-            fl_path.push(&fl.simulation_id);
-            let contents = serde_json::to_string(&fl).unwrap();
-            fs::write(fl_path, contents).unwrap();
+        let manifest = Manifest {
+            parents: index.parents.clone(),
+            episodes: index
+                .episodes
+                .iter()
+                .map(|episode| ManifestEntry {
+                    file: episode.key.clone(),
+                    split: episode.split,
+                    metadata: episode.metadata.clone(),
+                })
+                .collect(),
+        };
+        fs::write(
+            dataset_dir.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(&manifest)?,
+        )?;
+        match &index.spec {
+            Some(spec) => fs::write(dataset_dir.join(SPEC_FILE), spec)?,
+            None => remove_optional(&dataset_dir.join(SPEC_FILE))?,
         }
+        Ok(())
     }
 
-    fn insert_izhikevich_controller(
-        &mut self,
-        controller_id: &str,
-        controller: &IzhikevichController,
-    ) {
-        let mut controller_path = loader_path();
-        controller_path.push("izhikevich_controllers/");
-        fs::create_dir_all(&controller_path).unwrap();
-        controller_path.push(controller_id);
-        let serialized = serde_json::to_string(controller).unwrap();
-        fs::write(controller_path, serialized).unwrap();
+    // unlike the other ids these are the file names, not the full paths
+    fn get_stabilization_db_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        let mut dbs_dir = self.root.clone();
+        dbs_dir.push("angular_rate_dbs");
+        fs::create_dir_all(&dbs_dir)?;
+        let ids = fs::read_dir(dbs_dir)?
+            .map(|res| res.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ids)
     }
 
-    fn load_izhikevich_controller(&mut self, controller_id: &str) -> IzhikevichController {
-        let mut controller_path = loader_path();
-        controller_path.push("izhikevich_controllers/");
-        fs::create_dir_all(&controller_path).unwrap();
-        controller_path.push(controller_id);
-        let content = fs::read_to_string(controller_path).unwrap();
-        serde_json::from_slice(content.as_bytes()).unwrap()
+    fn insert_stabilization_db(
+        &mut self,
+        db: &AngularRateStabilizationDb,
+    ) -> Result<(), LoaderError> {
+        let mut db_path = self.root.clone();
+        db_path.push("angular_rate_dbs");
+        fs::create_dir_all(&db_path)?;
+        db_path.push(&db.db_name);
+        fs::write(db_path, serde_json::to_string(db)?)?;
+        Ok(())
     }
-}
 
-impl FileLoader {
-    pub fn try_load_res_controller(&mut self, controller_id: &str) -> Option<NonAdaptingDroneRc> {
-        let mut reservoir_dir = loader_path();
-        reservoir_dir.push("reservoirs/");
-        fs::create_dir_all(&reservoir_dir).ok()?;
-        reservoir_dir.push(controller_id);
-        let content = fs::read_to_string(reservoir_dir).ok()?;
-        serde_json::from_slice(content.as_bytes()).ok()
+    fn load_stabilization_db(
+        &mut self,
+        db_name: &str,
+    ) -> Result<AngularRateStabilizationDb, LoaderError> {
+        let mut db_path = self.root.clone();
+        db_path.push("angular_rate_dbs");
+        fs::create_dir_all(&db_path)?;
+        db_path.push(db_name);
+        let content = read_file(&db_path, db_name)?;
+        Ok(serde_json::from_str(&content)?)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::file_loader::loader_path;
+    use crate::{
+        EpisodeMetadata, FlDataSet, LoaderError, LoaderTrait,
+        file_loader::{FileLoader, loader_path},
+    };
     use drone::default_drone::default_7in_4s_drone;
+    use loggers::FlightLog;
     use std::fs;
 
+    #[test]
+    fn missing_controller_is_not_found() {
+        let err = FileLoader::default()
+            .load_res_controller("this_controller_does_not_exist")
+            .err()
+            .unwrap();
+        assert!(matches!(err, LoaderError::NotFound(id) if id == "this_controller_does_not_exist"));
+    }
+
+    #[test]
+    fn loader_reads_from_its_root() {
+        let root = std::env::temp_dir().join("quad_file_loader_root_test");
+        let mut drone_path = root.join("drones");
+        fs::create_dir_all(&drone_path).unwrap();
+        drone_path.push("isolated_drone.json");
+        fs::write(
+            drone_path,
+            serde_json::to_string(&default_7in_4s_drone()).unwrap(),
+        )
+        .unwrap();
+        assert!(
+            FileLoader::new(root.clone())
+                .load_drone("isolated_drone")
+                .is_ok()
+        );
+        assert!(matches!(
+            FileLoader::new(root.join("empty")).load_drone("isolated_drone"),
+            Err(LoaderError::NotFound(_))
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn data_set_keeps_splits_order_and_manifest() {
+        let root = std::env::temp_dir().join("quad_file_loader_data_set_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut loader = FileLoader::new(root.clone());
+        let train_data = (0..12)
+            .map(|ep| FlightLog::new(format!("a_{ep}"), vec![]))
+            .collect::<Vec<_>>();
+        let dataset = FlDataSet {
+            dataset_id: "imported".into(),
+            train_data: train_data.clone(),
+            validation_data: vec![FlightLog::new("v".into(), vec![])],
+            test_data: vec![FlightLog::new("recorded/b".into(), vec![])],
+            episode_metadata: [(
+                "v".to_owned(),
+                EpisodeMetadata {
+                    seed: Some(3),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            parents: vec!["base".into()],
+            spec: Some("seed = 1".into()),
+        };
+        loader.insert_data_set(dataset.clone()).unwrap();
+        let ids = |fls: &[FlightLog]| {
+            fls.iter()
+                .map(|fl| fl.simulation_id.clone())
+                .collect::<Vec<_>>()
+        };
+        let loaded = loader.load_data_set("imported").unwrap();
+        assert_eq!(ids(&loaded.train_data), ids(&train_data));
+        assert_eq!(ids(&loaded.validation_data), ["v"]);
+        assert_eq!(ids(&loaded.test_data), ["recorded/b"]);
+        assert_eq!(loaded.episode_metadata, dataset.episode_metadata);
+        assert_eq!(loaded.parents, ["base"]);
+        assert_eq!(loaded.spec.as_deref(), Some("seed = 1"));
+
+        // without the manifest the episodes are ordered by their number, not alphabetically
+        let dataset_dir = root.join("replays/imported");
+        fs::remove_file(dataset_dir.join("manifest.json")).unwrap();
+        let legacy = loader.load_data_set("imported").unwrap();
+        assert_eq!(ids(&legacy.train_data), ids(&train_data));
+
+        // overwriting with fewer episodes removes the stale ones
+        loader
+            .insert_data_set(dataset.subset("imported", |_, position, _| position < 2))
+            .unwrap();
+        assert!(!dataset_dir.join("training_11").exists());
+        assert!(!dataset_dir.join("spec.toml").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
     #[test]
     fn save_default_config_to_file() {
         let default_drone = default_7in_4s_drone();
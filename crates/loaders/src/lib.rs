@@ -1,63 +1,557 @@
+pub mod artifact;
 pub mod default_laoder;
 pub mod file_loader;
+pub mod sqlite_loader;
 
+use artifact::ControllerArtifact;
+use derive_more::derive::{Display, Error, From};
 use drone::Drone;
-use loggers::FlightLog;
-use res_controller::controllers::{
-    esn::NonAdaptingDroneRc, izhikevich_controller::IzhikevichController,
+use flight_controller::{Channels, bf_rates::Rates};
+use loggers::{
+    FlightLog,
+    format::{DecodeError, FORMAT_VERSION},
+    tabular,
 };
-use std::time::Duration;
+use res_controller::{
+    controllers::{
+        esn::{NonAdaptingDroneRc, TrainingError},
+        izhikevich_controller::IzhikevichController,
+    },
+    input_mapping::ReservoirInput,
+};
+use serde::{Deserialize, Serialize};
+use simulator::schedule::ScheduleError;
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum LoaderError {
+    // the requested drone, replay, data set or controller does not exist
+    #[display("`{_0}` not found")]
+    #[from(ignore)]
+    NotFound(#[error(not(source))] String),
+    // the stored data could not be deserialized, typically a stale file from an older version
+    #[display("failed to parse: {_0}")]
+    Parse(serde_json::Error),
+    // the stored data was written by an incompatible format version
+    #[display("version mismatch: expected {expected}, found {found}")]
+    #[from(ignore)]
+    VersionMismatch { expected: u32, found: u32 },
+    // a saved controller of another kind than requested
+    #[display("controller kind mismatch: expected `{expected}`, found `{found}`")]
+    #[from(ignore)]
+    KindMismatch { expected: String, found: String },
+    // a saved controller was trained on different reservoir inputs than the current ones
+    #[display("input features changed: expected {expected:?}, found {found:?}")]
+    #[from(ignore)]
+    InputFeatureMismatch {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    #[display("i/o error: {_0}")]
+    Io(io::Error),
+    #[display("failed to decode: {_0}")]
+    Decode(bincode::Error),
+    #[display("csv error: {_0}")]
+    Csv(csv::Error),
+    #[display("sqlite error: {_0}")]
+    Sqlite(rusqlite::Error),
+    #[display("training failed: {_0}")]
+    Training(TrainingError),
+    // a simulator whose controller can't be scheduled
+    #[display("invalid simulator schedule: {_0}")]
+    Schedule(ScheduleError),
+    // a data set spec that can't be written back to toml
+    #[display("invalid data set spec: {_0}")]
+    #[from(ignore)]
+    InvalidSpec(#[error(not(source))] String),
+}
+
+impl From<DecodeError> for LoaderError {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::Json(err) => Self::Parse(err),
+            DecodeError::Binary(err) => Self::Decode(err),
+            DecodeError::Truncated => Self::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                err.to_string(),
+            )),
+            DecodeError::UnsupportedVersion(found) => Self::VersionMismatch {
+                expected: FORMAT_VERSION,
+                found,
+            },
+        }
+    }
+}
+
+// Ordered as the splits are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Split {
+    Train,
+    // held out for tuning hyperparameters, so the test set stays unseen until the final evaluation
+    Validation,
+    Test,
+}
+
+impl Split {
+    pub const ALL: [Split; 3] = [Split::Train, Split::Validation, Split::Test];
+
+    // As written in the csv `split` column and the sqlite episodes table
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Train => "train",
+            Self::Validation => "validation",
+            Self::Test => "test",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|split| split.name() == name)
+    }
+
+    // Prefix of the episode files of the file loader and of generated simulation ids
+    pub fn file_prefix(self) -> &'static str {
+        match self {
+            Self::Train => "training_",
+            Self::Validation => "validation_",
+            Self::Test => "testing_",
+        }
+    }
+}
+
+impl std::fmt::Display for Split {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// How an episode was generated. Episodes imported from elsewhere have none of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+    // the stick input generator, as written in the data set spec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<serde_json::Value>,
+}
+
+// A stored episode, `key` identifies it within its data set for the loader that listed it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeRef {
+    pub split: Split,
+    pub key: String,
+    pub metadata: Option<EpisodeMetadata>,
+}
+
+// The episodes of a data set in order, without loading them
+#[derive(Debug, Clone, Default)]
+pub struct DataSetIndex {
+    pub dataset_id: String,
+    pub episodes: Vec<EpisodeRef>,
+    pub parents: Vec<String>,
+    pub spec: Option<String>,
+}
+
+impl DataSetIndex {
+    pub fn split(&self, split: Split) -> impl Iterator<Item = &EpisodeRef> {
+        self.episodes.iter().filter(move |ep| ep.split == split)
+    }
 
-#[derive(Default, Debug)]
+    // Loads the episodes of `split` one at a time, so only one of them is in memory at once
+    pub fn stream<'a, L: LoaderTrait + ?Sized>(
+        &'a self,
+        loader: &'a mut L,
+        split: Split,
+    ) -> impl Iterator<Item = Result<FlightLog, LoaderError>> + 'a {
+        self.split(split)
+            .map(move |episode| loader.load_episode(&self.dataset_id, episode))
+    }
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct FlDataSet {
     pub dataset_id: String,
     pub train_data: Vec<FlightLog>,
+    pub validation_data: Vec<FlightLog>,
     pub test_data: Vec<FlightLog>,
+    // By simulation id, episodes without metadata are missing
+    pub episode_metadata: BTreeMap<String, EpisodeMetadata>,
+    // The data sets this one was merged or subset from
+    pub parents: Vec<String>,
+    // The toml spec the data set was generated from, stored next to the episodes
+    pub spec: Option<String>,
 }
 
 impl FlDataSet {
+    pub fn episodes(&self, split: Split) -> &[FlightLog] {
+        match split {
+            Split::Train => &self.train_data,
+            Split::Validation => &self.validation_data,
+            Split::Test => &self.test_data,
+        }
+    }
+
+    pub fn episodes_mut(&mut self, split: Split) -> &mut Vec<FlightLog> {
+        match split {
+            Split::Train => &mut self.train_data,
+            Split::Validation => &mut self.validation_data,
+            Split::Test => &mut self.test_data,
+        }
+    }
+
+    // Every episode with its split, in storage order
+    pub fn all_episodes(&self) -> impl Iterator<Item = (Split, &FlightLog)> {
+        Split::ALL
+            .into_iter()
+            .flat_map(|split| self.episodes(split).iter().map(move |fl| (split, fl)))
+    }
+
+    // The split to tune hyperparameters on, data sets from before the validation split only
+    // have the test set
+    pub fn tuning_split(&self) -> Split {
+        match self.validation_data.is_empty() {
+            true => Split::Test,
+            false => Split::Validation,
+        }
+    }
+
+    // Moves the last `count` training episodes to the validation set
+    pub fn hold_out_validation(&mut self, count: usize) {
+        let at = self.train_data.len().saturating_sub(count);
+        let mut held_out = self.train_data.split_off(at);
+        held_out.append(&mut self.validation_data);
+        self.validation_data = held_out;
+    }
+
+    // Concatenates the splits of `datasets` in order. Metadata of episodes with the same
+    // simulation id is taken from the last data set.
+    pub fn merge(dataset_id: &str, datasets: Vec<FlDataSet>) -> Self {
+        let mut merged = FlDataSet {
+            dataset_id: dataset_id.to_owned(),
+            ..Default::default()
+        };
+        for mut dataset in datasets {
+            for split in Split::ALL {
+                merged
+                    .episodes_mut(split)
+                    .append(dataset.episodes_mut(split));
+            }
+            merged
+                .episode_metadata
+                .append(&mut dataset.episode_metadata);
+            merged.parents.push(dataset.dataset_id);
+        }
+        merged
+    }
+
+    // Keeps the episodes for which `keep(split, position in the split, episode)` is true
+    pub fn subset(
+        &self,
+        dataset_id: &str,
+        keep: impl Fn(Split, usize, &FlightLog) -> bool,
+    ) -> Self {
+        let mut subset = FlDataSet {
+            dataset_id: dataset_id.to_owned(),
+            parents: vec![self.dataset_id.clone()],
+            ..Default::default()
+        };
+        for split in Split::ALL {
+            for (position, fl) in self.episodes(split).iter().enumerate() {
+                if keep(split, position, fl) {
+                    subset.episodes_mut(split).push(fl.clone());
+                    if let Some(metadata) = self.episode_metadata.get(&fl.simulation_id) {
+                        subset
+                            .episode_metadata
+                            .insert(fl.simulation_id.clone(), metadata.clone());
+                    }
+                }
+            }
+        }
+        subset
+    }
+
     pub fn downsample(&mut self, duration: Duration) {
-        let FlDataSet {
-            train_data,
-            test_data,
-            ..
-        } = self;
-        train_data.iter_mut().for_each(|d| d.downsample(duration));
-        test_data.iter_mut().for_each(|d| d.downsample(duration));
+        for split in Split::ALL {
+            self.episodes_mut(split)
+                .iter_mut()
+                .for_each(|d| d.downsample(duration));
+        }
     }
+
+    // Writes every episode to a single tidy csv, the `split` column tells the splits apart
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), LoaderError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for (split, fl) in self.all_episodes() {
+            tabular::write_rows(&mut writer, fl, Some(split.name()))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Episodes without a split, e.g. externally recorded logs, are used for training
+    pub fn read_csv<R: Read>(dataset_id: &str, reader: R) -> Result<Self, LoaderError> {
+        let mut dataset = FlDataSet {
+            dataset_id: dataset_id.to_owned(),
+            ..Default::default()
+        };
+        for (split, fl) in tabular::read_flight_logs(reader)? {
+            let split = split
+                .as_deref()
+                .and_then(Split::from_name)
+                .unwrap_or(Split::Train);
+            dataset.episodes_mut(split).push(fl);
+        }
+        Ok(dataset)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StickAndTarget {
+    pub stick_input: Channels,
+    pub target: Rates,
 }
 
+// Stick inputs with the rates a controller should reach, used to evaluate rate stabilization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AngularRateStabilizationDb {
+    pub db_name: String,
+    pub sticks_and_targets: Vec<StickAndTarget>,
+}
+
+// Kinds of the controllers the loaders have typed helpers for
+pub const RESERVOIR_KIND: &str = "reservoir";
+pub const IZHIKEVICH_KIND: &str = "izhikevich";
+
 pub trait LoaderTrait: Send + Sync {
     // load a drone
-    fn load_drone(&mut self, config_id: &str) -> Drone;
+    fn load_drone(&mut self, config_id: &str) -> Result<Drone, LoaderError>;
 
     // Load replay
-    fn load_flight_log(&mut self, sim_id: &str) -> FlightLog;
+    fn load_flight_log(&mut self, sim_id: &str) -> Result<FlightLog, LoaderError>;
 
     // Get simulation ids
-    fn get_replay_ids(&mut self) -> Vec<String>;
+    fn get_replay_ids(&mut self) -> Result<Vec<String>, LoaderError>;
+
+    // Get the ids of the stored controllers of a kind, see `sim_context::controller_registry`
+    fn get_controller_ids(&mut self, kind: &str) -> Result<Vec<String>, LoaderError>;
+
+    // Controllers are stored as json, (de)serializing them is up to the registered kind
+    fn insert_controller(
+        &mut self,
+        kind: &str,
+        controller_id: &str,
+        controller: &str,
+    ) -> Result<(), LoaderError>;
+
+    fn load_controller(&mut self, kind: &str, controller_id: &str) -> Result<String, LoaderError>;
 
     // Get reservoir ids
-    fn get_reservoir_controller_ids(&mut self) -> Vec<String>;
+    fn get_reservoir_controller_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        self.get_controller_ids(RESERVOIR_KIND)
+    }
 
     // Get Izhikevich controller ids
-    fn get_izhikevich_controller_ids(&mut self) -> Vec<String>;
+    fn get_izhikevich_controller_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        self.get_controller_ids(IZHIKEVICH_KIND)
+    }
+
+    fn insert_artifact(
+        &mut self,
+        controller_id: &str,
+        artifact: &ControllerArtifact,
+    ) -> Result<(), LoaderError> {
+        self.insert_controller(&artifact.kind, controller_id, &artifact.encode()?)
+    }
+
+    // Checks the version and the kind, the input features are up to the caller
+    fn load_artifact(
+        &mut self,
+        kind: &str,
+        controller_id: &str,
+    ) -> Result<ControllerArtifact, LoaderError> {
+        let content = self.load_controller(kind, controller_id)?;
+        ControllerArtifact::decode(&content, kind)
+    }
 
     // Insert a new reservoir
-    fn insert_rc_controller(&mut self, controller_id: &str, controller: NonAdaptingDroneRc);
+    fn insert_rc_controller(
+        &mut self,
+        controller_id: &str,
+        controller: NonAdaptingDroneRc,
+    ) -> Result<(), LoaderError> {
+        let artifact = ControllerArtifact::new(RESERVOIR_KIND, &controller)?
+            .with_input_features(&ReservoirInput::FIELD_NAMES);
+        self.insert_artifact(controller_id, &artifact)
+    }
 
     // Load reservoir controller
-    fn load_res_controller(&mut self, controller_id: &str) -> NonAdaptingDroneRc;
+    fn load_res_controller(
+        &mut self,
+        controller_id: &str,
+    ) -> Result<NonAdaptingDroneRc, LoaderError> {
+        let artifact = self.load_artifact(RESERVOIR_KIND, controller_id)?;
+        artifact.check_input_features(&ReservoirInput::FIELD_NAMES)?;
+        artifact.controller()
+    }
 
-    fn load_data_set(&mut self, dataset_id: &str) -> FlDataSet;
+    // The episodes of a data set without their content, see `DataSetIndex::stream`
+    fn data_set_index(&mut self, dataset_id: &str) -> Result<DataSetIndex, LoaderError>;
 
-    fn insert_data_set(&mut self, dataset: FlDataSet);
+    fn load_episode(
+        &mut self,
+        dataset_id: &str,
+        episode: &EpisodeRef,
+    ) -> Result<FlightLog, LoaderError>;
+
+    // Loads every episode into memory, large data sets can be streamed through the index instead
+    fn load_data_set(&mut self, dataset_id: &str) -> Result<FlDataSet, LoaderError> {
+        let index = self.data_set_index(dataset_id)?;
+        let mut dataset = FlDataSet {
+            dataset_id: dataset_id.into(),
+            parents: index.parents.clone(),
+            spec: index.spec.clone(),
+            ..Default::default()
+        };
+        for episode in &index.episodes {
+            let fl = self.load_episode(dataset_id, episode)?;
+            if let Some(metadata) = &episode.metadata {
+                dataset
+                    .episode_metadata
+                    .insert(fl.simulation_id.clone(), metadata.clone());
+            }
+            dataset.episodes_mut(episode.split).push(fl);
+        }
+        Ok(dataset)
+    }
+
+    // Stores a single episode, e.g. as soon as it is simulated. The returned key refers to it in
+    // the index written by `insert_data_set_index` once every episode is stored.
+    fn insert_episode(
+        &mut self,
+        dataset_id: &str,
+        split: Split,
+        position: usize,
+        flight_log: &FlightLog,
+    ) -> Result<String, LoaderError>;
+
+    // Makes the episodes stored with `insert_episode` the data set, stored episodes missing from
+    // the index are removed
+    fn insert_data_set_index(&mut self, index: &DataSetIndex) -> Result<(), LoaderError>;
+
+    fn insert_data_set(&mut self, dataset: FlDataSet) -> Result<(), LoaderError> {
+        let mut index = DataSetIndex {
+            dataset_id: dataset.dataset_id.clone(),
+            episodes: vec![],
+            parents: dataset.parents.clone(),
+            spec: dataset.spec.clone(),
+        };
+        for split in Split::ALL {
+            for (position, fl) in dataset.episodes(split).iter().enumerate() {
+                index.episodes.push(EpisodeRef {
+                    split,
+                    key: self.insert_episode(&dataset.dataset_id, split, position, fl)?,
+                    metadata: dataset.episode_metadata.get(&fl.simulation_id).cloned(),
+                });
+            }
+        }
+        self.insert_data_set_index(&index)
+    }
 
     fn insert_izhikevich_controller(
         &mut self,
         controller_id: &str,
         controller: &IzhikevichController,
-    );
+    ) -> Result<(), LoaderError> {
+        let artifact = ControllerArtifact::new(IZHIKEVICH_KIND, controller)?
+            .with_input_features(&ReservoirInput::FIELD_NAMES);
+        self.insert_artifact(controller_id, &artifact)
+    }
 
-    fn load_izhikevich_controller(&mut self, controller_id: &str) -> IzhikevichController;
+    fn load_izhikevich_controller(
+        &mut self,
+        controller_id: &str,
+    ) -> Result<IzhikevichController, LoaderError> {
+        let artifact = self.load_artifact(IZHIKEVICH_KIND, controller_id)?;
+        artifact.check_input_features(&ReservoirInput::FIELD_NAMES)?;
+        artifact.controller()
+    }
+
+    // Get angular rate stabilization db names
+    fn get_stabilization_db_ids(&mut self) -> Result<Vec<String>, LoaderError>;
+
+    fn insert_stabilization_db(
+        &mut self,
+        db: &AngularRateStabilizationDb,
+    ) -> Result<(), LoaderError>;
+
+    fn load_stabilization_db(
+        &mut self,
+        db_name: &str,
+    ) -> Result<AngularRateStabilizationDb, LoaderError>;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EpisodeMetadata, FlDataSet, Split};
+    use loggers::FlightLog;
+
+    fn dataset(dataset_id: &str, train: &[&str], test: &[&str]) -> FlDataSet {
+        let episodes = |ids: &[&str]| {
+            ids.iter()
+                .map(|id| FlightLog::new(id.to_string(), vec![]))
+                .collect()
+        };
+        FlDataSet {
+            dataset_id: dataset_id.into(),
+            train_data: episodes(train),
+            test_data: episodes(test),
+            episode_metadata: train
+                .iter()
+                .map(|id| (id.to_string(), EpisodeMetadata::default()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(dataset: &FlDataSet, split: Split) -> Vec<&str> {
+        dataset
+            .episodes(split)
+            .iter()
+            .map(|fl| fl.simulation_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn merge_subset_and_hold_out() {
+        let merged = FlDataSet::merge(
+            "merged",
+            vec![
+                dataset("a", &["a0", "a1"], &["at"]),
+                dataset("b", &["b0"], &["bt"]),
+            ],
+        );
+        assert_eq!(ids(&merged, Split::Train), ["a0", "a1", "b0"]);
+        assert_eq!(ids(&merged, Split::Test), ["at", "bt"]);
+        assert_eq!(merged.parents, ["a", "b"]);
+        assert_eq!(merged.episode_metadata.len(), 3);
+
+        let mut subset = merged.subset("subset", |split, position, _| {
+            split == Split::Test || position > 0
+        });
+        assert_eq!(ids(&subset, Split::Train), ["a1", "b0"]);
+        assert_eq!(subset.parents, ["merged"]);
+        assert!(!subset.episode_metadata.contains_key("a0"));
+
+        assert_eq!(subset.tuning_split(), Split::Test);
+        subset.hold_out_validation(1);
+        assert_eq!(ids(&subset, Split::Train), ["a1"]);
+        assert_eq!(ids(&subset, Split::Validation), ["b0"]);
+        assert_eq!(subset.tuning_split(), Split::Validation);
+    }
 }
@@ -0,0 +1,782 @@
+// Keeps everything the file loader spreads over directories in a single database file. Every
+// table stores the creation time and optional parameters (json) next to the artifact, data sets
+// additionally record the ids of the data sets they were derived from. A new database starts
+// with the default drone, everything else is stored by the commands or copied over from the
+// files with `import_files`.
+
+use drone::{Drone, default_drone::default_7in_4s_drone};
+use loggers::{
+    FlightLog,
+    format::{LogFormat, decode, encode},
+    storage::data_root,
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    AngularRateStabilizationDb, DataSetIndex, EpisodeRef, FlDataSet, LoaderError, LoaderTrait,
+    Split, artifact::ControllerArtifact, file_loader::FileLoader,
+};
+
+// The config the simulations use unless told otherwise
+pub const DEFAULT_DRONE_ID: &str = "7in_4s_drone";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS drones (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    parameters TEXT,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS replays (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    parameters TEXT,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS datasets (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    parameters TEXT,
+    parents TEXT NOT NULL DEFAULT '[]',
+    spec TEXT
+);
+CREATE TABLE IF NOT EXISTS episodes (
+    dataset_id TEXT NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    split TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    simulation_id TEXT NOT NULL,
+    metadata TEXT,
+    data BLOB NOT NULL,
+    PRIMARY KEY (dataset_id, split, position)
+);
+CREATE TABLE IF NOT EXISTS stabilization_dbs (
+    id TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    parameters TEXT,
+    data TEXT NOT NULL
+);
+";
+
+// The kinds of artifacts stored by the loader, one table each. Every controller kind gets its own
+// `controllers_<kind>` table, created the first time the kind is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Table {
+    Drones,
+    Replays,
+    DataSets,
+    Controllers(String),
+    StabilizationDbs,
+}
+
+impl Table {
+    fn name(&self) -> String {
+        match self {
+            Self::Drones => "drones".into(),
+            Self::Replays => "replays".into(),
+            Self::DataSets => "datasets".into(),
+            Self::Controllers(kind) => format!("controllers_{kind}"),
+            Self::StabilizationDbs => "stabilization_dbs".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    // unix time in seconds of the first insert, overwriting an entry keeps it
+    pub created_at: u64,
+    pub parameters: Option<serde_json::Value>,
+}
+
+// What `import_files` copied
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportCounts {
+    pub drones: usize,
+    pub replays: usize,
+    pub controllers: usize,
+    pub data_sets: usize,
+    pub stabilization_dbs: usize,
+}
+
+impl std::fmt::Display for ImportCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} drones, {} replays, {} controllers, {} data sets and {} stabilization dbs",
+            self.drones, self.replays, self.controllers, self.data_sets, self.stabilization_dbs
+        )
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub struct SqliteLoader {
+    // rusqlite connections are not Sync, the trait methods take &mut self so this never blocks
+    connection: Mutex<Connection>,
+}
+
+impl SqliteLoader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LoaderError> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, LoaderError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    // The database next to the files of the file loader
+    pub fn open_in_root(root: &Path) -> Result<Self, LoaderError> {
+        Self::open(root.join("quad.sqlite"))
+    }
+
+    pub fn open_default() -> Result<Self, LoaderError> {
+        Self::open_in_root(&data_root())
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, LoaderError> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        let mut loader = Self {
+            connection: Mutex::new(connection),
+        };
+        // a stored config with the same id is kept
+        if matches!(
+            loader.load_drone(DEFAULT_DRONE_ID),
+            Err(LoaderError::NotFound(_))
+        ) {
+            loader.insert_drone(DEFAULT_DRONE_ID, &default_7in_4s_drone())?;
+        }
+        Ok(loader)
+    }
+
+    fn connection(&mut self) -> &mut Connection {
+        self.connection.get_mut().unwrap()
+    }
+
+    // The table name, making sure the table of a controller kind exists
+    fn table(&mut self, table: &Table) -> Result<String, LoaderError> {
+        let name = table.name();
+        if let Table::Controllers(kind) = table {
+            // the kind ends up in the statement, so it has to be a plain identifier
+            if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(LoaderError::NotFound(kind.clone()));
+            }
+            self.connection().execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {name} (
+                    id TEXT PRIMARY KEY,
+                    created_at INTEGER NOT NULL,
+                    parameters TEXT,
+                    data TEXT NOT NULL
+                );"
+            ))?;
+        }
+        Ok(name)
+    }
+
+    fn ids(&mut self, table: Table) -> Result<Vec<String>, LoaderError> {
+        let name = self.table(&table)?;
+        let connection = self.connection();
+        let mut statement = connection.prepare(&format!("SELECT id FROM {name} ORDER BY id"))?;
+        let ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    fn insert_data<T: rusqlite::ToSql>(
+        &mut self,
+        table: Table,
+        id: &str,
+        data: T,
+    ) -> Result<(), LoaderError> {
+        let name = self.table(&table)?;
+        self.connection().execute(
+            &format!(
+                "INSERT INTO {name} (id, created_at, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(id) DO UPDATE SET data = excluded.data"
+            ),
+            params![id, now(), data],
+        )?;
+        Ok(())
+    }
+
+    fn load_data<T: rusqlite::types::FromSql>(
+        &mut self,
+        table: Table,
+        id: &str,
+    ) -> Result<T, LoaderError> {
+        let name = self.table(&table)?;
+        self.connection()
+            .query_row(
+                &format!("SELECT data FROM {name} WHERE id = ?1"),
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(id.into()))
+    }
+
+    fn insert_json<T: Serialize>(
+        &mut self,
+        table: Table,
+        id: &str,
+        value: &T,
+    ) -> Result<(), LoaderError> {
+        let data = serde_json::to_string(value)?;
+        self.insert_data(table, id, data)
+    }
+
+    fn load_json<T: DeserializeOwned>(&mut self, table: Table, id: &str) -> Result<T, LoaderError> {
+        let data: String = self.load_data(table, id)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn insert_drone(&mut self, config_id: &str, drone: &Drone) -> Result<(), LoaderError> {
+        self.insert_json(Table::Drones, config_id, drone)
+    }
+
+    // Replays are stored in the binary log format
+    pub fn insert_flight_log(&mut self, flight_log: &FlightLog) -> Result<(), LoaderError> {
+        let data = encode(flight_log, LogFormat::Binary);
+        self.insert_data(Table::Replays, &flight_log.simulation_id, data)
+    }
+
+    pub fn metadata(&mut self, table: Table, id: &str) -> Result<Metadata, LoaderError> {
+        let name = self.table(&table)?;
+        let (created_at, parameters) = self
+            .connection()
+            .query_row(
+                &format!("SELECT created_at, parameters FROM {name} WHERE id = ?1"),
+                [id],
+                |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(id.into()))?;
+        let parameters = parameters
+            .map(|parameters| serde_json::from_str(&parameters))
+            .transpose()?;
+        Ok(Metadata {
+            created_at,
+            parameters,
+        })
+    }
+
+    // Records the parameters an entry was generated or trained with
+    pub fn set_parameters<T: Serialize>(
+        &mut self,
+        table: Table,
+        id: &str,
+        parameters: &T,
+    ) -> Result<(), LoaderError> {
+        let name = self.table(&table)?;
+        let parameters = serde_json::to_string(parameters)?;
+        let updated = self.connection().execute(
+            &format!("UPDATE {name} SET parameters = ?2 WHERE id = ?1"),
+            params![id, parameters],
+        )?;
+        match updated {
+            0 => Err(LoaderError::NotFound(id.into())),
+            _ => Ok(()),
+        }
+    }
+
+    // The data sets a data set was derived from
+    pub fn parents(&mut self, dataset_id: &str) -> Result<Vec<String>, LoaderError> {
+        let parents: String = self
+            .connection()
+            .query_row(
+                "SELECT parents FROM datasets WHERE id = ?1",
+                [dataset_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(dataset_id.into()))?;
+        Ok(serde_json::from_str(&parents)?)
+    }
+
+    // Copies everything stored by `files` into the database, overwriting entries with the same
+    // id. Data sets are loaded one at a time.
+    pub fn import_files(&mut self, files: &mut FileLoader) -> Result<ImportCounts, LoaderError> {
+        let mut counts = ImportCounts::default();
+        for config_id in files.drone_ids()? {
+            self.insert_drone(&config_id, &files.load_drone(&config_id)?)?;
+            counts.drones += 1;
+        }
+        for replay in files.replay_names()? {
+            // stored under the file name, which may differ from the simulation id inside
+            let mut flight_log = files.load_flight_log(&replay)?;
+            flight_log.simulation_id = replay;
+            self.insert_flight_log(&flight_log)?;
+            counts.replays += 1;
+        }
+        for kind in files.controller_kinds()? {
+            for controller_id in files.controller_names(&kind)? {
+                let controller = files.load_controller(&kind, &controller_id)?;
+                self.insert_controller(&kind, &controller_id, &controller)?;
+                counts.controllers += 1;
+            }
+        }
+        for dataset_id in files.data_set_ids()? {
+            self.insert_data_set(files.load_data_set(&dataset_id)?)?;
+            counts.data_sets += 1;
+        }
+        for db_name in files.get_stabilization_db_ids()? {
+            self.insert_stabilization_db(&files.load_stabilization_db(&db_name)?)?;
+            counts.stabilization_dbs += 1;
+        }
+        Ok(counts)
+    }
+
+    // The positions of the episodes of a split in order, with their metadata
+    fn episode_refs(
+        &mut self,
+        dataset_id: &str,
+        split: Split,
+    ) -> Result<Vec<EpisodeRef>, LoaderError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT position, metadata FROM episodes WHERE dataset_id = ?1 AND split = ?2
+             ORDER BY position",
+        )?;
+        let rows = statement
+            .query_map([dataset_id, split.name()], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(position, metadata)| {
+                Ok(EpisodeRef {
+                    split,
+                    key: position.to_string(),
+                    metadata: metadata.as_deref().map(serde_json::from_str).transpose()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl LoaderTrait for SqliteLoader {
+    fn load_drone(&mut self, config_id: &str) -> Result<Drone, LoaderError> {
+        self.load_json(Table::Drones, config_id)
+    }
+
+    fn load_flight_log(&mut self, sim_id: &str) -> Result<FlightLog, LoaderError> {
+        let data: Vec<u8> = self.load_data(Table::Replays, sim_id)?;
+        Ok(decode(&data)?)
+    }
+
+    fn get_replay_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        self.ids(Table::Replays)
+    }
+
+    fn get_controller_ids(&mut self, kind: &str) -> Result<Vec<String>, LoaderError> {
+        self.ids(Table::Controllers(kind.into()))
+    }
+
+    fn insert_controller(
+        &mut self,
+        kind: &str,
+        controller_id: &str,
+        controller: &str,
+    ) -> Result<(), LoaderError> {
+        let table = Table::Controllers(kind.into());
+        self.insert_data(table.clone(), controller_id, controller)?;
+        // the training parameters of an artifact are queryable through `metadata`
+        if let Ok(ControllerArtifact {
+            parameters: Some(parameters),
+            ..
+        }) = ControllerArtifact::decode(controller, kind)
+        {
+            self.set_parameters(table, controller_id, &parameters)?;
+        }
+        Ok(())
+    }
+
+    fn load_controller(&mut self, kind: &str, controller_id: &str) -> Result<String, LoaderError> {
+        self.load_data(Table::Controllers(kind.into()), controller_id)
+    }
+
+    // The key of an episode is its position in the split
+    fn data_set_index(&mut self, dataset_id: &str) -> Result<DataSetIndex, LoaderError> {
+        // a missing data set is reported instead of loading an empty one
+        let spec = self
+            .connection()
+            .query_row(
+                "SELECT spec FROM datasets WHERE id = ?1",
+                [dataset_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(dataset_id.into()))?;
+        let mut episodes = vec![];
+        for split in Split::ALL {
+            episodes.extend(self.episode_refs(dataset_id, split)?);
+        }
+        Ok(DataSetIndex {
+            dataset_id: dataset_id.into(),
+            episodes,
+            parents: self.parents(dataset_id)?,
+            spec,
+        })
+    }
+
+    fn load_episode(
+        &mut self,
+        dataset_id: &str,
+        episode: &EpisodeRef,
+    ) -> Result<FlightLog, LoaderError> {
+        let data: Vec<u8> = self
+            .connection()
+            .query_row(
+                "SELECT data FROM episodes WHERE dataset_id = ?1 AND split = ?2 AND position = ?3",
+                params![dataset_id, episode.split.name(), episode.key],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(episode.key.clone()))?;
+        Ok(decode(&data)?)
+    }
+
+    // The key of an episode is its position in the split. The data set row is created with the
+    // first episode, its lineage and spec are written with the index.
+    fn insert_episode(
+        &mut self,
+        dataset_id: &str,
+        split: Split,
+        position: usize,
+        flight_log: &FlightLog,
+    ) -> Result<String, LoaderError> {
+        let transaction = self.connection().transaction()?;
+        transaction.execute(
+            "INSERT INTO datasets (id, created_at) VALUES (?1, ?2) ON CONFLICT(id) DO NOTHING",
+            params![dataset_id, now()],
+        )?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO episodes (dataset_id, split, position, simulation_id, data)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                dataset_id,
+                split.name(),
+                position as i64,
+                flight_log.simulation_id,
+                encode(flight_log, LogFormat::Binary)
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(position.to_string())
+    }
+
+    fn insert_data_set_index(&mut self, index: &DataSetIndex) -> Result<(), LoaderError> {
+        let dataset_id = &index.dataset_id;
+        let parents = serde_json::to_string(&index.parents)?;
+        let transaction = self.connection().transaction()?;
+        transaction.execute(
+            "INSERT INTO datasets (id, created_at, parents, spec) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET parents = excluded.parents, spec = excluded.spec",
+            params![dataset_id, now(), parents, index.spec],
+        )?;
+        let stored = {
+            let mut statement = transaction
+                .prepare("SELECT split, position FROM episodes WHERE dataset_id = ?1")?;
+            statement
+                .query_map([dataset_id], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        for (split, position) in stored {
+            let episode = index.episodes.iter().find(|episode| {
+                episode.split.name() == split && episode.key == position.to_string()
+            });
+            match episode {
+                Some(episode) => transaction.execute(
+                    "UPDATE episodes SET metadata = ?4
+                     WHERE dataset_id = ?1 AND split = ?2 AND position = ?3",
+                    params![
+                        dataset_id,
+                        split,
+                        position,
+                        episode
+                            .metadata
+                            .as_ref()
+                            .map(serde_json::to_string)
+                            .transpose()?
+                    ],
+                )?,
+                None => transaction.execute(
+                    "DELETE FROM episodes WHERE dataset_id = ?1 AND split = ?2 AND position = ?3",
+                    params![dataset_id, split, position],
+                )?,
+            };
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    // Overwriting a data set replaces all of its episodes but keeps its creation time and
+    // parameters, all in one transaction
+    fn insert_data_set(&mut self, dataset: FlDataSet) -> Result<(), LoaderError> {
+        let dataset_id = &dataset.dataset_id;
+        let parents = serde_json::to_string(&dataset.parents)?;
+        let transaction = self.connection().transaction()?;
+        transaction.execute(
+            "INSERT INTO datasets (id, created_at, parents, spec) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET parents = excluded.parents, spec = excluded.spec",
+            params![dataset_id, now(), parents, dataset.spec],
+        )?;
+        transaction.execute("DELETE FROM episodes WHERE dataset_id = ?1", [dataset_id])?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO episodes (dataset_id, split, position, simulation_id, metadata, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for split in Split::ALL {
+                for (position, fl) in dataset.episodes(split).iter().enumerate() {
+                    let metadata = dataset
+                        .episode_metadata
+                        .get(&fl.simulation_id)
+                        .map(serde_json::to_string)
+                        .transpose()?;
+                    statement.execute(params![
+                        dataset_id,
+                        split.name(),
+                        position as i64,
+                        fl.simulation_id,
+                        metadata,
+                        encode(fl, LogFormat::Binary)
+                    ])?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_stabilization_db_ids(&mut self) -> Result<Vec<String>, LoaderError> {
+        self.ids(Table::StabilizationDbs)
+    }
+
+    fn insert_stabilization_db(
+        &mut self,
+        db: &AngularRateStabilizationDb,
+    ) -> Result<(), LoaderError> {
+        self.insert_json(Table::StabilizationDbs, &db.db_name, db)
+    }
+
+    fn load_stabilization_db(
+        &mut self,
+        db_name: &str,
+    ) -> Result<AngularRateStabilizationDb, LoaderError> {
+        self.load_json(Table::StabilizationDbs, db_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        AngularRateStabilizationDb, DataSetIndex, EpisodeMetadata, EpisodeRef, FlDataSet,
+        LoaderError, LoaderTrait, RESERVOIR_KIND, Split,
+        artifact::ControllerArtifact,
+        file_loader::FileLoader,
+        sqlite_loader::{DEFAULT_DRONE_ID, ImportCounts, SqliteLoader, Table},
+    };
+    use drone::default_drone::default_7in_4s_drone;
+    use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+    use loggers::{
+        FlightLog, SnapShot,
+        format::{LogFormat, encode},
+    };
+    use std::{fs, time::Duration};
+
+    fn flight_log(simulation_id: &str) -> FlightLog {
+        let steps = (0..3)
+            .map(|i| {
+                SnapShot::new(
+                    Duration::from_millis(i),
+                    MotorInput { input: [0.; 4] },
+                    BatteryUpdate::default(),
+                    GyroUpdate::default(),
+                    Channels::default(),
+                )
+            })
+            .collect();
+        FlightLog::new(simulation_id.into(), steps)
+    }
+
+    #[test]
+    fn drones_and_replays_roundtrip() {
+        let mut loader = SqliteLoader::open_in_memory().unwrap();
+        // seeded on open
+        assert!(loader.load_drone(DEFAULT_DRONE_ID).is_ok());
+        loader
+            .insert_drone("other_drone", &default_7in_4s_drone())
+            .unwrap();
+        assert!(loader.load_drone("other_drone").is_ok());
+        assert!(matches!(
+            loader.load_drone("missing"),
+            Err(LoaderError::NotFound(_))
+        ));
+
+        loader.insert_flight_log(&flight_log("replay")).unwrap();
+        assert_eq!(loader.get_replay_ids().unwrap(), ["replay"]);
+        assert_eq!(loader.load_flight_log("replay").unwrap().steps.len(), 3);
+    }
+
+    #[test]
+    fn data_set_keeps_splits_order_and_lineage() {
+        let mut loader = SqliteLoader::open_in_memory().unwrap();
+        assert!(matches!(
+            loader.load_data_set("derived"),
+            Err(LoaderError::NotFound(_))
+        ));
+        let dataset = FlDataSet {
+            dataset_id: "derived".into(),
+            train_data: vec![flight_log("b"), flight_log("a")],
+            validation_data: vec![flight_log("v")],
+            test_data: vec![flight_log("c")],
+            episode_metadata: [(
+                "a".to_owned(),
+                EpisodeMetadata {
+                    seed: Some(7),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            parents: vec!["base".into()],
+            spec: Some("seed = 1".into()),
+        };
+        loader.insert_data_set(dataset).unwrap();
+        loader
+            .set_parameters(Table::DataSets, "derived", &[1, 2, 3])
+            .unwrap();
+
+        let loaded = loader.load_data_set("derived").unwrap();
+        let ids = |fls: &[FlightLog]| {
+            fls.iter()
+                .map(|fl| fl.simulation_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&loaded.train_data), ["b", "a"]);
+        assert_eq!(ids(&loaded.validation_data), ["v"]);
+        assert_eq!(ids(&loaded.test_data), ["c"]);
+        assert_eq!(loaded.episode_metadata["a"].seed, Some(7));
+        assert!(!loaded.episode_metadata.contains_key("b"));
+        assert_eq!(loaded.spec.as_deref(), Some("seed = 1"));
+        assert_eq!(loaded.parents, ["base"]);
+        let metadata = loader.metadata(Table::DataSets, "derived").unwrap();
+        assert_eq!(metadata.parameters, Some(serde_json::json!([1, 2, 3])));
+    }
+
+    #[test]
+    fn episodes_stored_one_at_a_time() {
+        let mut loader = SqliteLoader::open_in_memory().unwrap();
+        let mut index = DataSetIndex {
+            dataset_id: "built".into(),
+            ..Default::default()
+        };
+        for (position, id) in ["a", "b", "c"].into_iter().enumerate() {
+            let key = loader
+                .insert_episode("built", Split::Train, position, &flight_log(id))
+                .unwrap();
+            index.episodes.push(EpisodeRef {
+                split: Split::Train,
+                key,
+                metadata: None,
+            });
+        }
+        // rebuilding with fewer episodes drops the stale ones
+        index.episodes.truncate(2);
+        index.episodes[1].metadata = Some(EpisodeMetadata {
+            seed: Some(1),
+            ..Default::default()
+        });
+        loader.insert_data_set_index(&index).unwrap();
+        let loaded = loader.load_data_set("built").unwrap();
+        assert_eq!(loaded.train_data.len(), 2);
+        assert_eq!(loaded.episode_metadata["b"].seed, Some(1));
+    }
+
+    #[test]
+    fn import_copies_the_files() {
+        let root = std::env::temp_dir().join("quad_sqlite_loader_import_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut files = FileLoader::new(root.clone());
+        fs::create_dir_all(root.join("drones")).unwrap();
+        fs::create_dir_all(root.join("replays")).unwrap();
+        fs::write(
+            root.join("drones/imported_drone.json"),
+            serde_json::to_string(&default_7in_4s_drone()).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            root.join("replays/recorded"),
+            encode(&flight_log("recorded"), LogFormat::Json),
+        )
+        .unwrap();
+        let artifact = ControllerArtifact::new(RESERVOIR_KIND, &[1., 2.])
+            .unwrap()
+            .with_parameters(&[0.5])
+            .unwrap();
+        files
+            .insert_controller(RESERVOIR_KIND, "trained", &artifact.encode().unwrap())
+            .unwrap();
+        files
+            .insert_controller("pid", "tuned", "{\"p\": 1.0}")
+            .unwrap();
+        files
+            .insert_data_set(FlDataSet {
+                dataset_id: "generated".into(),
+                train_data: vec![flight_log("a"), flight_log("b")],
+                test_data: vec![flight_log("c")],
+                ..Default::default()
+            })
+            .unwrap();
+        files
+            .insert_stabilization_db(&AngularRateStabilizationDb {
+                db_name: "steps".into(),
+                sticks_and_targets: vec![],
+            })
+            .unwrap();
+
+        let mut loader = SqliteLoader::open_in_memory().unwrap();
+        let counts = loader.import_files(&mut files).unwrap();
+        assert_eq!(
+            counts,
+            ImportCounts {
+                drones: 1,
+                replays: 1,
+                controllers: 2,
+                data_sets: 1,
+                stabilization_dbs: 1,
+            }
+        );
+        assert!(loader.load_drone("imported_drone").is_ok());
+        assert!(loader.load_drone(DEFAULT_DRONE_ID).is_ok());
+        assert_eq!(loader.get_replay_ids().unwrap(), ["recorded"]);
+        assert_eq!(loader.load_flight_log("recorded").unwrap().steps.len(), 3);
+        assert_eq!(loader.get_controller_ids("pid").unwrap(), ["tuned"]);
+        let metadata = loader
+            .metadata(Table::Controllers(RESERVOIR_KIND.into()), "trained")
+            .unwrap();
+        assert_eq!(metadata.parameters, Some(serde_json::json!([0.5])));
+        let dataset = loader.load_data_set("generated").unwrap();
+        assert_eq!(dataset.train_data.len(), 2);
+        assert_eq!(dataset.test_data.len(), 1);
+        assert!(loader.load_stabilization_db("steps").is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,11 +1,15 @@
-use crate::{FlightLog, Logger, SnapShot};
+use crate::{
+    FlightLog, Logger, SnapShot,
+    format::{LogFormat, encode},
+    storage::{data_root, replays_dir},
+};
 use std::{fs, path::PathBuf};
 
-const LOG_PATH: &str = concat!(env!("HOME"), ".local/share/quad/replays/");
-
 pub struct FileLogger {
     simulation_id: String,
     snapshots: Vec<SnapShot>,
+    format: LogFormat,
+    root: PathBuf,
 }
 
 impl Logger for FileLogger {
@@ -19,24 +23,35 @@ impl Logger for FileLogger {
             steps: self.snapshots.clone(),
         };
         if !self.snapshots.is_empty() {
-            let mut log_path = PathBuf::from(LOG_PATH);
+            let mut log_path = replays_dir(&self.root);
             log_path.push(self.simulation_id.clone());
             if let Some(parent) = log_path.parent() {
                 fs::create_dir_all(parent).unwrap();
             }
-            let contents = serde_json::to_string(&flight_log).unwrap();
-            fs::write(log_path, contents).unwrap();
+            fs::write(log_path, encode(&flight_log, self.format)).unwrap();
         }
     }
 }
 
 impl FileLogger {
     pub fn new(simulation_id: String) -> Self {
+        Self::with_format(simulation_id, LogFormat::default())
+    }
+
+    pub fn with_format(simulation_id: String, format: LogFormat) -> Self {
         Self {
             simulation_id,
             snapshots: vec![],
+            format,
+            root: data_root(),
         }
     }
+
+    // Writes below `root` instead of the default data root
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
 }
 
 impl Drop for FileLogger {
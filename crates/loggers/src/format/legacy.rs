@@ -0,0 +1,82 @@
+// Snapshot layouts written by older versions of the binary and stream formats. Bincode is not
+// self describing, so every version needs its own layout. Fields added since are left empty.
+
+use crate::{
+    GroundTruth, SnapShot,
+    format::{DecodeError, FORMAT_VERSION},
+};
+use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{io::Read, time::Duration};
+
+// 1: before the ground truth
+#[derive(Deserialize)]
+struct SnapShotV1 {
+    duration: Duration,
+    motor_input: MotorInput,
+    battery_update: BatteryUpdate,
+    gyro_update: GyroUpdate,
+    channels: Channels,
+}
+
+impl From<SnapShotV1> for SnapShot {
+    fn from(snapshot: SnapShotV1) -> Self {
+        SnapShot::new(
+            snapshot.duration,
+            snapshot.motor_input,
+            snapshot.battery_update,
+            snapshot.gyro_update,
+            snapshot.channels,
+        )
+    }
+}
+
+// 2: before the shadow controller motor input
+#[derive(Deserialize)]
+struct SnapShotV2 {
+    duration: Duration,
+    motor_input: MotorInput,
+    battery_update: BatteryUpdate,
+    gyro_update: GyroUpdate,
+    channels: Channels,
+    ground_truth: Option<GroundTruth>,
+}
+
+impl From<SnapShotV2> for SnapShot {
+    fn from(snapshot: SnapShotV2) -> Self {
+        SnapShot {
+            ground_truth: snapshot.ground_truth,
+            ..SnapShot::new(
+                snapshot.duration,
+                snapshot.motor_input,
+                snapshot.battery_update,
+                snapshot.gyro_update,
+                snapshot.channels,
+            )
+        }
+    }
+}
+
+fn read_steps<S: DeserializeOwned + Into<SnapShot>>(
+    reader: impl Read,
+) -> Result<Vec<SnapShot>, DecodeError> {
+    let steps: Vec<S> = bincode::deserialize_from(reader).map_err(DecodeError::Binary)?;
+    Ok(steps.into_iter().map(Into::into).collect())
+}
+
+// Reads a bincode encoded `Vec<SnapShot>` written by `version` of the format
+pub(super) fn read_versioned_steps(
+    version: u32,
+    reader: impl Read,
+) -> Result<Vec<SnapShot>, DecodeError> {
+    match version {
+        1 => read_steps::<SnapShotV1>(reader),
+        2 => read_steps::<SnapShotV2>(reader),
+        FORMAT_VERSION => read_steps::<SnapShot>(reader),
+        _ => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+pub(super) fn is_supported(version: u32) -> bool {
+    (1..=FORMAT_VERSION).contains(&version)
+}
@@ -0,0 +1,247 @@
+// On-disk encodings of a `FlightLog`. JSON is the original format and is still the default. The
+// binary format is a short header followed by the bincode encoded log, which is several times
+// smaller and much faster to parse. Readers detect the format from the header, so both kinds of
+// files can live next to each other in the replay directory. The stream format is written
+// incrementally by the streaming file logger, see `stream`.
+
+mod legacy;
+pub mod stream;
+
+use crate::FlightLog;
+use derive_more::derive::{Display, Error};
+use stream::{STREAM_MAGIC, StreamWriter, decode_stream};
+
+pub const MAGIC: &[u8; 4] = b"QLOG";
+// 2: snapshots carry an optional ground truth
+// 3: snapshots carry an optional shadow controller motor input
+pub const FORMAT_VERSION: u32 = 3;
+const HEADER_LEN: usize = MAGIC.len() + size_of::<u32>();
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Binary,
+    Stream,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum DecodeError {
+    #[display("invalid json flight log: {_0}")]
+    Json(serde_json::Error),
+    #[display("invalid binary flight log: {_0}")]
+    Binary(bincode::Error),
+    #[display("binary flight log header is truncated")]
+    Truncated,
+    // the header is ours, but written by a different version of the format
+    #[display("unsupported flight log version {_0}")]
+    UnsupportedVersion(#[error(not(source))] u32),
+}
+
+pub fn detect_format(bytes: &[u8]) -> LogFormat {
+    if bytes.starts_with(MAGIC) {
+        LogFormat::Binary
+    } else if bytes.starts_with(STREAM_MAGIC) {
+        LogFormat::Stream
+    } else {
+        LogFormat::Json
+    }
+}
+
+pub fn encode(flight_log: &FlightLog, format: LogFormat) -> Vec<u8> {
+    match format {
+        LogFormat::Json => serde_json::to_vec(flight_log).unwrap(),
+        LogFormat::Binary => {
+            let mut bytes = Vec::with_capacity(HEADER_LEN);
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+            bincode::serialize_into(&mut bytes, flight_log).unwrap();
+            bytes
+        }
+        LogFormat::Stream => {
+            let mut writer = StreamWriter::new(vec![], &flight_log.simulation_id).unwrap();
+            writer.write_chunk(&flight_log.steps).unwrap();
+            writer.finish().unwrap()
+        }
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<FlightLog, DecodeError> {
+    match detect_format(bytes) {
+        LogFormat::Json => serde_json::from_slice(bytes).map_err(DecodeError::Json),
+        LogFormat::Binary => {
+            let version_bytes = bytes
+                .get(MAGIC.len()..HEADER_LEN)
+                .ok_or(DecodeError::Truncated)?;
+            let version = u32::from_le_bytes(version_bytes.try_into().unwrap());
+            if !legacy::is_supported(version) {
+                return Err(DecodeError::UnsupportedVersion(version));
+            }
+            // same layout as `FlightLog`, read field by field so that older snapshots still decode
+            let mut reader = &bytes[HEADER_LEN..];
+            let simulation_id =
+                bincode::deserialize_from(&mut reader).map_err(DecodeError::Binary)?;
+            let steps = legacy::read_versioned_steps(version, reader)?;
+            Ok(FlightLog::new(simulation_id, steps))
+        }
+        LogFormat::Stream => decode_stream(bytes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FlightLog, GroundTruth, SnapShot,
+        format::{
+            LogFormat, MAGIC, decode, detect_format, encode,
+            stream::{STREAM_MAGIC, StreamWriter},
+        },
+    };
+    use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+    use serde::Serialize;
+    use std::time::Duration;
+
+    fn flight_log() -> FlightLog {
+        let steps = (0..10)
+            .map(|i| {
+                SnapShot::new(
+                    Duration::from_millis(i),
+                    MotorInput::default(),
+                    BatteryUpdate::default(),
+                    GyroUpdate::default(),
+                    Channels {
+                        throttle: i as f64 / 10.,
+                        roll: 0.,
+                        pitch: 0.,
+                        yaw: 0.,
+                    },
+                )
+            })
+            .collect();
+        FlightLog::new("test".into(), steps)
+    }
+
+    #[test]
+    fn roundtrip_both_formats() {
+        let fl = flight_log();
+        for format in [LogFormat::Json, LogFormat::Binary, LogFormat::Stream] {
+            let bytes = encode(&fl, format);
+            assert_eq!(detect_format(&bytes), format);
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded.simulation_id, fl.simulation_id);
+            assert_eq!(decoded.steps.len(), fl.steps.len());
+            assert_eq!(decoded.steps[3].channels, fl.steps[3].channels);
+        }
+    }
+
+    #[test]
+    fn stream_without_footer_keeps_complete_chunks() {
+        let fl = flight_log();
+        let mut writer = StreamWriter::new(vec![], &fl.simulation_id).unwrap();
+        writer.write_chunk(&fl.steps[..4]).unwrap();
+        writer.write_chunk(&fl.steps[4..]).unwrap();
+        let complete = writer.finish().unwrap();
+        // cut off the footer and part of the last chunk, like a logger that never closed
+        let crashed = &complete[..complete.len() - 60];
+
+        assert_eq!(decode(&complete).unwrap().steps.len(), 10);
+        assert_eq!(decode(crashed).unwrap().steps.len(), 4);
+    }
+
+    // u32 length followed by the bincode payload, as in the stream format
+    fn frame(value: &(impl Serialize + ?Sized)) -> Vec<u8> {
+        let payload = bincode::serialize(value).unwrap();
+        [(payload.len() as u32).to_le_bytes().to_vec(), payload].concat()
+    }
+
+    // Binary and stream encodings of `steps` as written by `version`
+    fn legacy_payloads(
+        version: u32,
+        simulation_id: &str,
+        steps: &[impl Serialize],
+    ) -> [Vec<u8>; 2] {
+        let binary = [
+            MAGIC.as_slice(),
+            &version.to_le_bytes(),
+            &bincode::serialize(&(simulation_id, steps)).unwrap(),
+        ]
+        .concat();
+        let stream = [
+            STREAM_MAGIC.as_slice(),
+            &version.to_le_bytes(),
+            &frame(simulation_id),
+            &frame(&steps[..4]),
+            &frame(&steps[4..]),
+        ]
+        .concat();
+        [binary, stream]
+    }
+
+    #[test]
+    fn decodes_version_1_without_ground_truth() {
+        let fl = flight_log();
+        // version 1 snapshots, bincode lays out tuples like the structs
+        let steps: Vec<_> = fl
+            .steps
+            .iter()
+            .map(|s| {
+                (
+                    s.duration,
+                    s.motor_input,
+                    s.battery_update,
+                    s.gyro_update,
+                    s.channels,
+                )
+            })
+            .collect();
+
+        for bytes in legacy_payloads(1, &fl.simulation_id, &steps) {
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded.simulation_id, fl.simulation_id);
+            assert_eq!(decoded.steps.len(), fl.steps.len());
+            assert_eq!(decoded.steps[3].channels, fl.steps[3].channels);
+            assert!(decoded.steps.iter().all(|s| s.ground_truth.is_none()));
+        }
+    }
+
+    #[test]
+    fn decodes_version_2_without_shadow_motor_input() {
+        let fl = flight_log();
+        let ground_truth = GroundTruth {
+            position: [1., 2., 3.],
+            ..Default::default()
+        };
+        let steps: Vec<_> = fl
+            .steps
+            .iter()
+            .map(|s| {
+                let SnapShot {
+                    duration,
+                    motor_input,
+                    battery_update,
+                    gyro_update,
+                    channels,
+                    ..
+                } = *s;
+                (
+                    duration,
+                    motor_input,
+                    battery_update,
+                    gyro_update,
+                    channels,
+                    Some(ground_truth),
+                )
+            })
+            .collect();
+
+        for bytes in legacy_payloads(2, &fl.simulation_id, &steps) {
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded.steps.len(), fl.steps.len());
+            assert_eq!(decoded.steps[3].channels, fl.steps[3].channels);
+            assert!(decoded.steps.iter().all(|s| {
+                s.ground_truth.map(|g| g.position) == Some(ground_truth.position)
+                    && s.shadow_motor_input.is_none()
+            }));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// Append-only layout used by the streaming file logger:
+//
+//   header: STREAM_MAGIC | version: u32 | frame(simulation_id)
+//   chunks: frame(Vec<SnapShot>)*
+//   footer: frame(StreamIndex) | footer offset: u64 | FOOTER_MAGIC
+//
+// where every frame is a u32 length followed by the bincode payload, all integers little endian.
+// The footer is only written when the logger is closed. Without it (e.g. after a crash) the reader
+// falls back to scanning the chunks and drops a trailing partially written one.
+
+use crate::{
+    FlightLog, SnapShot,
+    format::{DecodeError, FORMAT_VERSION, legacy},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::io::{self, Write};
+
+pub const STREAM_MAGIC: &[u8; 4] = b"QLGS";
+pub const FOOTER_MAGIC: &[u8; 4] = b"QEND";
+const FOOTER_TRAILER_LEN: usize = size_of::<u64>() + FOOTER_MAGIC.len();
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StreamIndex {
+    // byte offset of every chunk frame from the start of the file
+    pub chunk_offsets: Vec<u64>,
+    pub step_count: u64,
+}
+
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    index: StreamIndex,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(mut writer: W, simulation_id: &str) -> io::Result<Self> {
+        writer.write_all(STREAM_MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let mut stream_writer = Self {
+            writer,
+            offset: (STREAM_MAGIC.len() + size_of::<u32>()) as u64,
+            index: StreamIndex::default(),
+        };
+        stream_writer.write_frame(&simulation_id)?;
+        Ok(stream_writer)
+    }
+
+    fn write_frame<T: Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        let payload = bincode::serialize(value).map_err(io::Error::other)?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.offset += (size_of::<u32>() + payload.len()) as u64;
+        Ok(())
+    }
+
+    pub fn write_chunk(&mut self, steps: &[SnapShot]) -> io::Result<()> {
+        if steps.is_empty() {
+            return Ok(());
+        }
+        self.index.chunk_offsets.push(self.offset);
+        self.index.step_count += steps.len() as u64;
+        self.write_frame(steps)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // Writes the index and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let footer_offset = self.offset;
+        let index = std::mem::take(&mut self.index);
+        self.write_frame(&index)?;
+        self.writer.write_all(&footer_offset.to_le_bytes())?;
+        self.writer.write_all(FOOTER_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Returns the payload of the frame at `offset` and the offset of the next one, None if it is cut
+// short
+fn read_payload(bytes: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let len_bytes = bytes.get(offset..offset + size_of::<u32>())?;
+    let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+    let start = offset + size_of::<u32>();
+    let payload = bytes.get(start..start + len)?;
+    Some((payload, start + len))
+}
+
+fn read_frame<T: DeserializeOwned>(
+    bytes: &[u8],
+    offset: usize,
+) -> Option<Result<(T, usize), DecodeError>> {
+    let (payload, next_offset) = read_payload(bytes, offset)?;
+    Some(
+        bincode::deserialize(payload)
+            .map(|value| (value, next_offset))
+            .map_err(DecodeError::Binary),
+    )
+}
+
+// Reads the chunk at `offset`, with snapshots in the layout of `version`
+fn read_chunk(
+    bytes: &[u8],
+    offset: usize,
+    version: u32,
+) -> Option<Result<(Vec<SnapShot>, usize), DecodeError>> {
+    let (payload, next_offset) = read_payload(bytes, offset)?;
+    Some(legacy::read_versioned_steps(version, payload).map(|chunk| (chunk, next_offset)))
+}
+
+fn read_index(bytes: &[u8]) -> Option<(StreamIndex, usize)> {
+    let trailer_start = bytes.len().checked_sub(FOOTER_TRAILER_LEN)?;
+    if !bytes.ends_with(FOOTER_MAGIC) {
+        return None;
+    }
+    let offset_bytes = &bytes[trailer_start..trailer_start + size_of::<u64>()];
+    let footer_offset = u64::from_le_bytes(offset_bytes.try_into().unwrap()) as usize;
+    let (index, _) = read_frame(bytes, footer_offset)?.ok()?;
+    Some((index, footer_offset))
+}
+
+pub fn decode_stream(bytes: &[u8]) -> Result<FlightLog, DecodeError> {
+    let header_len = STREAM_MAGIC.len() + size_of::<u32>();
+    let version_bytes = bytes
+        .get(STREAM_MAGIC.len()..header_len)
+        .ok_or(DecodeError::Truncated)?;
+    let version = u32::from_le_bytes(version_bytes.try_into().unwrap());
+    if !legacy::is_supported(version) {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let (simulation_id, mut offset): (String, usize) =
+        read_frame(bytes, header_len).ok_or(DecodeError::Truncated)??;
+
+    match read_index(bytes) {
+        Some((index, footer_offset)) => {
+            let mut steps = Vec::with_capacity(index.step_count as usize);
+            for chunk_offset in index.chunk_offsets {
+                let (chunk, _) =
+                    read_chunk(&bytes[..footer_offset], chunk_offset as usize, version)
+                        .ok_or(DecodeError::Truncated)??;
+                steps.extend(chunk);
+            }
+            Ok(FlightLog::new(simulation_id, steps))
+        }
+        None => {
+            // the logger did not close properly, recover every complete chunk
+            let mut steps = vec![];
+            while let Some(frame) = read_chunk(bytes, offset, version) {
+                let (chunk, next_offset) = frame?;
+                steps.extend(chunk);
+                offset = next_offset;
+            }
+            Ok(FlightLog::new(simulation_id, steps))
+        }
+    }
+}
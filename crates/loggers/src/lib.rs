@@ -1,7 +1,11 @@
 pub mod empty_logger;
 pub mod file_logger;
+pub mod format;
 pub mod memory_logger;
 pub mod rerun_logger;
+pub mod storage;
+pub mod streaming_file_logger;
+pub mod tabular;
 
 use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
 use serde::{Deserialize, Serialize};
@@ -15,8 +19,31 @@ pub struct SnapShot {
     pub battery_update: BatteryUpdate,
     pub gyro_update: GyroUpdate,
     pub channels: Channels,
-    // // TODO: this has a lot, I think this should be enough
-    // pub current_frame: SimulationFrame,
+    // What actually happened in the simulation, as opposed to what the flight controller sees.
+    // Missing from replays recorded before it was introduced and from real flights.
+    #[serde(default)]
+    pub ground_truth: Option<GroundTruth>,
+    // What the shadow controller of the simulation would have sent to the motors at this step,
+    // see `Simulator::shadow_controller`. Only logged, the drone flies `motor_input`.
+    #[serde(default)]
+    pub shadow_motor_input: Option<MotorInput>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct RotorGroundTruth {
+    pub rpm: f64,
+    pub effective_thrust: f64,
+    pub current: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct GroundTruth {
+    pub position: [f64; 3],
+    pub linear_velocity: [f64; 3], // world frame
+    pub rotation: [f64; 4],        // i, j, k, w, same as the gyro update
+    pub angular_velocity: [f64; 3],
+    pub rotors: [RotorGroundTruth; 4],
+    pub bat_capacity: f64, // remaining battery capacity
 }
 
 impl SnapShot {
@@ -33,6 +60,8 @@ impl SnapShot {
             battery_update,
             gyro_update,
             channels,
+            ground_truth: None,
+            shadow_motor_input: None,
         }
     }
 }
@@ -67,6 +96,38 @@ impl FlightLog {
     pub fn flight_log_len(&self) -> Duration {
         self.steps.last().unwrap().duration
     }
+
+    // Median time between consecutive steps, the control period of a log recorded at the
+    // controller ticks (or downsampled). None with less than two steps.
+    pub fn sample_period(&self) -> Option<Duration> {
+        let mut intervals = self
+            .steps
+            .windows(2)
+            .map(|w| w[1].duration.saturating_sub(w[0].duration))
+            .collect::<Vec<_>>();
+        intervals.sort();
+        intervals.get(intervals.len() / 2).copied()
+    }
+
+    // Mean squared difference between the applied and the shadow motor inputs, over the steps
+    // logged with a shadow controller. None if there are none.
+    pub fn shadow_mse(&self) -> Option<f64> {
+        let squared_errors = self
+            .steps
+            .iter()
+            .filter_map(|step| {
+                let shadow = step.shadow_motor_input?;
+                Some(
+                    (0..4)
+                        .map(|i| (step.motor_input[i] - shadow[i]).powi(2))
+                        .sum::<f64>()
+                        / 4.,
+                )
+            })
+            .collect::<Vec<_>>();
+        (!squared_errors.is_empty())
+            .then(|| squared_errors.iter().sum::<f64>() / squared_errors.len() as f64)
+    }
 }
 
 pub trait Logger: Sync + Send + Any {
@@ -1,24 +1,93 @@
-use std::time::Duration;
+// Entity path layout of the recordings, all on the `stable_time` timeline:
+//
+//   rates/{roll,pitch,yaw}/measured   angular velocity reported by the gyro [rad/s]
+//   rates/{roll,pitch,yaw}/target     betaflight rates target derived from the sticks [rad/s]
+//   motors/{0,1,2,3}                  motor input of the flight controller [0, 1]
+//   battery/voltage                   battery voltage seen by the flight controller [V]
+//   battery/voltage_sag               voltage under load [V]
+//   channels/{throttle,roll,pitch,yaw} stick inputs [-1, 1]
+//   shadow_motors/{0,1,2,3}           motor input of the shadow controller, if any [0, 1]
+//
+// and when the snapshots carry the ground truth:
+//
+//   world/drone                       transform of the drone body
+//   world/drone/position              the drone as a point, at the origin of its transform
+//   world/trajectory/{segment}        line strips of the positions so far, one per segment
+//
+// The simulator is y up while rerun is z up, so positions and rotations are logged with y and z
+// swapped.
 
-use crate::{Logger, SnapShot};
-use rerun::RecordingStream;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{GroundTruth, Logger, SnapShot};
+use flight_controller::bf_rates::stick_inputs_to_targets;
+use rerun::{LineStrips3D, Points3D, Quaternion, RecordingStream, Scalars, Transform3D};
+
+// the trajectory is logged in segments of this many positions, each on its own entity so
+// earlier segments stay visible and are never sent again
+const TRAJECTORY_LOG_INTERVAL: usize = 100;
+
+// Where the recording goes
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum RerunSink {
+    // start a new viewer, needs a display
+    #[default]
+    Spawn,
+    // write an .rrd file that can be opened with the viewer later
+    File(PathBuf),
+    // stream to an already running viewer, the default local one if no url is given
+    Connect(Option<String>),
+}
 
 pub struct RerunLogger {
     rec: RecordingStream,
+    // positions not logged yet, starting with the last point of the previous segment
+    trajectory: Vec<[f32; 3]>,
+    trajectory_segments: usize,
 }
 
 impl Logger for RerunLogger {
     fn log_time_stamp(&mut self, snapshot: SnapShot) {
         self.rec.set_time("stable_time", snapshot.duration);
-        // let position = snapshot.current_frame.drone_frame_state.position;
-        // let point = Vec3::new(position.x as f32, position.z as f32, position.y as f32);
-        // let points = Points3D::new(vec![point]);
-        // self.rec
-        //     .log("drone/pos", &points.with_radii([0.8]))
-        //     .unwrap();
+
+        let [roll, pitch, yaw] = snapshot.gyro_update.angular_velocity;
+        let target = stick_inputs_to_targets(&snapshot.channels);
+        self.log_scalar("rates/roll/measured", roll);
+        self.log_scalar("rates/pitch/measured", pitch);
+        self.log_scalar("rates/yaw/measured", yaw);
+        self.log_scalar("rates/roll/target", target.roll);
+        self.log_scalar("rates/pitch/target", target.pitch);
+        self.log_scalar("rates/yaw/target", target.yaw);
+
+        for (motor, input) in snapshot.motor_input.input.iter().enumerate() {
+            self.log_scalar(&format!("motors/{motor}"), *input);
+        }
+
+        self.log_scalar("battery/voltage", snapshot.battery_update.bat_voltage);
+        self.log_scalar(
+            "battery/voltage_sag",
+            snapshot.battery_update.bat_voltage_sag,
+        );
+
+        let channels = snapshot.channels;
+        self.log_scalar("channels/throttle", channels.throttle);
+        self.log_scalar("channels/roll", channels.roll);
+        self.log_scalar("channels/pitch", channels.pitch);
+        self.log_scalar("channels/yaw", channels.yaw);
+
+        if let Some(shadow) = snapshot.shadow_motor_input {
+            for (motor, input) in shadow.input.iter().enumerate() {
+                self.log_scalar(&format!("shadow_motors/{motor}"), *input);
+            }
+        }
+
+        if let Some(ground_truth) = snapshot.ground_truth {
+            self.log_ground_truth(&ground_truth);
+        }
     }
 
     fn flush(&mut self) {
+        self.log_trajectory();
         self.rec.flush_blocking();
     }
 }
@@ -31,11 +100,70 @@ impl Drop for RerunLogger {
 
 impl RerunLogger {
     pub fn new(simulation_id: String) -> Self {
-        let rec = rerun::RecordingStreamBuilder::new(simulation_id)
-            .spawn()
-            .unwrap();
+        Self::with_sink(simulation_id, RerunSink::default())
+    }
+
+    pub fn with_sink(simulation_id: String, sink: RerunSink) -> Self {
+        let builder = rerun::RecordingStreamBuilder::new(simulation_id);
+        let rec = match sink {
+            RerunSink::Spawn => builder.spawn(),
+            RerunSink::File(path) => builder.save(path),
+            RerunSink::Connect(None) => builder.connect_grpc(),
+            RerunSink::Connect(Some(url)) => builder.connect_grpc_opts(url, None),
+        }
+        .unwrap();
         rec.set_time("stable_time", Duration::ZERO);
 
-        Self { rec }
+        Self {
+            rec,
+            trajectory: vec![],
+            trajectory_segments: 0,
+        }
+    }
+
+    fn log_scalar(&self, entity_path: &str, value: f64) {
+        self.rec.log(entity_path, &Scalars::single(value)).unwrap();
+    }
+
+    fn log_ground_truth(&mut self, ground_truth: &GroundTruth) {
+        let [x, y, z] = ground_truth.position;
+        let position = [x as f32, z as f32, y as f32];
+        // swapping two axes mirrors the frame, which flips the sense of the rotation
+        let [i, j, k, w] = ground_truth.rotation;
+        let rotation = Quaternion::from_xyzw([-i as f32, -k as f32, -j as f32, w as f32]);
+        self.rec
+            .log(
+                "world/drone",
+                &Transform3D::from_translation_rotation(position, rotation),
+            )
+            .unwrap();
+        // child of the transform, so it is logged at the origin of the drone frame
+        self.rec
+            .log(
+                "world/drone/position",
+                &Points3D::new([[0.0, 0.0, 0.0]]).with_radii([0.08]),
+            )
+            .unwrap();
+
+        self.trajectory.push(position);
+        if self.trajectory.len() > TRAJECTORY_LOG_INTERVAL {
+            self.log_trajectory();
+        }
+    }
+
+    fn log_trajectory(&mut self) {
+        if self.trajectory.len() < 2 {
+            return;
+        }
+        self.rec
+            .log(
+                format!("world/trajectory/{}", self.trajectory_segments),
+                &LineStrips3D::new([self.trajectory.iter().copied()]),
+            )
+            .unwrap();
+        self.trajectory_segments += 1;
+        // keep the last point so the next segment connects to this one
+        let last = self.trajectory.split_off(self.trajectory.len() - 1);
+        self.trajectory = last;
     }
 }
@@ -0,0 +1,26 @@
+// Everything persisted by the loggers, loaders and training utilities lives below one root. It
+// defaults to `$HOME/.local/share/quad` and can be moved with `QUAD_DATA_ROOT`, so experiments and
+// CI jobs can work in isolated directories. Code that gets a root passed explicitly (e.g. through
+// the `SimContext`) uses that one instead.
+
+use std::{env, path::PathBuf};
+
+pub const DATA_ROOT_ENV: &str = "QUAD_DATA_ROOT";
+
+pub fn data_root() -> PathBuf {
+    match env::var_os(DATA_ROOT_ENV) {
+        Some(root) if !root.is_empty() => PathBuf::from(root),
+        _ => home_dir().join(".local/share/quad"),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .expect("HOME is not set, set QUAD_DATA_ROOT instead")
+}
+
+// Where the file loggers write replays and the file loader looks for them
+pub fn replays_dir(root: &std::path::Path) -> PathBuf {
+    root.join("replays")
+}
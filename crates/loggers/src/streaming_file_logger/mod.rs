@@ -0,0 +1,88 @@
+use crate::{
+    Logger, SnapShot,
+    format::stream::StreamWriter,
+    storage::{data_root, replays_dir},
+};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+// Unlike the FileLogger, this only keeps the current chunk in memory. Full chunks are appended to
+// the file as they arrive and the index is written when the logger is dropped. The file is created
+// with the first chunk, so a logger that never logs leaves nothing behind.
+pub struct StreamingFileLogger {
+    simulation_id: String,
+    chunk: Vec<SnapShot>,
+    chunk_size: usize,
+    writer: Option<StreamWriter<BufWriter<File>>>,
+    root: PathBuf,
+}
+
+impl StreamingFileLogger {
+    pub fn new(simulation_id: String) -> Self {
+        Self::with_chunk_size(simulation_id, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(simulation_id: String, chunk_size: usize) -> Self {
+        Self {
+            simulation_id,
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size,
+            writer: None,
+            root: data_root(),
+        }
+    }
+
+    // Writes below `root` instead of the default data root
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
+
+    fn write_chunk(&mut self) {
+        if self.chunk.is_empty() {
+            return;
+        }
+        let writer = self.writer.get_or_insert_with(|| {
+            let mut log_path = replays_dir(&self.root);
+            log_path.push(&self.simulation_id);
+            if let Some(parent) = log_path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            let file = BufWriter::new(File::create(log_path).unwrap());
+            StreamWriter::new(file, &self.simulation_id).unwrap()
+        });
+        writer.write_chunk(&self.chunk).unwrap();
+        self.chunk.clear();
+    }
+}
+
+impl Logger for StreamingFileLogger {
+    fn log_time_stamp(&mut self, snapshot: SnapShot) {
+        self.chunk.push(snapshot);
+        if self.chunk.len() >= self.chunk_size {
+            self.write_chunk();
+        }
+    }
+
+    // writes the pending chunk, the file stays open for more snapshots
+    fn flush(&mut self) {
+        self.write_chunk();
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().unwrap();
+        }
+    }
+}
+
+impl Drop for StreamingFileLogger {
+    fn drop(&mut self) {
+        self.write_chunk();
+        if let Some(writer) = self.writer.take() {
+            writer.finish().unwrap();
+        }
+    }
+}
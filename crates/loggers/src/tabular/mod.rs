@@ -0,0 +1,403 @@
+// Tidy CSV export of flight logs: one row per `SnapShot` with a named column for every field, so
+// logs can be analyzed with pandas/Polars and externally recorded flights can be brought back in.
+// A file can hold several flight logs (e.g. a whole data set), rows are grouped by the
+// `simulation_id` and the optional `split` column. Ground truth and shadow motor columns are left
+// empty for snapshots without them.
+
+use crate::{FlightLog, GroundTruth, RotorGroundTruth, SnapShot};
+use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapShotRow {
+    pub split: Option<String>,
+    pub simulation_id: String,
+    pub time_ns: u64,
+    pub motor_0: f64,
+    pub motor_1: f64,
+    pub motor_2: f64,
+    pub motor_3: f64,
+    pub bat_voltage_sag: f64,
+    pub bat_voltage: f64,
+    pub amperage: f64,
+    pub m_ah_drawn: f64,
+    pub cell_count: u64,
+    pub gyro_rotation_i: f64,
+    pub gyro_rotation_j: f64,
+    pub gyro_rotation_k: f64,
+    pub gyro_rotation_w: f64,
+    pub gyro_linear_acc_x: f64,
+    pub gyro_linear_acc_y: f64,
+    pub gyro_linear_acc_z: f64,
+    pub gyro_angular_velocity_x: f64,
+    pub gyro_angular_velocity_y: f64,
+    pub gyro_angular_velocity_z: f64,
+    pub throttle: f64,
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+    pub gt_position_x: Option<f64>,
+    pub gt_position_y: Option<f64>,
+    pub gt_position_z: Option<f64>,
+    pub gt_linear_velocity_x: Option<f64>,
+    pub gt_linear_velocity_y: Option<f64>,
+    pub gt_linear_velocity_z: Option<f64>,
+    pub gt_rotation_i: Option<f64>,
+    pub gt_rotation_j: Option<f64>,
+    pub gt_rotation_k: Option<f64>,
+    pub gt_rotation_w: Option<f64>,
+    pub gt_angular_velocity_x: Option<f64>,
+    pub gt_angular_velocity_y: Option<f64>,
+    pub gt_angular_velocity_z: Option<f64>,
+    pub gt_rpm_0: Option<f64>,
+    pub gt_rpm_1: Option<f64>,
+    pub gt_rpm_2: Option<f64>,
+    pub gt_rpm_3: Option<f64>,
+    pub gt_thrust_0: Option<f64>,
+    pub gt_thrust_1: Option<f64>,
+    pub gt_thrust_2: Option<f64>,
+    pub gt_thrust_3: Option<f64>,
+    pub gt_current_0: Option<f64>,
+    pub gt_current_1: Option<f64>,
+    pub gt_current_2: Option<f64>,
+    pub gt_current_3: Option<f64>,
+    pub gt_bat_capacity: Option<f64>,
+    pub shadow_motor_0: Option<f64>,
+    pub shadow_motor_1: Option<f64>,
+    pub shadow_motor_2: Option<f64>,
+    pub shadow_motor_3: Option<f64>,
+}
+
+impl SnapShotRow {
+    pub fn from_snapshot(split: Option<&str>, simulation_id: &str, snapshot: &SnapShot) -> Self {
+        let SnapShot {
+            duration,
+            motor_input,
+            battery_update,
+            gyro_update,
+            channels,
+            ground_truth,
+            shadow_motor_input,
+        } = snapshot;
+        let [motor_0, motor_1, motor_2, motor_3] = motor_input.input;
+        let [
+            gyro_rotation_i,
+            gyro_rotation_j,
+            gyro_rotation_k,
+            gyro_rotation_w,
+        ] = gyro_update.rotation;
+        let [gyro_linear_acc_x, gyro_linear_acc_y, gyro_linear_acc_z] = gyro_update.linear_acc;
+        let [
+            gyro_angular_velocity_x,
+            gyro_angular_velocity_y,
+            gyro_angular_velocity_z,
+        ] = gyro_update.angular_velocity;
+        let mut row = Self {
+            split: split.map(str::to_owned),
+            simulation_id: simulation_id.to_owned(),
+            time_ns: duration.as_nanos() as u64,
+            motor_0,
+            motor_1,
+            motor_2,
+            motor_3,
+            bat_voltage_sag: battery_update.bat_voltage_sag,
+            bat_voltage: battery_update.bat_voltage,
+            amperage: battery_update.amperage,
+            m_ah_drawn: battery_update.m_ah_drawn,
+            cell_count: battery_update.cell_count,
+            gyro_rotation_i,
+            gyro_rotation_j,
+            gyro_rotation_k,
+            gyro_rotation_w,
+            gyro_linear_acc_x,
+            gyro_linear_acc_y,
+            gyro_linear_acc_z,
+            gyro_angular_velocity_x,
+            gyro_angular_velocity_y,
+            gyro_angular_velocity_z,
+            throttle: channels.throttle,
+            roll: channels.roll,
+            pitch: channels.pitch,
+            yaw: channels.yaw,
+            ..Default::default()
+        };
+        if let Some(gt) = ground_truth {
+            [row.gt_position_x, row.gt_position_y, row.gt_position_z] = gt.position.map(Some);
+            [
+                row.gt_linear_velocity_x,
+                row.gt_linear_velocity_y,
+                row.gt_linear_velocity_z,
+            ] = gt.linear_velocity.map(Some);
+            [
+                row.gt_rotation_i,
+                row.gt_rotation_j,
+                row.gt_rotation_k,
+                row.gt_rotation_w,
+            ] = gt.rotation.map(Some);
+            [
+                row.gt_angular_velocity_x,
+                row.gt_angular_velocity_y,
+                row.gt_angular_velocity_z,
+            ] = gt.angular_velocity.map(Some);
+            [row.gt_rpm_0, row.gt_rpm_1, row.gt_rpm_2, row.gt_rpm_3] =
+                gt.rotors.map(|r| Some(r.rpm));
+            [
+                row.gt_thrust_0,
+                row.gt_thrust_1,
+                row.gt_thrust_2,
+                row.gt_thrust_3,
+            ] = gt.rotors.map(|r| Some(r.effective_thrust));
+            [
+                row.gt_current_0,
+                row.gt_current_1,
+                row.gt_current_2,
+                row.gt_current_3,
+            ] = gt.rotors.map(|r| Some(r.current));
+            row.gt_bat_capacity = Some(gt.bat_capacity);
+        }
+        if let Some(shadow) = shadow_motor_input {
+            [
+                row.shadow_motor_0,
+                row.shadow_motor_1,
+                row.shadow_motor_2,
+                row.shadow_motor_3,
+            ] = shadow.input.map(Some);
+        }
+        row
+    }
+
+    // The ground truth is only restored if every one of its columns is present
+    fn ground_truth(&self) -> Option<GroundTruth> {
+        let rpm = [self.gt_rpm_0, self.gt_rpm_1, self.gt_rpm_2, self.gt_rpm_3];
+        let thrust = [
+            self.gt_thrust_0,
+            self.gt_thrust_1,
+            self.gt_thrust_2,
+            self.gt_thrust_3,
+        ];
+        let current = [
+            self.gt_current_0,
+            self.gt_current_1,
+            self.gt_current_2,
+            self.gt_current_3,
+        ];
+        let mut rotors = [RotorGroundTruth::default(); 4];
+        for (i, rotor) in rotors.iter_mut().enumerate() {
+            *rotor = RotorGroundTruth {
+                rpm: rpm[i]?,
+                effective_thrust: thrust[i]?,
+                current: current[i]?,
+            };
+        }
+        Some(GroundTruth {
+            position: [
+                self.gt_position_x?,
+                self.gt_position_y?,
+                self.gt_position_z?,
+            ],
+            linear_velocity: [
+                self.gt_linear_velocity_x?,
+                self.gt_linear_velocity_y?,
+                self.gt_linear_velocity_z?,
+            ],
+            rotation: [
+                self.gt_rotation_i?,
+                self.gt_rotation_j?,
+                self.gt_rotation_k?,
+                self.gt_rotation_w?,
+            ],
+            angular_velocity: [
+                self.gt_angular_velocity_x?,
+                self.gt_angular_velocity_y?,
+                self.gt_angular_velocity_z?,
+            ],
+            rotors,
+            bat_capacity: self.gt_bat_capacity?,
+        })
+    }
+
+    fn shadow_motor_input(&self) -> Option<MotorInput> {
+        Some(MotorInput {
+            input: [
+                self.shadow_motor_0?,
+                self.shadow_motor_1?,
+                self.shadow_motor_2?,
+                self.shadow_motor_3?,
+            ],
+        })
+    }
+
+    pub fn to_snapshot(&self) -> SnapShot {
+        SnapShot {
+            duration: Duration::from_nanos(self.time_ns),
+            motor_input: MotorInput {
+                input: [self.motor_0, self.motor_1, self.motor_2, self.motor_3],
+            },
+            battery_update: BatteryUpdate {
+                bat_voltage_sag: self.bat_voltage_sag,
+                bat_voltage: self.bat_voltage,
+                amperage: self.amperage,
+                m_ah_drawn: self.m_ah_drawn,
+                cell_count: self.cell_count,
+            },
+            gyro_update: GyroUpdate {
+                rotation: [
+                    self.gyro_rotation_i,
+                    self.gyro_rotation_j,
+                    self.gyro_rotation_k,
+                    self.gyro_rotation_w,
+                ],
+                linear_acc: [
+                    self.gyro_linear_acc_x,
+                    self.gyro_linear_acc_y,
+                    self.gyro_linear_acc_z,
+                ],
+                angular_velocity: [
+                    self.gyro_angular_velocity_x,
+                    self.gyro_angular_velocity_y,
+                    self.gyro_angular_velocity_z,
+                ],
+            },
+            channels: Channels {
+                throttle: self.throttle,
+                roll: self.roll,
+                pitch: self.pitch,
+                yaw: self.yaw,
+            },
+            ground_truth: self.ground_truth(),
+            shadow_motor_input: self.shadow_motor_input(),
+        }
+    }
+}
+
+// Appends the rows of a flight log, tagged with the split it belongs to if any
+pub fn write_rows<W: Write>(
+    writer: &mut csv::Writer<W>,
+    flight_log: &FlightLog,
+    split: Option<&str>,
+) -> csv::Result<()> {
+    for snapshot in &flight_log.steps {
+        writer.serialize(SnapShotRow::from_snapshot(
+            split,
+            &flight_log.simulation_id,
+            snapshot,
+        ))?;
+    }
+    Ok(())
+}
+
+pub fn write_flight_log<W: Write>(writer: W, flight_log: &FlightLog) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    write_rows(&mut writer, flight_log, None)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Reads every flight log in the file together with its split, in the order they appear
+pub fn read_flight_logs<R: Read>(reader: R) -> csv::Result<Vec<(Option<String>, FlightLog)>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut flight_logs: Vec<(Option<String>, FlightLog)> = vec![];
+    for row in reader.deserialize() {
+        let row: SnapShotRow = row?;
+        let snapshot = row.to_snapshot();
+        match flight_logs.last_mut() {
+            Some((split, fl)) if *split == row.split && fl.simulation_id == row.simulation_id => {
+                fl.steps.push(snapshot)
+            }
+            _ => flight_logs.push((row.split, FlightLog::new(row.simulation_id, vec![snapshot]))),
+        }
+    }
+    Ok(flight_logs)
+}
+
+// Reads a file holding a single flight log, the rows of any other log are ignored
+pub fn read_flight_log<R: Read>(reader: R) -> csv::Result<Option<FlightLog>> {
+    let flight_logs = read_flight_logs(reader)?;
+    Ok(flight_logs.into_iter().next().map(|(_, fl)| fl))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FlightLog, GroundTruth, SnapShot,
+        tabular::{read_flight_log, read_flight_logs, write_flight_log, write_rows},
+    };
+    use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+    use std::time::Duration;
+
+    fn flight_log(simulation_id: &str) -> FlightLog {
+        let steps = (0..5)
+            .map(|i| {
+                let mut snapshot = SnapShot::new(
+                    Duration::from_micros(i * 1250),
+                    MotorInput {
+                        input: [0.1, 0.2, 0.3, i as f64],
+                    },
+                    BatteryUpdate::default(),
+                    GyroUpdate::default(),
+                    Channels {
+                        throttle: -1.,
+                        roll: 0.5,
+                        pitch: 0.,
+                        yaw: 0.,
+                    },
+                );
+                if i % 2 == 0 {
+                    snapshot.ground_truth = Some(GroundTruth {
+                        position: [1., 2., i as f64],
+                        ..Default::default()
+                    });
+                }
+                if i == 3 {
+                    snapshot.shadow_motor_input = Some(MotorInput {
+                        input: [0.4, 0.3, 0.2, 0.1],
+                    });
+                }
+                snapshot
+            })
+            .collect();
+        FlightLog::new(simulation_id.into(), steps)
+    }
+
+    #[test]
+    fn flight_log_roundtrip() {
+        let fl = flight_log("episode");
+        let mut bytes = vec![];
+        write_flight_log(&mut bytes, &fl).unwrap();
+        let read = read_flight_log(bytes.as_slice()).unwrap().unwrap();
+        assert_eq!(read.simulation_id, "episode");
+        assert_eq!(read.steps.len(), 5);
+        for (a, b) in fl.steps.iter().zip(&read.steps) {
+            assert_eq!(a.duration, b.duration);
+            assert_eq!(a.motor_input.input, b.motor_input.input);
+            assert_eq!(a.channels, b.channels);
+            assert_eq!(
+                a.ground_truth.map(|gt| gt.position),
+                b.ground_truth.map(|gt| gt.position)
+            );
+            assert_eq!(
+                a.shadow_motor_input.map(|m| m.input),
+                b.shadow_motor_input.map(|m| m.input)
+            );
+        }
+    }
+
+    #[test]
+    fn rows_are_grouped_by_episode() {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        write_rows(&mut writer, &flight_log("a"), Some("train")).unwrap();
+        write_rows(&mut writer, &flight_log("b"), Some("train")).unwrap();
+        write_rows(&mut writer, &flight_log("a"), Some("test")).unwrap();
+        let bytes = writer.into_inner().unwrap();
+        let logs = read_flight_logs(bytes.as_slice()).unwrap();
+        let keys = logs
+            .iter()
+            .map(|(split, fl)| (split.as_deref().unwrap(), fl.simulation_id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("train", "a"), ("train", "b"), ("test", "a")]);
+    }
+}
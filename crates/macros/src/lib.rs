@@ -2,11 +2,17 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{Ident, ItemStruct, parse_macro_input};
 
+// Adds the number of fields as `const <attr>: usize` and their names as `FIELD_NAMES`, so saved
+// controllers can record the input layout they were trained with
 #[proc_macro_attribute]
 pub fn data_vars(attr: TokenStream, input: TokenStream) -> TokenStream {
     let const_ident = parse_macro_input!(attr as Ident);
     let item_struct = parse_macro_input!(input as ItemStruct);
     let field_count = item_struct.fields.iter().count();
+    let field_names = item_struct
+        .fields
+        .iter()
+        .filter_map(|field| field.ident.as_ref().map(|ident| ident.to_string()));
 
     let name = &item_struct.ident;
 
@@ -15,6 +21,7 @@ pub fn data_vars(attr: TokenStream, input: TokenStream) -> TokenStream {
 
         impl #name {
             pub const #const_ident: usize = #field_count;
+            pub const FIELD_NAMES: [&'static str; #field_count] = [#(#field_names),*];
         }
     };
 
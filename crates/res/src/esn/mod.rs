@@ -1,6 +1,6 @@
 // use crate::RcInput;
 use nalgebra::{Complex, ComplexField, DMatrix};
-use rand::thread_rng;
+use rand::{rngs::StdRng, SeedableRng};
 use rand_distr::{Bernoulli, Distribution, Uniform};
 use serde::{Deserialize, Serialize};
 
@@ -20,10 +20,12 @@ impl Esn {
         spectral_radius: f64,
         input_scaling: f64,
         n_vars: usize,
+        seed: u64,
     ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
         let internal_weights =
-            Self::internal_weights(n_internal_units, connectivity, spectral_radius);
-        let input_weights = Self::input_weights(n_internal_units, n_vars, input_scaling);
+            Self::internal_weights(&mut rng, n_internal_units, connectivity, spectral_radius);
+        let input_weights = Self::input_weights(&mut rng, n_internal_units, n_vars, input_scaling);
         Self {
             input_scaling,
             n_internal_units,
@@ -33,6 +35,7 @@ impl Esn {
     }
 
     fn internal_weights(
+        rng: &mut StdRng,
         n_internal_units: usize,
         connectivity: f64,
         spectral_radius: f64,
@@ -43,12 +46,11 @@ impl Esn {
         );
 
         // Generate a random sparse matrix with connectivity
-        let mut rng = thread_rng();
         let uniform_dist = Uniform::new(-0.5, 0.5);
         let bernoulli = Bernoulli::new(connectivity).unwrap();
         let mut internal_weights = DMatrix::from_fn(n_internal_units, n_internal_units, |_, _| {
-            if bernoulli.sample(&mut rng) {
-                uniform_dist.sample(&mut rng)
+            if bernoulli.sample(rng) {
+                uniform_dist.sample(rng)
             } else {
                 0.0
             }
@@ -70,14 +72,14 @@ impl Esn {
     }
 
     fn input_weights(
+        rng: &mut StdRng,
         n_internal_units: usize,
         variables: usize,
         input_scaling: f64,
     ) -> DMatrix<f64> {
-        let mut rng = thread_rng();
         let bernoulli = Bernoulli::new(0.5).unwrap();
         DMatrix::from_fn(n_internal_units, variables, |_, _| {
-            if bernoulli.sample(&mut rng) {
+            if bernoulli.sample(rng) {
                 input_scaling
             } else {
                 -input_scaling
@@ -239,8 +239,8 @@ pub fn fast_spiking_single_neuron_reservoir() -> IzhikevichReservoir {
     IzhikevichReservoir::new(a, b, c, d, DMatrix::zeros(1, 1), v0, u0)
 }
 
-pub fn random_izhikevich(n: usize, p: f64, excit_frac: f64) -> IzhikevichReservoir {
-    let mut rng = StdRng::seed_from_u64(42);
+pub fn random_izhikevich(n: usize, p: f64, excit_frac: f64, seed: u64) -> IzhikevichReservoir {
+    let mut rng = StdRng::seed_from_u64(seed);
 
     // Weight magnitudes (baseline; you’ll likely add a global g_rec later)
     let w_e_max: f64 = 0.5; // excitatory weights ~ U(0, w_e_max)
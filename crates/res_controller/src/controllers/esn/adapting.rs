@@ -0,0 +1,273 @@
+use crate::{
+    controllers::{
+        esn::{
+            NonAdaptingDroneRc, esn_res::DroneEsn, repr::BufferedStates,
+            setpoints::setpoints_from_flight_update,
+        },
+        hstack,
+    },
+    dimensionality_reducer::Reducer,
+    input_mapping::ReservoirInput,
+};
+use drone::default_drone::PROP_BLADE_MESH_NAMES;
+use flight_controller::{FlightController, FlightControllerUpdate, MotorInput};
+use nalgebra::{DMatrix, DVector};
+use ridge::{ReadoutModel, RidgeRegression, RidgeRegressionSol};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+// Motor pattern that raises each component of the gyro's angular velocity (the roll, pitch and
+// yaw rates of `ReservoirInput`), from the rotor layout of the default drone. The thrust acts
+// along the body y axis, so a rotor at r adds the torque r × (0, T, 0) = (-z T, 0, x T), and its
+// motor torque turns the frame about y in the rotor direction.
+pub fn rate_error_mixer() -> [[f64; 3]; 4] {
+    PROP_BLADE_MESH_NAMES
+        .map(|(rotor_dir, position)| [-position.z.signum(), rotor_dir, position.x.signum()])
+}
+
+// Where the targets of the online updates come from
+#[derive(Clone)]
+pub enum AdaptationSignal {
+    // Imitate a teacher controller (e.g. Betaflight) that sees the same updates. The teacher ticks
+    // at its own period, also while frozen, so its internal state follows the flight, and every
+    // step learns its latest motor input. Steps before its first tick don't learn.
+    Teacher(Arc<dyn FlightController>),
+    // Move the motors against the rate tracking error, `gains` per rate axis
+    RateError { gains: [f64; 3] },
+}
+
+impl AdaptationSignal {
+    pub fn rate_error() -> Self {
+        Self::RateError { gains: [0.05; 3] }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RlsParameters {
+    // λ in (0, 1], older samples are weighted by λ^age
+    pub forgetting_factor: f64,
+    // the inverse correlation matrix starts as I / regularization
+    pub regularization: f64,
+}
+
+impl Default for RlsParameters {
+    fn default() -> Self {
+        Self {
+            forgetting_factor: 0.9999,
+            regularization: 1.,
+        }
+    }
+}
+
+struct RlsState {
+    reservoir_state: DMatrix<f64>,
+    // targets x (1 + features), the first column is the intercept
+    weights: DMatrix<f64>,
+    // inverse of the (forgetting) correlation matrix of the features
+    p: DMatrix<f64>,
+    updates: usize,
+    // flight time on the learner's clock and the teacher's schedule on it
+    time: Duration,
+    teacher_next_tick: Option<Duration>,
+    teacher_motor_input: Option<MotorInput>,
+}
+
+// ESN controller whose linear readout keeps learning while flying, with recursive least
+// squares (FORCE learning): every step the weights move to reduce the error against the
+// adaptation signal. The reservoir, representation and reducer stay fixed.
+pub struct AdaptingDroneRc {
+    pub esn: DroneEsn,
+    pub representation: BufferedStates,
+    pub reducer: Reducer,
+    pub use_setpoint_repr: bool,
+    pub seed: u64,
+    pub scheduler_delta: Duration,
+    pub signal: AdaptationSignal,
+    pub rls: RlsParameters,
+    learning: Arc<AtomicBool>,
+    state: Mutex<RlsState>,
+}
+
+impl AdaptingDroneRc {
+    // Starts from the readout of a trained controller when it is a ridge readout, otherwise the
+    // weights start at zero
+    pub fn new(base: NonAdaptingDroneRc, signal: AdaptationSignal, rls: RlsParameters) -> Self {
+        let weights = match base.readout.as_ridge().and_then(|ridge| ridge.sol.as_ref()) {
+            Some(RidgeRegressionSol { coeff, intercept }) => hstack(
+                DMatrix::from_column_slice(4, 1, intercept.as_slice()),
+                coeff.clone(),
+            ),
+            None => DMatrix::zeros(4, 0),
+        };
+        let n_internal_units = base.esn.n_internal_units();
+        Self {
+            esn: base.esn,
+            representation: base.representation,
+            reducer: base.reducer,
+            use_setpoint_repr: base.use_setpoint_repr,
+            seed: base.seed,
+            scheduler_delta: base.scheduler_delta,
+            signal,
+            rls,
+            learning: Arc::new(AtomicBool::new(true)),
+            state: Mutex::new(RlsState {
+                reservoir_state: DMatrix::zeros(1, n_internal_units),
+                p: DMatrix::identity(weights.ncols(), weights.ncols()) / rls.regularization,
+                weights,
+                updates: 0,
+                time: Duration::ZERO,
+                teacher_next_tick: None,
+                teacher_motor_input: None,
+            }),
+        }
+    }
+
+    // Shared switch, learning stops while it is false and the weights are kept
+    pub fn learning(&self) -> Arc<AtomicBool> {
+        self.learning.clone()
+    }
+
+    pub fn freeze(&self) {
+        self.learning.store(false, Ordering::Relaxed);
+    }
+
+    pub fn unfreeze(&self) {
+        self.learning.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning.load(Ordering::Relaxed)
+    }
+
+    // Number of online updates so far
+    pub fn updates(&self) -> usize {
+        self.state.lock().unwrap().updates
+    }
+
+    // The current weights as a fixed readout, e.g. to save what was learned in flight
+    pub fn to_non_adapting(&self) -> NonAdaptingDroneRc {
+        let state = self.state.lock().unwrap();
+        let features = state.weights.ncols().saturating_sub(1);
+        let readout = RidgeRegression {
+            alpha: self.rls.regularization,
+            sol: Some(RidgeRegressionSol {
+                coeff: state.weights.columns(1, features).into_owned(),
+                intercept: state.weights.column(0).into_owned(),
+            }),
+            selection: None,
+        };
+        NonAdaptingDroneRc {
+            esn: self.esn.clone(),
+            representation: self.representation.clone(),
+            reducer: self.reducer.clone(),
+            readout: ReadoutModel::Ridge(readout),
+            use_setpoint_repr: self.use_setpoint_repr,
+            seed: self.seed,
+            scheduler_delta: self.scheduler_delta,
+            runtime_state: Arc::new(Mutex::new(DMatrix::zeros(1, self.esn.n_internal_units()))),
+        }
+    }
+
+    // None while the teacher has not ticked yet
+    fn target(
+        &self,
+        state: &mut RlsState,
+        update: FlightControllerUpdate,
+        prediction: &DVector<f64>,
+    ) -> Option<DVector<f64>> {
+        match &self.signal {
+            AdaptationSignal::Teacher(teacher) => {
+                let period = teacher.scheduler_delta();
+                let mut next_tick = *state.teacher_next_tick.get_or_insert(period);
+                while next_tick <= state.time {
+                    state.teacher_motor_input = Some(teacher.update(period.as_secs_f64(), update));
+                    if period.is_zero() {
+                        // without a period it ticks once per step
+                        break;
+                    }
+                    next_tick += period;
+                }
+                state.teacher_next_tick = Some(next_tick);
+                let motor_input = state.teacher_motor_input?;
+                Some(DVector::from_row_slice(&motor_input.input))
+            }
+            AdaptationSignal::RateError { gains } => {
+                let errors = ReservoirInput::from_flight_controller_update(update).rate_errors();
+                let mixer = rate_error_mixer();
+                Some(DVector::from_fn(4, |motor, _| {
+                    let correction: f64 = (0..3)
+                        .map(|axis| mixer[motor][axis] * gains[axis] * errors[axis])
+                        .sum();
+                    prediction[motor] + correction
+                }))
+            }
+        }
+    }
+}
+
+// One RLS step for the features `r` (with the leading 1) and the error prediction - target
+fn rls_update(state: &mut RlsState, r: &DVector<f64>, error: &DVector<f64>, lambda: f64) {
+    let pr = &state.p * r;
+    let k = &pr / (lambda + r.dot(&pr));
+    state.p = (&state.p - &k * pr.transpose()) / lambda;
+    state.weights -= error * k.transpose();
+    state.updates += 1;
+}
+
+impl FlightController for AdaptingDroneRc {
+    // Resets the reservoir for a new flight, what was learned is kept
+    fn init(&self) {
+        self.representation.reset_online();
+        let mut state = self.state.lock().unwrap();
+        state.reservoir_state = DMatrix::zeros(1, self.esn.n_internal_units());
+        state.time = Duration::ZERO;
+        state.teacher_next_tick = None;
+        state.teacher_motor_input = None;
+        if let AdaptationSignal::Teacher(teacher) = &self.signal {
+            teacher.init();
+        }
+    }
+
+    fn update(&self, delta_time: f64, update: FlightControllerUpdate) -> MotorInput {
+        let rc_input = ReservoirInput::from_flight_controller_update(update);
+        let mut state = self.state.lock().unwrap();
+        state.time += Duration::from_secs_f64(delta_time);
+        self.esn
+            .advance_state(&rc_input, &mut state.reservoir_state);
+        let state_repr = self.representation.repr_online_step(&state.reservoir_state);
+        let mut input_repr = self.reducer.transform(state_repr);
+        if self.use_setpoint_repr {
+            input_repr = hstack(input_repr, setpoints_from_flight_update(&update));
+        }
+        let features = input_repr.ncols();
+        let mut r = DVector::from_element(1 + features, 1.);
+        r.rows_mut(1, features)
+            .copy_from(&input_repr.row(0).transpose());
+
+        if state.weights.ncols() != r.len() {
+            // no ridge readout to start from
+            state.weights = DMatrix::zeros(4, r.len());
+            state.p = DMatrix::identity(r.len(), r.len()) / self.rls.regularization;
+        }
+        let prediction = &state.weights * &r;
+        // the teacher is stepped even when frozen to keep its state in sync with the flight
+        let target = self.target(&mut state, update, &prediction);
+        if let Some(target) = target.filter(|_| self.is_learning()) {
+            let error = &prediction - target;
+            rls_update(&mut state, &r, &error, self.rls.forgetting_factor);
+        }
+        MotorInput {
+            input: [0, 1, 2, 3].map(|i| prediction[i].clamp(0., 1.)),
+        }
+    }
+
+    fn scheduler_delta(&self) -> Duration {
+        self.scheduler_delta
+    }
+}
@@ -13,6 +13,7 @@ impl DroneEsn {
         connectivity: f64,
         spectral_radius: f64,
         input_scaling: f64,
+        seed: u64,
     ) -> Self {
         let esn = Esn::new(
             n_internal_units,
@@ -20,6 +21,7 @@ impl DroneEsn {
             spectral_radius,
             input_scaling,
             ReservoirInput::NVARS,
+            seed,
         );
         Self(esn)
     }
@@ -1,3 +1,4 @@
+pub mod adapting;
 pub mod esn_res;
 pub mod repr;
 pub mod setpoints;
@@ -14,16 +15,50 @@ use crate::{
     dimensionality_reducer::{Reducer, ReducerType},
     input_mapping::{MultipleReservoirInputTrajectory, ReservoirInput},
 };
+use derive_more::derive::{Display, Error, From};
 use flight_controller::{FlightController, FlightControllerUpdate, MotorInput};
 use loggers::FlightLog;
-use nalgebra::DMatrix;
-use ridge::RidgeRegression;
+use nalgebra::{DMatrix, DVector};
+use ridge::{
+    AlphaSearch, AlphaSearchError, AlphaSelectionMethod, Readout, ReadoutError, ReadoutModel,
+    ReadoutType, RidgeAccumulator, RidgeRegression, readout::deserialize_readout,
+};
 use serde::{Deserialize, Serialize};
+use simulator::schedule::ScheduleError;
 use std::{
+    slice,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
+// Why a controller could not be trained from the given data and parameters
+#[derive(Debug, Display, Error, From)]
+pub enum TrainingError {
+    // there were no episodes, or all of them were empty
+    #[display("no training data to fit the readout")]
+    #[from(ignore)]
+    NoTrainingData,
+    #[display("invalid alpha search: {_0}")]
+    AlphaSearch(AlphaSearchError),
+    #[display("could not fit the readout: {_0}")]
+    Readout(ReadoutError),
+    // the alpha search picks the penalty of the ridge readout only
+    #[display("alpha search needs the ridge readout, got {_0:?}")]
+    #[from(ignore)]
+    AlphaSearchWithoutRidge(#[error(not(source))] ReadoutType),
+    // streaming training fits the readout from accumulated normal equations
+    #[display("streaming training only fits the ridge readout, got {_0:?}")]
+    #[from(ignore)]
+    UnsupportedReadout(#[error(not(source))] ReadoutType),
+    // most steps of an episode share their time stamp, so it has no rate to run the controller at
+    #[display("the training data has a zero sample period")]
+    #[from(ignore)]
+    ZeroSamplePeriod,
+    // a rollout simulator whose learner or teacher can't be scheduled
+    #[display("invalid rollout schedule: {_0}")]
+    Schedule(ScheduleError),
+}
+
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct DroneRCParameters {
     pub internal_units: usize,
@@ -32,8 +67,13 @@ pub struct DroneRCParameters {
     pub input_scaling: f64,
     pub buffer_size: usize,
     pub alpha: f64,
+    // when set, alpha is picked from this grid by cross-validation and `alpha` is ignored
+    pub alpha_search: Option<AlphaSearch>,
+    // alpha only applies to the ridge readout, alpha_search is rejected for the others
+    pub readout_type: ReadoutType,
     pub reducer_type: ReducerType,
     pub use_setpoint_repr: bool,
+    pub seed: u64, // seeds the reservoir weights
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -41,19 +81,41 @@ pub struct NonAdaptingDroneRc {
     pub esn: DroneEsn,
     pub representation: BufferedStates,
     pub reducer: Reducer,
-    // Could be changed to ElasticNetWrapper
-    pub readout: RidgeRegression,
+    #[serde(deserialize_with = "deserialize_readout")]
+    pub readout: ReadoutModel,
     pub use_setpoint_repr: bool,
+    // the seed the reservoir was generated with, 0 for controllers saved before it was recorded
+    #[serde(default)]
+    pub seed: u64,
+    // the sample period of the training data, so the controller runs at the rate it was trained
+    // at
+    #[serde(default = "default_scheduler_delta")]
+    pub scheduler_delta: Duration,
     #[serde(skip)]
     pub runtime_state: Arc<Mutex<DMatrix<f64>>>,
 }
 
+// Controllers saved before the period was recorded were trained on logs downsampled to 10 ms
+fn default_scheduler_delta() -> Duration {
+    Duration::from_millis(10)
+}
+
+fn training_period(flight_log: &FlightLog) -> Result<Duration, TrainingError> {
+    let period = flight_log
+        .sample_period()
+        .unwrap_or_else(default_scheduler_delta);
+    if period.is_zero() {
+        return Err(TrainingError::ZeroSamplePeriod);
+    }
+    Ok(period)
+}
+
 impl NonAdaptingDroneRc {
     pub fn train_new(
         train_data: &[FlightLog],
         motor_inputs: DMatrix<f64>,
         parameters: DroneRCParameters,
-    ) -> Self {
+    ) -> Result<Self, TrainingError> {
         let DroneRCParameters {
             internal_units,
             connectivity,
@@ -61,10 +123,41 @@ impl NonAdaptingDroneRc {
             input_scaling,
             buffer_size,
             alpha,
+            alpha_search,
+            readout_type,
             reducer_type,
             use_setpoint_repr,
+            seed,
         } = parameters;
-        let esn = DroneEsn::new(internal_units, connectivity, spectral_radius, input_scaling);
+        // empty episodes have no samples, but the reservoir steps all episodes side by side
+        let non_empty: Vec<FlightLog>;
+        let train_data = if train_data.iter().any(|fl| fl.steps.is_empty()) {
+            non_empty = train_data
+                .iter()
+                .filter(|fl| !fl.steps.is_empty())
+                .cloned()
+                .collect();
+            &non_empty
+        } else {
+            train_data
+        };
+        if train_data.is_empty() {
+            return Err(TrainingError::NoTrainingData);
+        }
+        if let Some(search) = &alpha_search {
+            if readout_type != ReadoutType::Ridge {
+                return Err(TrainingError::AlphaSearchWithoutRidge(readout_type));
+            }
+            search.folds(train_data.len())?;
+        }
+        let scheduler_delta = training_period(&train_data[0])?;
+        let esn = DroneEsn::new(
+            internal_units,
+            connectivity,
+            spectral_radius,
+            input_scaling,
+            seed,
+        );
         let representation = BufferedStates::new(buffer_size);
         let multi_reservoir_inputs = MultipleReservoirInputTrajectory::from_flight_logs(train_data);
         let res_states = esn.compute_state_matricies(multi_reservoir_inputs);
@@ -78,15 +171,160 @@ impl NonAdaptingDroneRc {
             input_repr = hstack(input_repr, setpoints_repr);
         }
         // TODO: readd this!
-        let readout = RidgeRegression::fit_multiple_svd(alpha, input_repr, &motor_inputs);
-        Self {
+        let mut readout = readout_type.untrained(alpha);
+        match (&mut readout, alpha_search) {
+            (ReadoutModel::Ridge(ridge), Some(search)) => {
+                let episode_lengths: Vec<_> = train_data.iter().map(|fl| fl.steps.len()).collect();
+                *ridge = RidgeRegression::fit_multiple_svd_search(
+                    &search,
+                    input_repr,
+                    &motor_inputs,
+                    &episode_lengths,
+                )?;
+            }
+            _ => readout.fit(input_repr, &motor_inputs)?,
+        }
+        Ok(Self {
             esn,
             representation,
             readout,
             reducer,
             use_setpoint_repr,
+            seed,
+            scheduler_delta,
             runtime_state: Arc::new(Mutex::new(DMatrix::zeros(1, internal_units))),
+        })
+    }
+
+    // Same model as `train_new`, but one episode is in memory at a time and the readout is fit
+    // from accumulated normal equations. `episodes` is called once per pass over the data, twice
+    // for a PCA reducer (covariance first, then the readout). Only the ridge readout can be fit
+    // this way.
+    pub fn train_streaming<E, I>(
+        mut episodes: impl FnMut() -> I,
+        parameters: DroneRCParameters,
+    ) -> Result<Self, E>
+    where
+        I: IntoIterator<Item = Result<FlightLog, E>>,
+        E: From<TrainingError>,
+    {
+        if parameters.readout_type != ReadoutType::Ridge {
+            return Err(TrainingError::UnsupportedReadout(parameters.readout_type).into());
         }
+        let DroneRCParameters {
+            internal_units,
+            connectivity,
+            spectral_radius,
+            input_scaling,
+            buffer_size,
+            alpha,
+            alpha_search,
+            readout_type: _,
+            reducer_type,
+            use_setpoint_repr,
+            seed,
+        } = parameters;
+        if let Some(search) = &alpha_search {
+            search.validate().map_err(TrainingError::from)?;
+        }
+        let esn = DroneEsn::new(
+            internal_units,
+            connectivity,
+            spectral_radius,
+            input_scaling,
+            seed,
+        );
+        let representation = BufferedStates::new(buffer_size);
+        let state_repr = |flight_log: &FlightLog| {
+            let inputs =
+                MultipleReservoirInputTrajectory::from_flight_logs(slice::from_ref(flight_log));
+            representation.repr2(esn.compute_state_matricies(inputs))
+        };
+        let state_features = internal_units * (buffer_size + 1);
+
+        let reducer = match reducer_type {
+            ReducerType::PCA(max_components) => {
+                let mut covariance = RidgeAccumulator::new(state_features, 0);
+                for flight_log in episodes() {
+                    let flight_log = flight_log?;
+                    if flight_log.steps.is_empty() {
+                        continue;
+                    }
+                    let repr = state_repr(&flight_log);
+                    covariance.add_batch(&repr, &DMatrix::zeros(repr.nrows(), 0));
+                }
+                Reducer::pca_reducer_from_covariance(
+                    covariance.x_mean().clone(),
+                    covariance.x_covariance(),
+                    max_components,
+                )
+            }
+            ReducerType::Null => Reducer::null_reducer(),
+        };
+
+        // k-fold keeps one accumulator per fold, the episodes are dealt round robin into them
+        let folds = match alpha_search.map(|search| search.method) {
+            Some(AlphaSelectionMethod::KFold(folds)) => folds,
+            _ => 1,
+        };
+        let mut accumulators: Vec<RidgeAccumulator> = vec![];
+        let mut episode = 0;
+        let mut scheduler_delta = None;
+        for flight_log in episodes() {
+            let flight_log = flight_log?;
+            if flight_log.steps.is_empty() {
+                continue;
+            }
+            if scheduler_delta.is_none() {
+                scheduler_delta = Some(training_period(&flight_log)?);
+            }
+            let mut input_repr = reducer.transform(state_repr(&flight_log));
+            if use_setpoint_repr {
+                let setpoints_repr = setpoints_from_flight_logs(slice::from_ref(&flight_log));
+                input_repr = hstack(input_repr, setpoints_repr);
+            }
+            let motor_inputs = DMatrix::from_rows(
+                &flight_log
+                    .steps
+                    .iter()
+                    .map(|step| DVector::from_row_slice(&step.motor_input.input).transpose())
+                    .collect::<Vec<_>>(),
+            );
+            if accumulators.len() < folds {
+                accumulators.push(RidgeAccumulator::new(input_repr.ncols(), 4));
+            }
+            accumulators[episode % folds].add_batch(&input_repr, &motor_inputs);
+            episode += 1;
+        }
+        let Some(scheduler_delta) = scheduler_delta else {
+            return Err(TrainingError::NoTrainingData.into());
+        };
+        let curve = alpha_search
+            .map(|search| -> Result<_, AlphaSearchError> {
+                let alphas = search.alphas()?;
+                match search.folds(episode)? {
+                    Some(_) => RidgeAccumulator::k_fold_curve(&accumulators, &alphas),
+                    None => accumulators[0].gcv_curve(&alphas),
+                }
+            })
+            .transpose()
+            .map_err(TrainingError::from)?;
+        let mut all = accumulators.pop().unwrap();
+        for accumulator in accumulators {
+            all.merge(accumulator);
+        }
+        let mut readout = all.solve(curve.as_ref().map_or(alpha, |curve| curve.best_alpha));
+        readout.selection = curve;
+        Ok(Self {
+            esn,
+            representation,
+            readout: ReadoutModel::Ridge(readout),
+            reducer,
+            use_setpoint_repr,
+            seed,
+            scheduler_delta,
+            runtime_state: Arc::new(Mutex::new(DMatrix::zeros(1, internal_units))),
+        })
     }
 }
 
@@ -118,6 +356,6 @@ impl FlightController for NonAdaptingDroneRc {
     }
 
     fn scheduler_delta(&self) -> Duration {
-        Duration::from_millis(10)
+        self.scheduler_delta
     }
 }
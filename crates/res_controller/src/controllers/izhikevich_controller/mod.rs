@@ -2,7 +2,7 @@ use crate::input_mapping::ReservoirInput;
 use flight_controller::{Channels, FlightController, FlightControllerUpdate, MotorInput};
 use nalgebra::{DMatrix, DVector};
 use res::izhikevich::{IzhikevichInput, IzhikevichReservoir};
-use ridge::RidgeRegression;
+use ridge::{Readout, ReadoutModel, readout::deserialize_readout};
 use serde::{Deserialize, Serialize};
 use std::{sync::Mutex, time::Duration};
 
@@ -14,10 +14,14 @@ pub struct IzhikevichController {
     // let alpha = (-dt_ms / tau_ms).exp();
     pub spike_trace_decay_factor: f64,
     pub w_in: DMatrix<f64>,
-    pub readout: RidgeRegression,
+    #[serde(deserialize_with = "deserialize_readout")]
+    pub readout: ReadoutModel,
     pub scheduler_delta: Duration,
     pub network_delta: Duration,
     pub g_in: f64,
+    // the seed the reservoir and input weights were generated with
+    #[serde(default)]
+    pub seed: u64,
 }
 
 impl IzhikevichController {
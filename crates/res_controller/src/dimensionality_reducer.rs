@@ -1,4 +1,4 @@
-use nalgebra::{DMatrix, DVector, SVD};
+use nalgebra::{DMatrix, DVector, SVD, SymmetricEigen};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -48,6 +48,39 @@ impl PCADimensionalityReducer {
         }
     }
 
+    // Same components as the SVD fit, from the covariance of the data instead of the data
+    pub fn from_covariance(
+        mean: DVector<f64>,
+        covariance: DMatrix<f64>,
+        max_components: usize,
+    ) -> Self {
+        let features = mean.len();
+        if features == 0 {
+            return Self {
+                mean,
+                components: DMatrix::zeros(0, 0),
+                max_components,
+            };
+        }
+        let SymmetricEigen {
+            eigenvectors,
+            eigenvalues,
+        } = SymmetricEigen::new(covariance);
+        let mut order: Vec<usize> = (0..features).collect();
+        order.sort_by(|a, b| eigenvalues[*b].total_cmp(&eigenvalues[*a]));
+
+        let k = max_components.min(features);
+        let columns: Vec<_> = order[..k]
+            .iter()
+            .map(|i| eigenvectors.column(*i).into_owned())
+            .collect();
+        Self {
+            mean,
+            components: DMatrix::from_columns(&columns),
+            max_components,
+        }
+    }
+
     pub fn transform(&self, input: DMatrix<f64>) -> DMatrix<f64> {
         let (samples, features) = input.shape();
         if samples == 0 || features == 0 {
@@ -115,6 +148,18 @@ impl Reducer {
         ))
     }
 
+    pub fn pca_reducer_from_covariance(
+        mean: DVector<f64>,
+        covariance: DMatrix<f64>,
+        max_components: usize,
+    ) -> Self {
+        Self::PCAReducer(PCADimensionalityReducer::from_covariance(
+            mean,
+            covariance,
+            max_components,
+        ))
+    }
+
     pub fn null_reducer() -> Self {
         Self::NullReducer(NullReducer)
     }
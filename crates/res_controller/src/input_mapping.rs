@@ -98,6 +98,11 @@ impl ReservoirInput {
         }
     }
 
+    // target minus measured rate for roll, pitch and yaw
+    pub fn rate_errors(&self) -> [f64; 3] {
+        [self.roll_err, self.pitch_err, self.yaw_err]
+    }
+
     pub fn to_vector(&self) -> DVector<f64> {
         DVector::from_row_slice(&[
             self.throttle,
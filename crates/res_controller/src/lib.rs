@@ -1,4 +1,4 @@
-pub mod bf_rates;
+pub use flight_controller::bf_rates;
 pub mod controllers;
 pub mod dimensionality_reducer;
 pub mod input;
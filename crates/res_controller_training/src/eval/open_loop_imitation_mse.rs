@@ -1,6 +1,6 @@
 use flight_controller::FlightController;
 use flight_controller::FlightControllerUpdate;
-use loaders::FlDataSet;
+use loaders::{FlDataSet, Split};
 use loggers::FlightLog;
 use std::time::Duration;
 
@@ -46,9 +46,10 @@ pub fn evaluate_open_loop_fl_mse<C: FlightController>(
 pub fn evaluate_open_loop_dataset_mse<C: FlightController>(
     controller: &C,
     dataset: &FlDataSet,
+    split: Split,
 ) -> OpenLoopEvaluationResult {
     let episode_mses = dataset
-        .test_data
+        .episodes(split)
         .iter()
         .map(|flight_log| {
             let simulation_id = flight_log.simulation_id.clone();
@@ -1,3 +1,6 @@
 pub mod eval;
 pub mod train;
 pub mod utils;
+
+#[cfg(test)]
+mod test_utils;
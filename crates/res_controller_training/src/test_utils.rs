@@ -0,0 +1,129 @@
+// Fixtures shared by the training tests: smooth stick inputs, cheap teachers and flights with them
+use drone::default_drone::default_7in_4s_drone;
+use flight_controller::{
+    BatteryUpdate, Channels, FlightController, FlightControllerUpdate, GyroUpdate, MotorInput,
+};
+use loggers::{FlightLog, SnapShot, memory_logger::MemoryLogger};
+use res_controller::{controllers::esn::adapting::rate_error_mixer, input_mapping::ReservoirInput};
+use simulator::Simulator;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Motor outputs that only depend on the sticks, so the reservoir can represent them
+pub struct StickTeacher;
+
+impl FlightController for StickTeacher {
+    fn init(&self) {}
+    fn update(&self, _: f64, update: FlightControllerUpdate) -> MotorInput {
+        let Channels { throttle, roll, .. } = update.channels;
+        MotorInput {
+            input: [
+                0.4 + 0.2 * throttle,
+                0.4 + 0.1 * roll,
+                0.4 - 0.1 * roll,
+                0.4 + 0.2 * throttle,
+            ],
+        }
+    }
+    fn scheduler_delta(&self) -> Duration {
+        Duration::from_millis(10)
+    }
+}
+
+// Proportional rate controller, a cheap stand-in for Betaflight
+pub struct RateTeacher {
+    pub period: Duration,
+}
+
+impl Default for RateTeacher {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(10),
+        }
+    }
+}
+
+impl FlightController for RateTeacher {
+    fn init(&self) {}
+    fn update(&self, _: f64, update: FlightControllerUpdate) -> MotorInput {
+        let errors = ReservoirInput::from_flight_controller_update(update).rate_errors();
+        let throttle = 0.3 + 0.1 * update.channels.throttle;
+        MotorInput {
+            input: rate_error_mixer().map(|mix| {
+                let correction: f64 = (0..3).map(|axis| 0.02 * mix[axis] * errors[axis]).sum();
+                (throttle + correction).clamp(0., 1.)
+            }),
+        }
+    }
+    fn scheduler_delta(&self) -> Duration {
+        self.period
+    }
+}
+
+// Sines on every axis, one input per `step`. `phase` (in seconds) shifts them so that episodes
+// differ.
+pub fn sticks(steps: usize, step: Duration, phase: f64) -> Vec<Channels> {
+    (0..steps)
+        .map(|i| {
+            let t = i as f64 * step.as_secs_f64() + phase;
+            Channels {
+                throttle: (1.3 * t).sin(),
+                roll: 0.5 * (2.1 * t).cos(),
+                pitch: 0.5 * (1.7 * t).sin(),
+                yaw: 0.3 * t.cos(),
+            }
+        })
+        .collect()
+}
+
+// Flies the default drone with `controller`, one stick input per `step`
+pub fn fly(
+    controller: Arc<dyn FlightController>,
+    sticks: &[Channels],
+    step: Duration,
+    simulation_id: &str,
+) -> FlightLog {
+    let logger = Arc::new(Mutex::new(MemoryLogger::new(simulation_id.into())));
+    let mut simulation = Simulator::with_drone_and_controller_logger(
+        default_7in_4s_drone(),
+        controller,
+        logger.clone(),
+    );
+    simulation.init().unwrap();
+    for channels in sticks {
+        simulation.simulate_delta(step, *channels);
+    }
+    let steps = std::mem::take(&mut logger.lock().unwrap().snapshots);
+    FlightLog::new(simulation_id.into(), steps)
+}
+
+// 2 s sampled at 10 ms without a simulation: the gyro follows the sticks and the motor inputs
+// are the `StickTeacher`'s
+pub fn synthetic_flight_log(simulation_id: &str, phase: f64) -> FlightLog {
+    let step = Duration::from_millis(10);
+    let steps = sticks(200, step, phase)
+        .into_iter()
+        .enumerate()
+        .map(|(i, channels)| {
+            let gyro_update = GyroUpdate {
+                angular_velocity: [channels.roll, channels.pitch, channels.yaw],
+                ..Default::default()
+            };
+            let update = FlightControllerUpdate {
+                battery_update: BatteryUpdate::default(),
+                gyro_update,
+                channels,
+            };
+            SnapShot::new(
+                step * i as u32,
+                StickTeacher.update(0., update),
+                BatteryUpdate::default(),
+                gyro_update,
+                channels,
+            )
+        })
+        .collect();
+    FlightLog::new(simulation_id.into(), steps)
+}
@@ -0,0 +1,188 @@
+use drone::Drone;
+use flight_controller::Channels;
+use loggers::{FlightLog, memory_logger::MemoryLogger};
+use res_controller::controllers::esn::adapting::AdaptingDroneRc;
+use simulator::Simulator;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Flies the adaptive controller in closed loop, one stick input per millisecond, learning until
+// `freeze_after` (or the whole flight). The controller keeps what it learned, so it can be flown
+// again or turned into a fixed controller with `to_non_adapting`.
+pub fn fly_adaptive(
+    drone: Drone,
+    controller: Arc<AdaptingDroneRc>,
+    inputs: &[Channels],
+    freeze_after: Option<Duration>,
+    simulation_id: &str,
+) -> FlightLog {
+    let logger = Arc::new(Mutex::new(MemoryLogger::new(simulation_id.into())));
+    let mut simulation =
+        Simulator::with_drone_and_controller_logger(drone, controller.clone(), logger.clone());
+    simulation.init().unwrap();
+    controller.unfreeze();
+    let step = Duration::from_millis(1);
+    for (i, input) in inputs.iter().enumerate() {
+        if freeze_after.is_some_and(|freeze_after| step * i as u32 >= freeze_after) {
+            controller.freeze();
+        }
+        simulation.simulate_delta(step, *input);
+    }
+    let steps = std::mem::take(&mut logger.lock().unwrap().snapshots);
+    FlightLog::new(simulation_id.into(), steps)
+}
+
+#[cfg(test)]
+mod test {
+    use super::fly_adaptive;
+    use crate::test_utils::{RateTeacher, StickTeacher, fly, sticks};
+    use drone::default_drone::default_7in_4s_drone;
+    use flight_controller::{
+        FlightController, FlightControllerUpdate, controllers::null_controller::NullController,
+    };
+    use loaders::FlDataSet;
+    use loggers::FlightLog;
+    use res_controller::{
+        controllers::esn::{
+            DroneRCParameters,
+            adapting::{AdaptationSignal, AdaptingDroneRc, RlsParameters},
+        },
+        dimensionality_reducer::ReducerType,
+    };
+    use ridge::ReadoutType;
+    use std::{sync::Arc, time::Duration};
+
+    const STEP: Duration = Duration::from_millis(1);
+
+    // A reservoir trained on a null controller flight, whose readout is far from the teacher
+    fn untrained_controller(teacher: Arc<dyn FlightController>) -> AdaptingDroneRc {
+        let mut flight_log = fly(
+            Arc::new(NullController::default()),
+            &sticks(500, STEP, 0.),
+            STEP,
+            "null",
+        );
+        flight_log.downsample(Duration::from_millis(10));
+        let dataset = FlDataSet {
+            train_data: vec![flight_log],
+            ..Default::default()
+        };
+        let parameters = DroneRCParameters {
+            internal_units: 30,
+            connectivity: 0.3,
+            spectral_radius: 0.9,
+            input_scaling: 0.5,
+            buffer_size: 0,
+            alpha: 1.,
+            alpha_search: None,
+            readout_type: ReadoutType::Ridge,
+            reducer_type: ReducerType::Null,
+            use_setpoint_repr: true,
+            seed: 3,
+        };
+        AdaptingDroneRc::new(
+            crate::train::train_on_dataset(&dataset, parameters).unwrap(),
+            AdaptationSignal::Teacher(teacher),
+            RlsParameters::default(),
+        )
+    }
+
+    // Against the teacher's output for the same update, the one it holds until the learner ticks
+    fn imitation_mse(
+        flight_log: &FlightLog,
+        teacher: &dyn FlightController,
+        steps: std::ops::Range<usize>,
+    ) -> f64 {
+        let errors: Vec<f64> = flight_log.steps[steps]
+            .iter()
+            .map(|step| {
+                let update = FlightControllerUpdate {
+                    battery_update: step.battery_update,
+                    gyro_update: step.gyro_update,
+                    channels: step.channels,
+                };
+                let target = teacher.update(0., update);
+                (0..4)
+                    .map(|i| (step.motor_input[i] - target[i]).powi(2))
+                    .sum::<f64>()
+                    / 4.
+            })
+            .collect();
+        errors.iter().sum::<f64>() / errors.len() as f64
+    }
+
+    #[test]
+    fn learns_the_teacher_online_and_freezes() {
+        let controller = Arc::new(untrained_controller(Arc::new(StickTeacher)));
+        let inputs = sticks(4000, STEP, 0.);
+        let flight_log = fly_adaptive(
+            default_7in_4s_drone(),
+            controller.clone(),
+            &inputs,
+            Some(Duration::from_secs(3)),
+            "adaptive",
+        );
+        // 4 s of flight, frozen after 3 s
+        let steps = flight_log.steps.len();
+        let early = imitation_mse(&flight_log, &StickTeacher, 0..steps * 3 / 40);
+        let late = imitation_mse(&flight_log, &StickTeacher, steps * 27 / 40..steps * 3 / 4);
+        // the readout starts far from the teacher and tracks it closely before freezing
+        assert!(early > 1e-3, "{early}");
+        assert!(late < 1e-4, "{late}");
+        assert!(late < early / 100., "{early} -> {late}");
+        assert!(!controller.is_learning());
+
+        // frozen weights don't change in flight, and the fixed controller matches them
+        let updates = controller.updates();
+        controller.init();
+        let update = FlightControllerUpdate::default();
+        let adapted = controller.update(0., update);
+        assert_eq!(controller.updates(), updates);
+        let fixed = controller.to_non_adapting();
+        fixed.init();
+        let expected = fixed.update(0., update);
+        for i in 0..4 {
+            assert!((adapted[i] - expected[i]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn learns_the_teacher_at_its_own_rate() {
+        // a slower teacher: the steps at 10 and 20 ms come before its first tick at 25 ms and
+        // don't learn, the steps at 30 and 40 ms learn its output of the 25 ms tick
+        let slow = RateTeacher {
+            period: Duration::from_millis(25),
+        };
+        let controller = untrained_controller(Arc::new(slow));
+        controller.init();
+        let step = controller.scheduler_delta().as_secs_f64();
+        let update = FlightControllerUpdate::default();
+        controller.update(step, update);
+        controller.update(step, update);
+        assert_eq!(controller.updates(), 0);
+        controller.update(step, update);
+        controller.update(step, update);
+        assert_eq!(controller.updates(), 2);
+
+        // a teacher 10 times faster than the reservoir is learned from its held output
+        let fast = RateTeacher {
+            period: Duration::from_millis(1),
+        };
+        let controller = Arc::new(untrained_controller(Arc::new(RateTeacher {
+            period: fast.period,
+        })));
+        let flight_log = fly_adaptive(
+            default_7in_4s_drone(),
+            controller.clone(),
+            &sticks(4000, STEP, 0.),
+            None,
+            "adaptive_fast_teacher",
+        );
+        let steps = flight_log.steps.len();
+        let early = imitation_mse(&flight_log, &fast, 0..steps / 10);
+        let late = imitation_mse(&flight_log, &fast, steps * 3 / 4..steps);
+        assert!(late < early / 10., "{early} -> {late}");
+    }
+}
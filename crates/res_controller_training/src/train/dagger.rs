@@ -0,0 +1,318 @@
+use crate::{
+    eval::angular_rate_stabilization::angular_rate_stabilization_test, train::train_on_dataset,
+};
+use drone::Drone;
+use flight_controller::{Channels, FlightController, FlightControllerUpdate};
+use loaders::{AngularRateStabilizationDb, FlDataSet};
+use loggers::{FlightLog, memory_logger::MemoryLogger};
+use res_controller::{
+    controllers::esn::{DroneRCParameters, NonAdaptingDroneRc, TrainingError},
+    input_mapping::ReservoirInput,
+};
+use serde::Serialize;
+use simulator::Simulator;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct DaggerParameters {
+    // Retrain rounds after the initial training on the teacher logs
+    pub iterations: usize,
+    // Training episodes whose stick inputs the learner flies per iteration, cycling through them
+    pub rollouts_per_iteration: usize,
+    // Also run the angular rate stabilization test each iteration (slow)
+    pub stabilization_db: Option<AngularRateStabilizationDb>,
+}
+
+// Closed-loop metrics of the controller trained in one iteration, measured on its own rollouts
+#[derive(Debug, Clone, Serialize)]
+pub struct DaggerIteration {
+    pub iteration: usize,
+    // Episodes the controller was trained on
+    pub train_episodes: usize,
+    // Controller against teacher motor inputs on the states the controller flew into
+    pub closed_loop_imitation_mse: f64,
+    // Setpoint minus measured angular rate, over the three axes
+    pub rate_tracking_mse: f64,
+    pub stabilization_rate: Option<f64>,
+}
+
+struct Rollout {
+    flight_log: FlightLog,
+    imitation_squared_error: f64,
+    rate_squared_error: f64,
+}
+
+// The learner flies while the teacher shadows it, the teacher's motor inputs become the labels of
+// the visited states. One stick input per learner tick, like the downsampled teacher logs. The
+// teacher ticks at its own rate and each learner tick is labelled with its last motor input, so
+// a faster teacher's labels are downsampled to the learner's rate. Ticks before the first teacher
+// tick have no label and are dropped. The in-memory training batches the episodes, so the rollout
+// has as many steps as the episode it replays.
+fn fly_rollout(
+    drone: Drone,
+    learner: Arc<dyn FlightController>,
+    teacher: Arc<dyn FlightController>,
+    sticks: &[Channels],
+    simulation_id: String,
+) -> Result<Rollout, TrainingError> {
+    let delta = learner.scheduler_delta();
+    let logger = Arc::new(Mutex::new(MemoryLogger::new(simulation_id.clone())));
+    let mut simulator = Simulator::with_drone_and_controller_logger(drone, learner, logger.clone())
+        .with_shadow_controller(teacher);
+    simulator.log_ground_truth = false;
+    simulator.init()?;
+    let (mut tick, mut logged, mut labelled) = (0, 0, 0);
+    while labelled < sticks.len() {
+        simulator.simulate_delta(delta, sticks[tick.min(sticks.len() - 1)]);
+        tick += 1;
+        // only count the snapshots of this tick
+        let snapshots = &logger.lock().unwrap().snapshots;
+        labelled += snapshots[logged..]
+            .iter()
+            .filter(|step| step.shadow_motor_input.is_some())
+            .count();
+        logged = snapshots.len();
+    }
+
+    let mut steps = std::mem::take(&mut logger.lock().unwrap().snapshots);
+    steps.retain(|step| step.shadow_motor_input.is_some());
+    steps.truncate(sticks.len());
+    let mut imitation_squared_error = 0.;
+    let mut rate_squared_error = 0.;
+    for step in steps.iter_mut() {
+        let label = step.shadow_motor_input.take().unwrap();
+        imitation_squared_error += (0..4)
+            .map(|i| (step.motor_input[i] - label[i]).powi(2))
+            .sum::<f64>();
+        step.motor_input = label;
+        let update = FlightControllerUpdate {
+            battery_update: step.battery_update,
+            gyro_update: step.gyro_update,
+            channels: step.channels,
+        };
+        rate_squared_error += ReservoirInput::from_flight_controller_update(update)
+            .rate_errors()
+            .iter()
+            .map(|e| e * e)
+            .sum::<f64>();
+    }
+    Ok(Rollout {
+        flight_log: FlightLog::new(simulation_id, steps),
+        imitation_squared_error,
+        rate_squared_error,
+    })
+}
+
+// Dataset aggregation (DAgger): the controller trained on the teacher logs flies the stick inputs
+// of the training episodes itself, the teacher labels every state it visited, the labelled
+// rollouts are added to the training split of `dataset` and the controller is retrained. Expects
+// the data set downsampled to the controller rate, empty episodes are not flown. Returns the final
+// controller and the metrics of every iteration, the last entry being the returned controller.
+pub fn train_dagger(
+    drone: &Drone,
+    teacher: Arc<dyn FlightController>,
+    dataset: &mut FlDataSet,
+    training_parameters: DroneRCParameters,
+    dagger_parameters: &DaggerParameters,
+) -> Result<(Arc<NonAdaptingDroneRc>, Vec<DaggerIteration>), TrainingError> {
+    let sticks: Vec<Vec<Channels>> = dataset
+        .train_data
+        .iter()
+        .filter(|flight_log| !flight_log.steps.is_empty())
+        .map(|flight_log| flight_log.steps.iter().map(|s| s.channels).collect())
+        .collect();
+    if sticks.is_empty() {
+        return Err(TrainingError::NoTrainingData);
+    }
+
+    let mut controller = Arc::new(train_on_dataset(dataset, training_parameters)?);
+    let mut iterations = vec![];
+    for iteration in 0..=dagger_parameters.iterations {
+        let rollouts = (0..dagger_parameters.rollouts_per_iteration)
+            .map(|i| {
+                let episode =
+                    (iteration * dagger_parameters.rollouts_per_iteration + i) % sticks.len();
+                fly_rollout(
+                    drone.clone(),
+                    controller.clone(),
+                    teacher.clone(),
+                    &sticks[episode],
+                    format!("{}_dagger_{iteration}_{i}", dataset.dataset_id),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let samples = rollouts
+            .iter()
+            .map(|r| r.flight_log.steps.len())
+            .sum::<usize>()
+            .max(1) as f64;
+        let stabilization_rate = dagger_parameters.stabilization_db.clone().map(|db| {
+            angular_rate_stabilization_test(
+                drone.clone(),
+                controller.clone(),
+                db,
+                &format!("dagger_{iteration}"),
+            )
+        });
+        let metrics = DaggerIteration {
+            iteration,
+            train_episodes: dataset.train_data.len(),
+            closed_loop_imitation_mse: rollouts
+                .iter()
+                .map(|r| r.imitation_squared_error)
+                .sum::<f64>()
+                / (samples * 4.),
+            rate_tracking_mse: rollouts.iter().map(|r| r.rate_squared_error).sum::<f64>()
+                / (samples * 3.),
+            stabilization_rate,
+        };
+        iterations.push(metrics);
+
+        if iteration == dagger_parameters.iterations {
+            break;
+        }
+        dataset
+            .train_data
+            .extend(rollouts.into_iter().map(|r| r.flight_log));
+        controller = Arc::new(train_on_dataset(dataset, training_parameters)?);
+    }
+    Ok((controller, iterations))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DaggerParameters, fly_rollout, train_dagger};
+    use crate::test_utils::{RateTeacher, fly, sticks};
+    use drone::default_drone::default_7in_4s_drone;
+    use loaders::FlDataSet;
+    use loggers::FlightLog;
+    use res_controller::{
+        controllers::esn::{DroneRCParameters, TrainingError},
+        dimensionality_reducer::ReducerType,
+    };
+    use ridge::ReadoutType;
+    use simulator::schedule::ScheduleError;
+    use std::{sync::Arc, time::Duration};
+
+    fn teacher_log(phase: f64) -> FlightLog {
+        let step = Duration::from_millis(10);
+        fly(
+            Arc::new(RateTeacher::default()),
+            &sticks(200, step, phase),
+            step,
+            &format!("teacher_{phase}"),
+        )
+    }
+
+    #[test]
+    fn dagger_aggregates_rollouts_and_reports_each_iteration() {
+        let mut dataset = FlDataSet {
+            dataset_id: "dagger".into(),
+            train_data: vec![teacher_log(0.), teacher_log(3.)],
+            ..Default::default()
+        };
+        let parameters = DroneRCParameters {
+            internal_units: 40,
+            connectivity: 0.3,
+            spectral_radius: 0.9,
+            input_scaling: 0.3,
+            buffer_size: 0,
+            alpha: 1e-2,
+            alpha_search: None,
+            readout_type: ReadoutType::Ridge,
+            reducer_type: ReducerType::Null,
+            use_setpoint_repr: true,
+            seed: 5,
+        };
+        let dagger_parameters = DaggerParameters {
+            iterations: 2,
+            rollouts_per_iteration: 2,
+            stabilization_db: None,
+        };
+        let mut empty = FlDataSet {
+            train_data: vec![FlightLog::new("empty".into(), vec![])],
+            ..Default::default()
+        };
+        assert!(matches!(
+            train_dagger(
+                &default_7in_4s_drone(),
+                Arc::new(RateTeacher::default()),
+                &mut empty,
+                parameters,
+                &dagger_parameters,
+            ),
+            Err(TrainingError::NoTrainingData)
+        ));
+
+        let (_, iterations) = train_dagger(
+            &default_7in_4s_drone(),
+            Arc::new(RateTeacher::default()),
+            &mut dataset,
+            parameters,
+            &dagger_parameters,
+        )
+        .unwrap();
+
+        assert_eq!(iterations.len(), 3);
+        assert_eq!(
+            iterations
+                .iter()
+                .map(|i| i.train_episodes)
+                .collect::<Vec<_>>(),
+            [2, 4, 6]
+        );
+        assert_eq!(dataset.train_data.len(), 6);
+        assert!(
+            dataset.train_data[2..]
+                .iter()
+                .all(|flight_log| flight_log.steps.len() > 150)
+        );
+        for iteration in &iterations {
+            assert!(iteration.closed_loop_imitation_mse.is_finite());
+            assert!(iteration.rate_tracking_mse.is_finite());
+        }
+        // training on the states it visits brings the controller closer to the teacher there
+        let first = iterations[0].closed_loop_imitation_mse;
+        let last = iterations[2].closed_loop_imitation_mse;
+        assert!(last < first, "{first} -> {last}");
+    }
+
+    #[test]
+    fn rollouts_are_labelled_by_the_teacher_at_its_own_rate() {
+        let step = Duration::from_millis(10);
+        let sticks = sticks(100, step, 0.);
+        let teacher = RateTeacher {
+            period: Duration::from_millis(25),
+        };
+        let rollout = fly_rollout(
+            default_7in_4s_drone(),
+            Arc::new(RateTeacher::default()),
+            Arc::new(teacher),
+            &sticks,
+            "rollout".into(),
+        )
+        .unwrap();
+        let steps = &rollout.flight_log.steps;
+        assert_eq!(steps.len(), sticks.len());
+        // the ticks at 10 and 20 ms come before the first teacher tick at 25 ms
+        assert_eq!(steps[0].duration, 3 * step);
+        // the label of the 25 ms tick is held for the learner ticks at 30 and 40 ms
+        assert_eq!(steps[0].motor_input.input, steps[1].motor_input.input);
+        assert!(steps.iter().all(|step| step.shadow_motor_input.is_none()));
+
+        // a teacher without a period can't be scheduled
+        let unscheduled = RateTeacher {
+            period: Duration::ZERO,
+        };
+        assert!(matches!(
+            fly_rollout(
+                default_7in_4s_drone(),
+                Arc::new(RateTeacher::default()),
+                Arc::new(unscheduled),
+                &sticks,
+                "unscheduled".into(),
+            ),
+            Err(TrainingError::Schedule(ScheduleError::ZeroShadowPeriod))
+        ));
+    }
+}
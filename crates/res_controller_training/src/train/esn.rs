@@ -2,22 +2,42 @@ use crate::{
     eval::open_loop_imitation_mse::{OpenLoopEvaluationResult, evaluate_open_loop_dataset_mse},
     train::train_on_dataset,
 };
+use loaders::RESERVOIR_KIND;
 use res_controller::controllers::esn::DroneRCParameters;
 use sim_context::SimContext;
 use std::time::Duration;
 
+// Evaluates on the validation set when the data set has one, so sweeps don't tune on the test set
 pub fn train_and_evaluate_open_loop_imitation_mse(
     sim_context: &mut SimContext,
     dataset_id: &str,
     training_parameters: DroneRCParameters,
 ) -> (OpenLoopEvaluationResult, DroneRCParameters) {
-    let mut fl_data_set = sim_context.loader.lock().unwrap().load_data_set(dataset_id);
+    let mut fl_data_set = sim_context
+        .loader
+        .lock()
+        .unwrap()
+        .load_data_set(dataset_id)
+        .unwrap();
     fl_data_set.downsample(Duration::from_millis(10));
 
     let params_for_log = format!("{training_parameters:?}");
 
-    let drone_rc = train_on_dataset(&fl_data_set, training_parameters);
-    let evaluation_result = evaluate_open_loop_dataset_mse(&drone_rc, &fl_data_set);
-    sim_context.insert_drone_rc(&params_for_log, drone_rc);
+    let drone_rc = train_on_dataset(&fl_data_set, training_parameters).unwrap();
+    let split = fl_data_set.tuning_split();
+    let evaluation_result = evaluate_open_loop_dataset_mse(&drone_rc, &fl_data_set, split);
+    let artifact = sim_context
+        .new_artifact(RESERVOIR_KIND, &drone_rc)
+        .unwrap()
+        .with_parameters(&training_parameters)
+        .unwrap()
+        .with_dataset_id(dataset_id)
+        .with_score(
+            &format!("open_loop_{split}_mean_mse"),
+            evaluation_result.mean_mse,
+        );
+    sim_context
+        .insert_artifact(&params_for_log, &artifact)
+        .unwrap();
     (evaluation_result, training_parameters)
 }
@@ -4,9 +4,10 @@ use nalgebra::{DMatrix, DVector};
 use rand::{Rng, SeedableRng, rngs::StdRng};
 use res::izhikevich::{IzhikevichHarness, IzhikevichInput, random_izhikevich};
 use res_controller::{
-    controllers::izhikevich_controller::IzhikevichController, input_mapping::ReservoirInput,
+    controllers::{esn::TrainingError, izhikevich_controller::IzhikevichController},
+    input_mapping::ReservoirInput,
 };
-use ridge::RidgeRegression;
+use ridge::{AlphaSearch, Readout, ReadoutModel, ReadoutType, RidgeRegression};
 use std::{sync::Mutex, time::Duration};
 
 pub struct IzhikevichControllerParameters {
@@ -17,12 +18,17 @@ pub struct IzhikevichControllerParameters {
     pub network_delta: Duration,
     pub g_in: f64,
     pub alpha: f64,
+    // when set, alpha is picked from this grid by cross-validation and `alpha` is ignored
+    pub alpha_search: Option<AlphaSearch>,
+    // alpha only applies to the ridge readout, alpha_search is rejected for the others
+    pub readout_type: ReadoutType,
+    pub seed: u64,
 }
 
 pub fn train_izhikevich_controller2(
     controller_params: IzhikevichControllerParameters,
     flight_logs: &[FlightLog],
-) -> IzhikevichController {
+) -> Result<IzhikevichController, TrainingError> {
     let IzhikevichControllerParameters {
         n,
         p,
@@ -31,8 +37,18 @@ pub fn train_izhikevich_controller2(
         network_delta,
         g_in,
         alpha,
+        alpha_search,
+        readout_type,
+        seed,
     } = controller_params;
-    let reservoir = random_izhikevich(n, p, excit_frac);
+    if let Some(search) = &alpha_search {
+        if readout_type != ReadoutType::Ridge {
+            return Err(TrainingError::AlphaSearchWithoutRidge(readout_type));
+        }
+        search.folds(flight_logs.len())?;
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let reservoir = random_izhikevich(n, p, excit_frac, rng.r#gen());
     let dt_ms = reservoir.dt.as_secs_f64() * 1000.0;
     let tau_ms = 20.; // NOTE: wil need to tune this
     let spike_trace_decay_factor = (-dt_ms / tau_ms).exp();
@@ -47,7 +63,6 @@ pub fn train_izhikevich_controller2(
 
     let input_dim = ReservoirInput::NVARS;
 
-    let mut rng = StdRng::seed_from_u64(1337);
     let mut w_in = DMatrix::<f64>::zeros(n, input_dim);
     for i in 0..n {
         for j in 0..input_dim {
@@ -101,9 +116,21 @@ pub fn train_izhikevich_controller2(
     }
     debug_assert_eq!(row, total_steps);
 
-    let readout = RidgeRegression::fit_multiple_svd(alpha, representation, &motor_inputs);
+    let mut readout = readout_type.untrained(alpha);
+    match (&mut readout, alpha_search) {
+        (ReadoutModel::Ridge(ridge), Some(search)) => {
+            let episode_lengths: Vec<_> = flight_logs.iter().map(|fl| fl.steps.len()).collect();
+            *ridge = RidgeRegression::fit_multiple_svd_search(
+                &search,
+                representation,
+                &motor_inputs,
+                &episode_lengths,
+            )?;
+        }
+        _ => readout.fit(representation, &motor_inputs)?,
+    }
 
-    IzhikevichController {
+    Ok(IzhikevichController {
         reservoir: Mutex::new(reservoir),
         spike_traces: Mutex::new(DVector::zeros(n)),
         spike_trace_decay_factor,
@@ -112,7 +139,8 @@ pub fn train_izhikevich_controller2(
         scheduler_delta,
         network_delta,
         g_in,
-    }
+        seed,
+    })
 }
 
 #[cfg(test)]
@@ -124,19 +152,24 @@ mod test {
         },
         train::izhikevich::{IzhikevichControllerParameters, train_izhikevich_controller2},
     };
+    use loaders::Split;
+    use ridge::ReadoutType;
     use sim_context::SimContext;
     use std::{sync::Arc, time::Duration};
 
     #[test]
     fn train_and_evaluate_izhikevich_controller() {
         let mut sim_context = SimContext::default();
-        sim_context.set_loader(&sim_context::LoaderType::File);
+        sim_context
+            .set_loader(&sim_context::LoaderType::File)
+            .unwrap();
 
         let mut fl_data_set = sim_context
             .loader
             .lock()
             .unwrap()
-            .load_data_set("intermediate_dataset");
+            .load_data_set("intermediate_dataset")
+            .unwrap();
         fl_data_set.downsample(Duration::from_millis(10));
         let params = IzhikevichControllerParameters {
             n: 512,
@@ -146,17 +179,21 @@ mod test {
             network_delta: Duration::from_millis(10),
             g_in: 5.,
             alpha: 1.,
+            alpha_search: None,
+            readout_type: ReadoutType::Ridge,
+            seed: 42,
         };
 
-        let drone = sim_context.load_drone().unwrap();
-        let controller = train_izhikevich_controller2(params, &fl_data_set.train_data);
-        let res = evaluate_open_loop_dataset_mse(&controller, &fl_data_set);
+        let drone = sim_context.load_drone().unwrap().unwrap();
+        let controller = train_izhikevich_controller2(params, &fl_data_set.train_data).unwrap();
+        let res = evaluate_open_loop_dataset_mse(&controller, &fl_data_set, Split::Test);
         println!("{:#?}", res);
         sim_context
             .loader
             .lock()
             .unwrap()
-            .insert_izhikevich_controller("initial_izhikevich_controller", &controller);
+            .insert_izhikevich_controller("initial_izhikevich_controller", &controller)
+            .unwrap();
         let db = dummy_stabilization_db();
         angular_rate_stabilization_test(drone, Arc::new(controller), db, "izh_controller");
     }
@@ -1,14 +1,345 @@
+pub mod adaptive;
+pub mod dagger;
 pub mod esn;
 pub mod izhikevich;
 
 use crate::utils::snapshots_to_motor_inputs;
-use loaders::FlDataSet;
-use res_controller::controllers::esn::{DroneRCParameters, NonAdaptingDroneRc};
+use loaders::{FlDataSet, LoaderError, LoaderTrait, Split};
+use res_controller::controllers::esn::{DroneRCParameters, NonAdaptingDroneRc, TrainingError};
+use std::{cell::RefCell, time::Duration};
 
 pub fn train_on_dataset(
     dataset: &FlDataSet,
     training_parameters: DroneRCParameters,
-) -> NonAdaptingDroneRc {
+) -> Result<NonAdaptingDroneRc, TrainingError> {
     let train_motor_input = snapshots_to_motor_inputs(&dataset.train_data);
     NonAdaptingDroneRc::train_new(&dataset.train_data, train_motor_input, training_parameters)
 }
+
+// Trains on the training split of a stored data set without loading all of it, episodes are
+// read (and downsampled) one at a time
+pub fn train_streaming_on_dataset<L: LoaderTrait + ?Sized>(
+    loader: &mut L,
+    dataset_id: &str,
+    downsample: Option<Duration>,
+    training_parameters: DroneRCParameters,
+) -> Result<NonAdaptingDroneRc, LoaderError> {
+    let index = loader.data_set_index(dataset_id)?;
+    let loader = RefCell::new(loader);
+    let episodes = || {
+        index.split(Split::Train).map(|episode| {
+            let mut flight_log = loader.borrow_mut().load_episode(dataset_id, episode)?;
+            // an empty episode has nothing to downsample, training skips it
+            if let Some(duration) = downsample.filter(|_| !flight_log.steps.is_empty()) {
+                flight_log.downsample(duration);
+            }
+            Ok(flight_log)
+        })
+    };
+    NonAdaptingDroneRc::train_streaming(episodes, training_parameters)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{train_on_dataset, train_streaming_on_dataset};
+    use crate::{
+        eval::open_loop_imitation_mse::evaluate_open_loop_fl_mse, test_utils::synthetic_flight_log,
+    };
+    use flight_controller::{FlightController, FlightControllerUpdate, MotorInput};
+    use loaders::{FlDataSet, LoaderError, LoaderTrait, file_loader::FileLoader};
+    use loggers::FlightLog;
+    use res_controller::{
+        controllers::esn::{DroneRCParameters, NonAdaptingDroneRc, TrainingError},
+        dimensionality_reducer::ReducerType,
+    };
+    use ridge::{AlphaSearch, AlphaSearchError, ReadoutType};
+    use std::{fs, time::Duration};
+
+    fn predictions(controller: &NonAdaptingDroneRc, flight_log: &FlightLog) -> Vec<MotorInput> {
+        controller.init();
+        flight_log
+            .steps
+            .iter()
+            .map(|step| {
+                controller.update(
+                    0.,
+                    FlightControllerUpdate {
+                        battery_update: step.battery_update,
+                        gyro_update: step.gyro_update,
+                        channels: step.channels,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streaming_training_and_alpha_selection_match_in_memory() {
+        let root = std::env::temp_dir().join("quad_streaming_training_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut loader = FileLoader::new(root.clone());
+        let dataset = FlDataSet {
+            dataset_id: "synthetic".into(),
+            train_data: (0..3)
+                .map(|i| synthetic_flight_log(&format!("train_{i}"), i as f64))
+                .collect(),
+            ..Default::default()
+        };
+        loader.insert_data_set(dataset.clone()).unwrap();
+
+        let parameters = DroneRCParameters {
+            internal_units: 20,
+            connectivity: 0.3,
+            spectral_radius: 0.9,
+            input_scaling: 0.5,
+            buffer_size: 1,
+            alpha: 1e-3,
+            alpha_search: None,
+            readout_type: ReadoutType::Ridge,
+            reducer_type: ReducerType::PCA(10),
+            use_setpoint_repr: true,
+            seed: 7,
+        };
+        loader
+            .insert_data_set(FlDataSet {
+                dataset_id: "empty".into(),
+                train_data: vec![FlightLog::new("empty".into(), vec![])],
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(matches!(
+            train_streaming_on_dataset(&mut loader, "empty", None, parameters),
+            Err(LoaderError::Training(TrainingError::NoTrainingData))
+        ));
+        // every step logged at the same time, so there is no rate to run the controller at
+        let mut stalled = synthetic_flight_log("stalled", 0.);
+        for step in &mut stalled.steps {
+            step.duration = Duration::ZERO;
+        }
+        let stalled = FlDataSet {
+            dataset_id: "stalled".into(),
+            train_data: vec![stalled],
+            ..Default::default()
+        };
+        loader.insert_data_set(stalled.clone()).unwrap();
+        assert!(matches!(
+            train_streaming_on_dataset(&mut loader, "stalled", None, parameters),
+            Err(LoaderError::Training(TrainingError::ZeroSamplePeriod))
+        ));
+        assert!(matches!(
+            train_on_dataset(&stalled, parameters),
+            Err(TrainingError::ZeroSamplePeriod)
+        ));
+        let kernel_ridge = DroneRCParameters {
+            readout_type: ReadoutType::KernelRidge {
+                alpha: 1e-3,
+                gamma: 0.5,
+                max_centers: 100,
+            },
+            ..parameters
+        };
+        assert!(matches!(
+            train_streaming_on_dataset(&mut loader, "synthetic", None, kernel_ridge),
+            Err(LoaderError::Training(TrainingError::UnsupportedReadout(_)))
+        ));
+        // invalid searches are rejected before any training, also a k-fold with one episode
+        for (alpha_search, expected) in [
+            (
+                AlphaSearch::k_fold(0, 1e-4, 10., 6),
+                AlphaSearchError::TooFewFolds(0),
+            ),
+            (
+                AlphaSearch::gcv(0., 10., 6),
+                AlphaSearchError::InvalidRange { min: 0., max: 10. },
+            ),
+        ] {
+            let parameters = DroneRCParameters {
+                alpha_search: Some(alpha_search),
+                ..parameters
+            };
+            assert!(matches!(
+                train_streaming_on_dataset(&mut loader, "synthetic", None, parameters),
+                Err(LoaderError::Training(TrainingError::AlphaSearch(e))) if e == expected
+            ));
+            assert!(matches!(
+                train_on_dataset(&dataset, parameters),
+                Err(TrainingError::AlphaSearch(e)) if e == expected
+            ));
+        }
+        let one_episode = FlDataSet {
+            train_data: dataset.train_data[..1].to_vec(),
+            ..dataset.clone()
+        };
+        let parameters_k_fold = DroneRCParameters {
+            alpha_search: Some(AlphaSearch::k_fold(1, 1e-4, 10., 6)),
+            ..parameters
+        };
+        assert!(matches!(
+            train_on_dataset(&one_episode, parameters_k_fold),
+            Err(TrainingError::AlphaSearch(AlphaSearchError::TooFewFolds(1)))
+        ));
+        let parameters_k_fold = DroneRCParameters {
+            alpha_search: Some(AlphaSearch::k_fold(3, 1e-4, 10., 6)),
+            ..parameters
+        };
+        assert!(matches!(
+            train_on_dataset(&one_episode, parameters_k_fold),
+            Err(TrainingError::AlphaSearch(
+                AlphaSearchError::TooFewEpisodes(1)
+            ))
+        ));
+
+        let searches = [
+            None,
+            Some(AlphaSearch::gcv(1e-4, 10., 6)),
+            Some(AlphaSearch::k_fold(3, 1e-4, 10., 6)),
+        ];
+        let probe = synthetic_flight_log("probe", 0.3);
+        for alpha_search in searches {
+            let parameters = DroneRCParameters {
+                alpha_search,
+                ..parameters
+            };
+            let in_memory = train_on_dataset(&dataset, parameters).unwrap();
+            let streamed =
+                train_streaming_on_dataset(&mut loader, "synthetic", None, parameters).unwrap();
+            // both run at the 10 ms the synthetic logs were sampled at
+            assert_eq!(in_memory.scheduler_delta(), Duration::from_millis(10));
+            assert_eq!(streamed.scheduler_delta(), Duration::from_millis(10));
+
+            // both paths see the same validation errors and pick the same alpha
+            let (in_memory_ridge, streamed_ridge) = (
+                in_memory.readout.as_ridge().unwrap(),
+                streamed.readout.as_ridge().unwrap(),
+            );
+            assert_eq!(in_memory_ridge.alpha, streamed_ridge.alpha);
+            let curves = (&in_memory_ridge.selection, &streamed_ridge.selection);
+            assert_eq!(curves.0.is_some(), alpha_search.is_some());
+            if let (Some(expected), Some(actual)) = curves {
+                for (e, a) in expected.errors.iter().zip(&actual.errors) {
+                    assert!((e - a).abs() < 1e-6 * e.abs().max(1.));
+                }
+            }
+
+            // the PCA components may differ in sign, the predictions may not
+            for (expected, actual) in predictions(&in_memory, &probe)
+                .iter()
+                .zip(predictions(&streamed, &probe))
+            {
+                for i in 0..4 {
+                    assert!((expected[i] - actual[i]).abs() < 1e-6);
+                }
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn empty_episodes_are_skipped() {
+        let root = std::env::temp_dir().join("quad_empty_episodes_training_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut loader = FileLoader::new(root.clone());
+        // the empty episode comes first, so it can't give the sample period either
+        let dataset = FlDataSet {
+            dataset_id: "with_empty".into(),
+            train_data: [FlightLog::new("empty".into(), vec![])]
+                .into_iter()
+                .chain((0..2).map(|i| synthetic_flight_log(&format!("train_{i}"), i as f64)))
+                .collect(),
+            ..Default::default()
+        };
+        loader.insert_data_set(dataset.clone()).unwrap();
+        let parameters = DroneRCParameters {
+            internal_units: 20,
+            connectivity: 0.3,
+            spectral_radius: 0.9,
+            input_scaling: 0.5,
+            buffer_size: 1,
+            alpha: 1e-3,
+            alpha_search: None,
+            readout_type: ReadoutType::Ridge,
+            reducer_type: ReducerType::PCA(10),
+            use_setpoint_repr: true,
+            seed: 7,
+        };
+
+        let in_memory = train_on_dataset(&dataset, parameters).unwrap();
+        assert_eq!(in_memory.scheduler_delta(), Duration::from_millis(10));
+        let downsampled = train_streaming_on_dataset(
+            &mut loader,
+            "with_empty",
+            Some(Duration::from_millis(20)),
+            parameters,
+        )
+        .unwrap();
+        assert_eq!(downsampled.scheduler_delta(), Duration::from_millis(20));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn readouts_fit_and_round_trip() {
+        let dataset = FlDataSet {
+            dataset_id: "synthetic".into(),
+            train_data: (0..3)
+                .map(|i| synthetic_flight_log(&format!("train_{i}"), i as f64))
+                .collect(),
+            ..Default::default()
+        };
+        let probe = synthetic_flight_log("probe", 0.3);
+        // the targets are linear in the sticks, so ridge fits them almost exactly
+        let readout_types = [
+            (ReadoutType::Ridge, 1e-8),
+            (
+                ReadoutType::ElasticNet {
+                    penalty: 1e-4,
+                    l1_ratio: 0.5,
+                },
+                1e-4,
+            ),
+            (
+                ReadoutType::KernelRidge {
+                    alpha: 1e-3,
+                    gamma: 0.5,
+                    max_centers: 100,
+                },
+                1e-4,
+            ),
+        ];
+        for (readout_type, max_mse) in readout_types {
+            let parameters = DroneRCParameters {
+                internal_units: 20,
+                connectivity: 0.3,
+                spectral_radius: 0.9,
+                input_scaling: 0.5,
+                buffer_size: 1,
+                alpha: 1e-3,
+                alpha_search: None,
+                readout_type,
+                reducer_type: ReducerType::PCA(10),
+                use_setpoint_repr: true,
+                seed: 7,
+            };
+            let controller = train_on_dataset(&dataset, parameters).unwrap();
+            controller.init();
+            let mse = evaluate_open_loop_fl_mse(&controller, &probe);
+            assert!(mse < max_mse, "{readout_type:?} fits poorly: {mse}");
+
+            let restored: NonAdaptingDroneRc =
+                serde_json::from_str(&serde_json::to_string(&controller).unwrap()).unwrap();
+            restored.init();
+            let restored_mse = evaluate_open_loop_fl_mse(&restored, &probe);
+            assert!((restored_mse - mse).abs() <= 1e-9 * mse.max(1e-9));
+
+            if readout_type != ReadoutType::Ridge {
+                let parameters = DroneRCParameters {
+                    alpha_search: Some(AlphaSearch::gcv(1e-4, 10., 6)),
+                    ..parameters
+                };
+                assert!(matches!(
+                    train_on_dataset(&dataset, parameters),
+                    Err(TrainingError::AlphaSearchWithoutRidge(t)) if t == readout_type
+                ));
+            }
+        }
+    }
+}
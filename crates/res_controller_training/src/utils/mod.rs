@@ -1,16 +1,20 @@
 pub mod angular_rate_stabilization_db;
 
 use flight_controller::Channels;
-use loggers::FlightLog;
+use loggers::{FlightLog, storage::data_root};
 use nalgebra::{DMatrix, DVector};
+use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 
+// Results are written to `results/` below the data root, see `loggers::storage`
 pub fn results_path(filename: &str) -> PathBuf {
-    Path::new(env!("CARGO_MANIFEST_DIR")).join(filename)
+    let results_dir = data_root().join("results");
+    fs::create_dir_all(&results_dir).expect("Failed to create results directory");
+    results_dir.join(filename)
 }
 
 pub fn reset_results_file(path: &Path, header: &str) {
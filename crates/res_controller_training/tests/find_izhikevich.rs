@@ -47,12 +47,19 @@ fn find_izhikevich_reservoir() {
 
     // Load data
     let mut sim_context = SimContext::default();
-    sim_context.set_loader(&sim_context::LoaderType::File);
-    let mut fl_data_set = sim_context.loader.lock().unwrap().load_data_set("10_len");
+    sim_context
+        .set_loader(&sim_context::LoaderType::File)
+        .unwrap();
+    let mut fl_data_set = sim_context
+        .loader
+        .lock()
+        .unwrap()
+        .load_data_set("10_len")
+        .unwrap();
     fl_data_set.downsample(sample_rate);
 
     // Load the testing harness
-    let reservoir = random_izhikevich(512, 0.05, 0.80);
+    let reservoir = random_izhikevich(512, 0.05, 0.80, 42);
     let mut harness = IzhikevichHarness::new(reservoir);
 
     let first_flight = fl_data_set.train_data[0].clone();
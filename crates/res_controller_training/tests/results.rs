@@ -1,5 +1,6 @@
 // NOTE: these are the results that are going to be used!
 
+use bf_controller::BFController;
 use drone::default_drone::default_7in_4s_drone;
 use flight_controller::Channels;
 use flight_controller::FlightController;
@@ -12,10 +13,13 @@ use res_controller::dimensionality_reducer::ReducerType;
 use res_controller_training::eval::angular_rate_stabilization::angular_rate_stabilization_test;
 use res_controller_training::eval::angular_rate_stabilization::dummy_stabilization_db;
 use res_controller_training::eval::open_loop_imitation_mse::OpenLoopEvaluationResult;
+use res_controller_training::train::dagger::{DaggerParameters, train_dagger};
 use res_controller_training::train::esn::train_and_evaluate_open_loop_imitation_mse;
 use res_controller_training::train::train_on_dataset;
 use res_controller_training::utils::append_result;
+use res_controller_training::utils::reset_results_file;
 use res_controller_training::utils::results_path;
+use ridge::ReadoutType;
 use sim_context::SimContext;
 use simulator::Simulator;
 use std::sync::Arc;
@@ -41,8 +45,11 @@ impl ParameterSweep {
                     input_scaling: self.base.input_scaling,
                     buffer_size,
                     alpha: self.base.alpha,
+                    alpha_search: self.base.alpha_search,
+                    readout_type: self.base.readout_type,
                     reducer_type: ReducerType::PCA(pca_dim),
                     use_setpoint_repr: self.base.use_setpoint_repr,
+                    seed: self.base.seed,
                 });
             }
         }
@@ -52,6 +59,7 @@ impl ParameterSweep {
 
 fn esn_parameters_sweep_test_on_db(
     dataset_id: &str,
+    seed: u64,
 ) -> Vec<((OpenLoopEvaluationResult, DroneRCParameters), String)> {
     let base = DroneRCParameters {
         internal_units: 200,
@@ -60,8 +68,11 @@ fn esn_parameters_sweep_test_on_db(
         input_scaling: 0.15,
         buffer_size: 1,
         alpha: 1.0,
+        alpha_search: None,
+        readout_type: ReadoutType::Ridge,
         reducer_type: ReducerType::PCA(64), // will be overridden by sweep
         use_setpoint_repr: false,
+        seed,
     };
     let parameter_sweep = ParameterSweep {
         base,
@@ -74,7 +85,9 @@ fn esn_parameters_sweep_test_on_db(
         .into_par_iter()
         .map(|params| {
             let mut sim_context = SimContext::default();
-            sim_context.set_loader(&sim_context::LoaderType::File);
+            sim_context
+                .set_loader(&sim_context::LoaderType::File)
+                .unwrap();
             (
                 train_and_evaluate_open_loop_imitation_mse(&mut sim_context, dataset_id, params),
                 dataset_id.to_owned(),
@@ -94,7 +107,7 @@ fn esn_parameter_sweep_test() {
         .flat_map(|ds_id| {
             (0..tries_per_ds)
                 .into_par_iter()
-                .flat_map(|_| esn_parameters_sweep_test_on_db(ds_id))
+                .flat_map(|seed| esn_parameters_sweep_test_on_db(ds_id, seed))
                 .collect::<Vec<_>>()
         })
         .collect();
@@ -131,36 +144,121 @@ fn evaluate_esn_stabilization() {
         input_scaling: 0.15,
         buffer_size: 8,
         alpha: 1.,
+        alpha_search: None,
+        readout_type: ReadoutType::Ridge,
         reducer_type: ReducerType::PCA(64),
         use_setpoint_repr: false,
+        seed: 0,
     };
 
-    (0..10).into_par_iter().for_each(|_| {
+    (0..10).into_par_iter().for_each(|seed| {
         for ds in ["5_len", "10_len", "25_len", "50_len"] {
             let controller_id = format!("{ds}_trained_2");
             let mut sim_context = SimContext::default();
-            sim_context.set_loader(&sim_context::LoaderType::File);
-            let mut fl_data_set = sim_context.loader.lock().unwrap().load_data_set(ds);
+            sim_context
+                .set_loader(&sim_context::LoaderType::File)
+                .unwrap();
+            let mut fl_data_set = sim_context
+                .loader
+                .lock()
+                .unwrap()
+                .load_data_set(ds)
+                .unwrap();
             fl_data_set.downsample(Duration::from_millis(10));
-            let controller = train_on_dataset(&fl_data_set, drone_params);
-            sim_context.insert_drone_rc(&controller_id, controller.clone());
+            let drone_params = DroneRCParameters {
+                seed,
+                ..drone_params
+            };
+            let controller = train_on_dataset(&fl_data_set, drone_params).unwrap();
+            sim_context
+                .insert_drone_rc(&controller_id, controller.clone())
+                .unwrap();
             controller.init();
             let db = dummy_stabilization_db();
-            let drone = sim_context.load_drone().unwrap();
+            let drone = sim_context.load_drone().unwrap().unwrap();
             angular_rate_stabilization_test(drone, Arc::new(controller), db, &controller_id);
         }
     });
 }
 
+// Closed-loop dataset aggregation with Betaflight as the teacher, from the 10 episode data set
+#[test]
+fn dagger_stabilization() {
+    let drone_params = DroneRCParameters {
+        internal_units: 200,
+        connectivity: 0.15,
+        spectral_radius: 0.9,
+        input_scaling: 0.15,
+        buffer_size: 8,
+        alpha: 1.,
+        alpha_search: None,
+        readout_type: ReadoutType::Ridge,
+        reducer_type: ReducerType::PCA(64),
+        use_setpoint_repr: false,
+        seed: 0,
+    };
+    let mut sim_context = SimContext::default();
+    sim_context
+        .set_loader(&sim_context::LoaderType::File)
+        .unwrap();
+    let mut fl_data_set = sim_context
+        .loader
+        .lock()
+        .unwrap()
+        .load_data_set("10_len")
+        .unwrap();
+    fl_data_set.downsample(Duration::from_millis(10));
+    let drone = sim_context.load_drone().unwrap().unwrap();
+    let dagger_parameters = DaggerParameters {
+        iterations: 5,
+        rollouts_per_iteration: 10,
+        stabilization_db: Some(dummy_stabilization_db()),
+    };
+    let (controller, iterations) = train_dagger(
+        &drone,
+        Arc::new(BFController::default()),
+        &mut fl_data_set,
+        drone_params,
+        &dagger_parameters,
+    )
+    .unwrap();
+    sim_context
+        .insert_drone_rc("10_len_dagger", (*controller).clone())
+        .unwrap();
+
+    let file_name = results_path("dagger.csv");
+    reset_results_file(
+        &file_name,
+        "iteration,train_episodes,closed_loop_imitation_mse,rate_tracking_mse,stabilization_rate",
+    );
+    for iteration in iterations {
+        println!("{iteration:?}");
+        append_result(
+            &file_name,
+            &format!(
+                "{},{},{},{},{}",
+                iteration.iteration,
+                iteration.train_episodes,
+                iteration.closed_loop_imitation_mse,
+                iteration.rate_tracking_mse,
+                iteration.stabilization_rate.unwrap_or(f64::NAN)
+            ),
+        );
+    }
+}
+
 #[test]
 fn compare_it_with_original() {
     let mut sim_context = SimContext::default();
-    sim_context.set_loader(&sim_context::LoaderType::File);
+    sim_context
+        .set_loader(&sim_context::LoaderType::File)
+        .unwrap();
     let mut fl = sim_context
         .loader
         .lock()
         .unwrap()
-        .load_flight_log("manual_flight");
+        .load_flight_log("manual_flight")
+        .unwrap();
     let downsample_len = Duration::from_millis(10);
     fl.downsample(downsample_len);
     let logger = Arc::new(Mutex::new(MemoryLogger::new("mem".into())));
@@ -168,12 +266,13 @@ fn compare_it_with_original() {
         .loader
         .lock()
         .unwrap()
-        .load_res_controller("10_long_trained");
+        .load_res_controller("10_long_trained")
+        .unwrap();
     let controller = Arc::new(controller);
     let drone = default_7in_4s_drone();
     let mut simulator =
         Simulator::with_drone_and_controller_logger(drone, controller.clone(), logger.clone());
-    simulator.init();
+    simulator.init().unwrap();
     for snapshot in fl.steps.iter() {
         simulator.simulate_delta(downsample_len, snapshot.channels);
     }
@@ -235,15 +334,14 @@ fn benchmark_all_loaded_controllers_as_tolerance_halves() {
     let drone = default_7in_4s_drone();
 
     let mut file_loader = FileLoader::default();
-    let controller_ids = file_loader.get_reservoir_controller_ids();
+    let controller_ids = file_loader.get_reservoir_controller_ids().unwrap();
 
     let mut controllers: Vec<(String, Arc<dyn FlightController>)> = Vec::new();
     for controller_id in controller_ids {
-        let Some(controller) = file_loader.try_load_res_controller(&controller_id) else {
-            println!("Skipping unserializable controller: {controller_id}");
-            continue;
-        };
-        controllers.push((controller_id, Arc::new(controller)));
+        match file_loader.load_res_controller(&controller_id) {
+            Ok(controller) => controllers.push((controller_id, Arc::new(controller))),
+            Err(err) => println!("Skipping controller {controller_id}: {err}"),
+        }
     }
     assert!(
         !controllers.is_empty(),
@@ -0,0 +1,340 @@
+use crate::{
+    selection::{AlphaCurve, AlphaSearchError, AlphaSelectionMethod},
+    RidgeRegression, RidgeRegressionSol,
+};
+use nalgebra::{DMatrix, DVector, Dyn, SymmetricEigen};
+use serde::{Deserialize, Serialize};
+
+// Sufficient statistics of a ridge fit: the sample count, the means and the centered
+// comoments XcᵀXc, XcᵀYc and YcᵀYc. Batches are merged with the pairwise update of Chan et al. so
+// the statistics stay centered, which avoids the cancellation of subtracting n μμᵀ from XᵀX.
+// The data is only touched once, any alpha can be solved for afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RidgeAccumulator {
+    n: usize,
+    x_mean: DVector<f64>,
+    y_mean: DVector<f64>,
+    xx: DMatrix<f64>,
+    xy: DMatrix<f64>,
+    yy: DMatrix<f64>,
+}
+
+impl RidgeAccumulator {
+    pub fn new(features: usize, targets: usize) -> Self {
+        Self {
+            n: 0,
+            x_mean: DVector::zeros(features),
+            y_mean: DVector::zeros(targets),
+            xx: DMatrix::zeros(features, features),
+            xy: DMatrix::zeros(features, targets),
+            yy: DMatrix::zeros(targets, targets),
+        }
+    }
+
+    // Statistics of a single batch, x is samples x features and y is samples x targets
+    pub fn from_batch(x: &DMatrix<f64>, y: &DMatrix<f64>) -> Self {
+        assert_eq!(x.nrows(), y.nrows(), "x and y must have the same rows");
+        let n = x.nrows();
+        if n == 0 {
+            return Self::new(x.ncols(), y.ncols());
+        }
+        let x_mean = x.row_mean().transpose();
+        let y_mean = y.row_mean().transpose();
+        let mut xc = x.clone();
+        for (i, mut col) in xc.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-x_mean[i]);
+        }
+        let mut yc = y.clone();
+        for (i, mut col) in yc.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-y_mean[i]);
+        }
+        Self {
+            n,
+            xx: xc.tr_mul(&xc),
+            xy: xc.tr_mul(&yc),
+            yy: yc.tr_mul(&yc),
+            x_mean,
+            y_mean,
+        }
+    }
+
+    pub fn features(&self) -> usize {
+        self.x_mean.len()
+    }
+
+    pub fn targets(&self) -> usize {
+        self.y_mean.len()
+    }
+
+    pub fn samples(&self) -> usize {
+        self.n
+    }
+
+    pub fn add_batch(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) {
+        assert_eq!(x.ncols(), self.features(), "feature count mismatch");
+        assert_eq!(y.ncols(), self.targets(), "target count mismatch");
+        self.merge(Self::from_batch(x, y));
+    }
+
+    // Combines the statistics of two disjoint sets of samples, e.g. accumulated on different
+    // threads or per episode
+    pub fn merge(&mut self, other: Self) {
+        assert_eq!(other.features(), self.features(), "feature count mismatch");
+        assert_eq!(other.targets(), self.targets(), "target count mismatch");
+        if other.n == 0 {
+            return;
+        }
+        if self.n == 0 {
+            *self = other;
+            return;
+        }
+        let n = self.n + other.n;
+        let weight = (self.n * other.n) as f64 / n as f64;
+        let dx = &other.x_mean - &self.x_mean;
+        let dy = &other.y_mean - &self.y_mean;
+        self.xx += other.xx + &dx * dx.transpose() * weight;
+        self.xy += other.xy + &dx * dy.transpose() * weight;
+        self.yy += other.yy + &dy * dy.transpose() * weight;
+        self.x_mean += dx * (other.n as f64 / n as f64);
+        self.y_mean += dy * (other.n as f64 / n as f64);
+        self.n = n;
+    }
+
+    pub fn x_mean(&self) -> &DVector<f64> {
+        &self.x_mean
+    }
+
+    pub fn y_mean(&self) -> &DVector<f64> {
+        &self.y_mean
+    }
+
+    // Population covariance of the features, enough to fit a PCA without the data
+    pub fn x_covariance(&self) -> DMatrix<f64> {
+        &self.xx / self.n.max(1) as f64
+    }
+
+    pub fn solve(&self, alpha: f64) -> RidgeRegression {
+        self.solve_many(&[alpha]).pop().unwrap()
+    }
+
+    // One eigendecomposition of XcᵀXc serves every alpha: β(α) = Q (Λ + αI)⁻¹ Qᵀ XcᵀYc.
+    // Zero eigenvalues are skipped for alpha = 0, which gives the minimum norm solution.
+    pub fn solve_many(&self, alphas: &[f64]) -> Vec<RidgeRegression> {
+        let eigen = SymmetricEigen::new(self.xx.clone());
+        alphas
+            .iter()
+            .map(|&alpha| self.solve_decomposed(&eigen, alpha))
+            .collect()
+    }
+
+    fn solve_decomposed(&self, eigen: &SymmetricEigen<f64, Dyn>, alpha: f64) -> RidgeRegression {
+        let SymmetricEigen {
+            eigenvectors,
+            eigenvalues,
+        } = eigen;
+        let tolerance = eigenvalues.amax() * f64::EPSILON * self.features() as f64;
+        let mut scaled = eigenvectors.tr_mul(&self.xy);
+        for (i, mut row) in scaled.row_iter_mut().enumerate() {
+            let denominator = eigenvalues[i].max(0.) + alpha;
+            if denominator <= tolerance {
+                row.fill(0.);
+            } else {
+                row /= denominator;
+            }
+        }
+        // targets x features like `fit_multiple_svd`
+        let coeff = (eigenvectors * scaled).transpose();
+        let intercept = &self.y_mean - &coeff * &self.x_mean;
+        RidgeRegression {
+            alpha,
+            sol: Some(RidgeRegressionSol { coeff, intercept }),
+            selection: None,
+        }
+    }
+
+    // Sum of squared errors of a fitted model on the accumulated samples, over all targets
+    pub fn sse(&self, model: &RidgeRegression) -> f64 {
+        let Some(RidgeRegressionSol { coeff, intercept }) = model.sol.as_ref() else {
+            panic!("the model has not been fit")
+        };
+        // e = y - Bx - b splits into its mean and the centered part
+        let mean_error = &self.y_mean - coeff * &self.x_mean - intercept;
+        let centered = self.yy.trace() - 2. * (coeff * &self.xy).trace()
+            + (coeff * &self.xx * coeff.transpose()).trace();
+        centered.max(0.) + self.n as f64 * mean_error.norm_squared()
+    }
+
+    // Generalized cross-validation, the closed form approximation of leave-one-out:
+    // GCV(α) = MSE(α) / (1 - dof(α) / n)², dof(α) = Σ λᵢ / (λᵢ + α) + 1 for the intercept
+    pub fn gcv_curve(&self, alphas: &[f64]) -> Result<AlphaCurve, AlphaSearchError> {
+        let eigen = SymmetricEigen::new(self.xx.clone());
+        let n = self.n as f64;
+        let mse_and_dof = alphas.iter().map(|&alpha| {
+            let model = self.solve_decomposed(&eigen, alpha);
+            let mse = self.sse(&model) / (n * self.targets() as f64);
+            let dof = eigen
+                .eigenvalues
+                .iter()
+                .map(|&l| {
+                    let l = l.max(0.);
+                    if l + alpha > 0. {
+                        l / (l + alpha)
+                    } else {
+                        0.
+                    }
+                })
+                .sum::<f64>()
+                + 1.;
+            (mse, dof)
+        });
+        AlphaCurve::gcv(alphas, mse_and_dof, self.n)
+    }
+
+    // Every fold is held out once and scored by a model fit on the other folds, the error is
+    // the mean squared error over all held out samples. Keeping whole episodes in the same fold
+    // keeps the strongly correlated samples of an episode out of its own validation.
+    pub fn k_fold_curve(
+        folds: &[RidgeAccumulator],
+        alphas: &[f64],
+    ) -> Result<AlphaCurve, AlphaSearchError> {
+        if folds.len() < 2 {
+            return Err(AlphaSearchError::TooFewFolds(folds.len()));
+        }
+        let mut sse = vec![0.; alphas.len()];
+        let mut samples = 0;
+        for (held_out, validation) in folds.iter().enumerate() {
+            let mut train = RidgeAccumulator::new(validation.features(), validation.targets());
+            for (i, fold) in folds.iter().enumerate() {
+                if i != held_out {
+                    train.merge(fold.clone());
+                }
+            }
+            for (error, model) in sse.iter_mut().zip(train.solve_many(alphas)) {
+                *error += validation.sse(&model);
+            }
+            samples += validation.n;
+        }
+        let denominator = (samples.max(1) * folds[0].targets().max(1)) as f64;
+        let errors = sse.into_iter().map(|e| e / denominator).collect();
+        Ok(AlphaCurve::new(
+            AlphaSelectionMethod::KFold(folds.len()),
+            alphas,
+            errors,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accumulator::RidgeAccumulator, selection::AlphaSearch, RidgeRegression, RidgeRegressionSol,
+    };
+    use nalgebra::DMatrix;
+
+    // Deterministic noise in [-1, 1]
+    fn noise(i: usize, j: usize) -> f64 {
+        let v = ((i * 7919 + j * 104729) as f64 * 0.618).sin() * 43758.5453;
+        2. * (v - v.floor()) - 1.
+    }
+
+    // A linear map of 5 features to 3 targets, plus `noise_scale` noise on the targets
+    fn linear_problem(samples: usize, noise_scale: f64) -> (DMatrix<f64>, DMatrix<f64>) {
+        let x = DMatrix::from_fn(samples, 5, |i, j| noise(i, j) + 0.3 * j as f64);
+        let weights = DMatrix::from_fn(5, 3, |i, j| (i as f64 - 2.) * (j as f64 + 1.) * 0.5);
+        let y = &x * weights + DMatrix::from_fn(samples, 3, |i, j| noise_scale * noise(i, j + 11));
+        (x, y)
+    }
+
+    fn assert_close(a: &DMatrix<f64>, b: &DMatrix<f64>, tolerance: f64) {
+        assert_eq!(a.shape(), b.shape());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() <= tolerance * b.abs().max(1.), "{a} != {b}");
+        }
+    }
+
+    fn solution(model: &RidgeRegression) -> (&DMatrix<f64>, DMatrix<f64>) {
+        let RidgeRegressionSol { coeff, intercept } = model.sol.as_ref().unwrap();
+        (
+            coeff,
+            DMatrix::from_column_slice(intercept.len(), 1, intercept.as_slice()),
+        )
+    }
+
+    #[test]
+    fn merged_batches_match_a_single_batch() {
+        let (x, y) = linear_problem(120, 0.1);
+        let single = RidgeAccumulator::from_batch(&x, &y);
+        let mut merged = RidgeAccumulator::new(5, 3);
+        // uneven batches, including an empty one
+        for (start, len) in [(0, 7), (7, 0), (7, 50), (57, 63)] {
+            let mut batch = RidgeAccumulator::new(5, 3);
+            batch.add_batch(
+                &x.rows(start, len).into_owned(),
+                &y.rows(start, len).into_owned(),
+            );
+            merged.merge(batch);
+        }
+        assert_eq!(merged.samples(), single.samples());
+        assert_close(
+            &DMatrix::from_column_slice(5, 1, merged.x_mean().as_slice()),
+            &DMatrix::from_column_slice(5, 1, single.x_mean().as_slice()),
+            1e-12,
+        );
+        assert_close(&merged.x_covariance(), &single.x_covariance(), 1e-10);
+        let (merged_model, single_model) = (merged.solve(0.1), single.solve(0.1));
+        let (merged_coeff, merged_intercept) = solution(&merged_model);
+        let (single_coeff, single_intercept) = solution(&single_model);
+        assert_close(merged_coeff, single_coeff, 1e-10);
+        assert_close(&merged_intercept, &single_intercept, 1e-10);
+    }
+
+    #[test]
+    fn solve_many_matches_the_svd_fit() {
+        let (x, y) = linear_problem(80, 0.2);
+        let accumulator = RidgeAccumulator::from_batch(&x, &y);
+        let alphas = [0., 1e-3, 0.5, 20.];
+        for (alpha, solved) in alphas.iter().zip(accumulator.solve_many(&alphas)) {
+            let fitted = RidgeRegression::fit_multiple_svd(*alpha, x.clone(), &y);
+            let (solved_coeff, solved_intercept) = solution(&solved);
+            let (fitted_coeff, fitted_intercept) = solution(&fitted);
+            assert_close(solved_coeff, fitted_coeff, 1e-8);
+            assert_close(&solved_intercept, &fitted_intercept, 1e-8);
+            // the accumulated sum of squared errors is the one of the predictions
+            let residual = fitted.predict(x.clone()) - &y;
+            let sse = accumulator.sse(&solved);
+            assert!((sse - residual.norm_squared()).abs() < 1e-8 * sse.max(1.));
+        }
+    }
+
+    // Noise free targets are fit best without a penalty, targets that don't depend on the
+    // features are predicted best by their mean, i.e. the largest penalty
+    #[test]
+    fn gcv_and_k_fold_pick_the_expected_alpha() {
+        let search = AlphaSearch::gcv(1e-6, 1e6, 13);
+        let alphas = search.alphas().unwrap();
+        let (x, clean) = linear_problem(150, 0.);
+        let unrelated = DMatrix::from_fn(150, 3, |i, j| noise(i, j + 23));
+        for (y, expected) in [(&clean, alphas[0]), (&unrelated, alphas[12])] {
+            let accumulator = RidgeAccumulator::from_batch(&x, y);
+            assert_eq!(accumulator.gcv_curve(&alphas).unwrap().best_alpha, expected);
+            let svd_curve = RidgeRegression::fit_multiple_svd_gcv(&alphas, x.clone(), y)
+                .unwrap()
+                .selection
+                .unwrap();
+            assert_eq!(svd_curve.best_alpha, expected);
+
+            let folds = (0..5)
+                .map(|fold| {
+                    let rows = (0..150).filter(|i| i % 5 == fold).collect::<Vec<_>>();
+                    RidgeAccumulator::from_batch(&x.select_rows(&rows), &y.select_rows(&rows))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                RidgeAccumulator::k_fold_curve(&folds, &alphas)
+                    .unwrap()
+                    .best_alpha,
+                expected
+            );
+        }
+    }
+}
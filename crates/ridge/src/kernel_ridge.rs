@@ -0,0 +1,149 @@
+use crate::readout::ReadoutError;
+use nalgebra::{Cholesky, DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelRidgeSol {
+    // standardization of the inputs, the kernel is evaluated on standardized data
+    pub x_mean: DVector<f64>,
+    pub x_scale: DVector<f64>,
+    // centers x features, standardized
+    pub centers: DMatrix<f64>,
+    // centers x targets
+    pub dual_coeff: DMatrix<f64>,
+    pub intercept: DVector<f64>,
+}
+
+// Kernel ridge regression with a gaussian kernel k(x, c) = exp(-gamma |x - c|² / features).
+// The full kernel matrix is quadratic in the samples, so the function is restricted to the
+// span of `max_centers` evenly spaced training samples (subset of regressors): the dual
+// coefficients solve (KₙₘᵀKₙₘ + alpha Kₘₘ) β = Kₙₘᵀy on centered Kₙₘ and y.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelRidge {
+    pub alpha: f64,
+    pub gamma: f64,
+    pub max_centers: usize,
+    pub sol: Option<KernelRidgeSol>,
+}
+
+impl KernelRidge {
+    pub fn new(alpha: f64, gamma: f64, max_centers: usize) -> Self {
+        Self {
+            alpha,
+            gamma,
+            max_centers,
+            sol: None,
+        }
+    }
+
+    fn kernel(&self, x: &DMatrix<f64>, centers: &DMatrix<f64>) -> DMatrix<f64> {
+        let features = x.ncols().max(1) as f64;
+        DMatrix::from_fn(x.nrows(), centers.nrows(), |i, j| {
+            let distance = (x.row(i) - centers.row(j)).norm_squared();
+            (-self.gamma * distance / features).exp()
+        })
+    }
+
+    fn standardize(x: &mut DMatrix<f64>, mean: &DVector<f64>, scale: &DVector<f64>) {
+        for (i, mut col) in x.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-mean[i]);
+            col /= scale[i];
+        }
+    }
+
+    pub fn fit(&mut self, mut x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError> {
+        let samples = x.nrows();
+        if samples == 0 {
+            return Err(ReadoutError::NoSamples);
+        }
+        let x_mean = x.row_mean().transpose();
+        let x_scale = x.row_variance().transpose().map(|v| {
+            let std = v.sqrt();
+            if std > f64::EPSILON {
+                std
+            } else {
+                1.
+            }
+        });
+        Self::standardize(&mut x, &x_mean, &x_scale);
+
+        let m = self.max_centers.clamp(1, samples);
+        let centers = DMatrix::from_rows(
+            &(0..m)
+                .map(|i| x.row(i * samples / m).into_owned())
+                .collect::<Vec<_>>(),
+        );
+
+        let mut k_nm = self.kernel(&x, &centers);
+        let k_mean = k_nm.row_mean().transpose();
+        for (i, mut col) in k_nm.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-k_mean[i]);
+        }
+        let y_mean = y.row_mean().transpose();
+        let mut yc = y.clone();
+        for (i, mut col) in yc.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-y_mean[i]);
+        }
+
+        let k_mm = self.kernel(&centers, &centers);
+        // the jitter keeps the system positive definite when centers repeat
+        let system = k_nm.tr_mul(&k_nm)
+            + k_mm * self.alpha
+            + DMatrix::identity(m, m) * (1e-10 * samples as f64);
+        let dual_coeff = Cholesky::new(system)
+            .ok_or(ReadoutError::NotPositiveDefinite)?
+            .solve(&k_nm.tr_mul(&yc));
+        let intercept = y_mean - dual_coeff.tr_mul(&k_mean);
+
+        self.sol = Some(KernelRidgeSol {
+            x_mean,
+            x_scale,
+            centers,
+            dual_coeff,
+            intercept,
+        });
+        Ok(())
+    }
+
+    pub fn predict(&self, mut x: DMatrix<f64>) -> DMatrix<f64> {
+        let KernelRidgeSol {
+            x_mean,
+            x_scale,
+            centers,
+            dual_coeff,
+            intercept,
+        } = self.sol.as_ref().expect("model not fitted");
+        Self::standardize(&mut x, x_mean, x_scale);
+        let mut out = self.kernel(&x, centers) * dual_coeff;
+        for mut row in out.row_iter_mut() {
+            row += intercept.transpose();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{kernel_ridge::KernelRidge, readout::ReadoutError};
+    use nalgebra::DMatrix;
+
+    #[test]
+    fn fits_a_nonlinear_function() {
+        let x = DMatrix::from_fn(200, 1, |i, _| i as f64 / 200. * 6.);
+        let y = x.map(|v| v.sin() + 0.5 * v);
+        let mut kernel_ridge = KernelRidge::new(1e-6, 4., 40);
+        kernel_ridge.fit(x.clone(), &y).unwrap();
+        let mse = (kernel_ridge.predict(x.clone()) - &y).norm_squared() / 200.;
+        assert!(mse < 1e-4, "{mse}");
+
+        // a negative alpha makes the system indefinite
+        assert_eq!(
+            KernelRidge::new(-1e3, 4., 40).fit(x, &y),
+            Err(ReadoutError::NotPositiveDefinite)
+        );
+        assert_eq!(
+            KernelRidge::new(1e-6, 4., 40).fit(DMatrix::zeros(0, 1), &DMatrix::zeros(0, 1)),
+            Err(ReadoutError::NoSamples)
+        );
+    }
+}
@@ -1,4 +1,12 @@
+pub mod accumulator;
+pub mod kernel_ridge;
+pub mod readout;
 pub mod ridge2;
+pub mod selection;
+
+pub use accumulator::RidgeAccumulator;
+pub use readout::{Readout, ReadoutError, ReadoutModel, ReadoutType};
+pub use selection::{AlphaCurve, AlphaSearch, AlphaSearchError, AlphaSelectionMethod};
 
 use nalgebra::{DMatrix, DVector, RawStorage, SVD};
 use serde::{Deserialize, Serialize};
@@ -14,11 +22,18 @@ pub struct RidgeRegressionSol {
 pub struct RidgeRegression {
     pub alpha: f64,
     pub sol: Option<RidgeRegressionSol>,
+    // how alpha was picked, if it was selected by cross-validation
+    #[serde(default)]
+    pub selection: Option<AlphaCurve>,
 }
 
 impl RidgeRegression {
     pub fn new(alpha: f64) -> Self {
-        Self { alpha, sol: None }
+        Self {
+            alpha,
+            sol: None,
+            selection: None,
+        }
     }
 
     pub fn fit_on_decomposed_svd(
@@ -39,38 +54,108 @@ impl RidgeRegression {
         (coeff, intercept)
     }
 
-    pub fn fit_multiple_svd(alpha: f64, mut x: DMatrix<f64>, y: &DMatrix<f64>) -> Self {
-        let (_, data_features) = x.data.shape();
-        let (_, target_dim) = y.data.shape();
-        let mut coeff_mult: DMatrix<f64> = DMatrix::zeros(target_dim.0, data_features.0);
-        let mut intercept_mult: DVector<f64> = DVector::zeros(target_dim.0);
+    pub fn fit_multiple_svd(alpha: f64, x: DMatrix<f64>, y: &DMatrix<f64>) -> Self {
+        let decomposed = CenteredSvd::new(x);
+        Self::fit_decomposed(alpha, &decomposed, y)
+    }
 
-        let x_mean = DVector::from(x.column_iter().map(|col| col.mean()).collect::<Vec<_>>());
-        for (i, mut col) in x.column_iter_mut().enumerate() {
-            col.add_scalar_mut(-x_mean[i]);
+    // Picks alpha from the grid of `search` before fitting. `episode_lengths` splits the rows
+    // of x into episodes for k-fold, the episodes are dealt round robin into the folds.
+    pub fn fit_multiple_svd_search(
+        search: &AlphaSearch,
+        x: DMatrix<f64>,
+        y: &DMatrix<f64>,
+        episode_lengths: &[usize],
+    ) -> Result<Self, AlphaSearchError> {
+        let alphas = search.alphas()?;
+        let curve = match search.folds(episode_lengths.len())? {
+            None => return Self::fit_multiple_svd_gcv(&alphas, x, y),
+            Some(folds) => {
+                assert_eq!(
+                    episode_lengths.iter().sum::<usize>(),
+                    x.nrows(),
+                    "episode lengths must add up to the rows"
+                );
+                let mut accumulators = vec![RidgeAccumulator::new(x.ncols(), y.ncols()); folds];
+                let mut row = 0;
+                for (episode, &len) in episode_lengths.iter().enumerate() {
+                    accumulators[episode % folds].add_batch(
+                        &x.rows(row, len).into_owned(),
+                        &y.rows(row, len).into_owned(),
+                    );
+                    row += len;
+                }
+                RidgeAccumulator::k_fold_curve(&accumulators, &alphas)?
+            }
+        };
+        let mut fitted = Self::fit_multiple_svd(curve.best_alpha, x, y);
+        fitted.selection = Some(curve);
+        Ok(fitted)
+    }
+
+    // Reuses a single SVD for the generalized cross-validation error of every alpha and the
+    // final fit. With fᵢ = sᵢ² / (sᵢ² + α) the residual of the centered targets is
+    // |yc|² - Σ (1 - (1 - fᵢ)²) (uᵢᵀyc)² and the degrees of freedom are Σ fᵢ + 1.
+    pub fn fit_multiple_svd_gcv(
+        alphas: &[f64],
+        x: DMatrix<f64>,
+        y: &DMatrix<f64>,
+    ) -> Result<Self, AlphaSearchError> {
+        let decomposed = CenteredSvd::new(x);
+        let n = y.nrows() as f64;
+        let mut yc = y.clone();
+        for mut col in yc.column_iter_mut() {
+            let mean = col.mean();
+            col.add_scalar_mut(-mean);
         }
+        let total = yc.norm_squared();
+        let projected = decomposed.u.tr_mul(&yc);
+        let mse_and_dof = alphas.iter().map(|&alpha| {
+            let mut rss = total;
+            let mut dof = 1.;
+            for (i, sig) in decomposed.singular_values.iter().enumerate() {
+                let denominator = sig.powi(2) + alpha;
+                let f = if denominator > 0. {
+                    sig.powi(2) / denominator
+                } else {
+                    0.
+                };
+                rss -= (1. - (1. - f).powi(2)) * projected.row(i).norm_squared();
+                dof += f;
+            }
+            (rss.max(0.) / (n * y.ncols() as f64), dof)
+        });
+        let curve = AlphaCurve::gcv(alphas, mse_and_dof, y.nrows())?;
+        let mut fitted = Self::fit_decomposed(curve.best_alpha, &decomposed, y);
+        fitted.selection = Some(curve);
+        Ok(fitted)
+    }
 
-        let SVD {
+    fn fit_decomposed(alpha: f64, decomposed: &CenteredSvd, y: &DMatrix<f64>) -> Self {
+        let CenteredSvd {
+            x_mean,
             u,
             v_t,
             singular_values,
-        } = SVD::new(x, true, true);
+        } = decomposed;
+        let (_, target_dim) = y.data.shape();
+        let mut coeff_mult: DMatrix<f64> = DMatrix::zeros(target_dim.0, v_t.ncols());
+        let mut intercept_mult: DVector<f64> = DVector::zeros(target_dim.0);
 
         let d = singular_values.map(|sig| sig / (sig.powi(2) + alpha));
-        let v_t = v_t.unwrap();
-        let u = u.unwrap();
 
         for (i, col) in y.column_iter().enumerate() {
-            let (coeff, intercept) = Self::fit_on_decomposed_svd(&u, &v_t, &d, col.into(), &x_mean);
+            let (coeff, intercept) = Self::fit_on_decomposed_svd(u, v_t, &d, col.into(), x_mean);
             coeff_mult.set_row(i, &coeff.transpose());
             intercept_mult[i] = intercept;
         }
         Self {
             alpha,
             sol: Some(RidgeRegressionSol {
-                coeff: coeff_mult.clone(),
-                intercept: intercept_mult.clone(),
+                coeff: coeff_mult,
+                intercept: intercept_mult,
             }),
+            selection: None,
         }
     }
 
@@ -97,3 +182,32 @@ impl RidgeRegression {
         )
     }
 }
+
+// SVD of the mean centered design matrix, shared by the fits for different alphas
+struct CenteredSvd {
+    x_mean: DVector<f64>,
+    u: DMatrix<f64>,
+    v_t: DMatrix<f64>,
+    singular_values: DVector<f64>,
+}
+
+impl CenteredSvd {
+    fn new(mut x: DMatrix<f64>) -> Self {
+        let x_mean = DVector::from(x.column_iter().map(|col| col.mean()).collect::<Vec<_>>());
+        for (i, mut col) in x.column_iter_mut().enumerate() {
+            col.add_scalar_mut(-x_mean[i]);
+        }
+
+        let SVD {
+            u,
+            v_t,
+            singular_values,
+        } = SVD::new(x, true, true);
+        Self {
+            x_mean,
+            u: u.unwrap(),
+            v_t: v_t.unwrap(),
+            singular_values,
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{kernel_ridge::KernelRidge, ridge2::ElasticNetWrapper, RidgeRegression};
+use derive_more::derive::{Display, Error};
+use nalgebra::DMatrix;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+
+// Why a readout could not be fit to the given data
+#[derive(Debug, Clone, PartialEq, Display, Error)]
+pub enum ReadoutError {
+    #[display("no samples to fit")]
+    NoSamples,
+    // e.g. a negative alpha, or features that are not finite
+    #[display("the system of the readout is not positive definite")]
+    NotPositiveDefinite,
+    // the message of the linfa error, e.g. for an `l1_ratio` outside of 0..=1
+    #[display("elastic net fit failed: {_0}")]
+    ElasticNet(#[error(not(source))] String),
+}
+
+// A learner mapping reservoir features (samples x features) to motor inputs
+// (samples x targets), saved together with the controller
+pub trait Readout: Serialize + DeserializeOwned {
+    fn fit(&mut self, x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError>;
+    fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64>;
+}
+
+impl Readout for RidgeRegression {
+    fn fit(&mut self, x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError> {
+        *self = RidgeRegression::fit_multiple_svd(self.alpha, x, y);
+        Ok(())
+    }
+
+    fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64> {
+        RidgeRegression::predict(self, x)
+    }
+}
+
+impl Readout for ElasticNetWrapper {
+    fn fit(&mut self, x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError> {
+        self.fit_multiple(&x, y)
+            .map_err(|err| ReadoutError::ElasticNet(err.to_string()))?;
+        Ok(())
+    }
+
+    fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64> {
+        ElasticNetWrapper::predict(self, x)
+    }
+}
+
+impl Readout for KernelRidge {
+    fn fit(&mut self, x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError> {
+        KernelRidge::fit(self, x, y)
+    }
+
+    fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64> {
+        KernelRidge::predict(self, x)
+    }
+}
+
+// Which readout to train, chosen in the training parameters. The ridge penalty comes from the
+// `alpha` (or `alpha_search`) next to it in the parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadoutType {
+    #[default]
+    Ridge,
+    // l1_ratio 1 is the lasso, 0 is ridge fit by coordinate descent
+    ElasticNet {
+        penalty: f64,
+        l1_ratio: f64,
+    },
+    KernelRidge {
+        alpha: f64,
+        gamma: f64,
+        max_centers: usize,
+    },
+}
+
+impl ReadoutType {
+    pub fn untrained(&self, ridge_alpha: f64) -> ReadoutModel {
+        match *self {
+            Self::Ridge => ReadoutModel::Ridge(RidgeRegression::new(ridge_alpha)),
+            Self::ElasticNet { penalty, l1_ratio } => {
+                ReadoutModel::ElasticNet(ElasticNetWrapper::new_elastic(penalty, l1_ratio))
+            }
+            Self::KernelRidge {
+                alpha,
+                gamma,
+                max_centers,
+            } => ReadoutModel::KernelRidge(KernelRidge::new(alpha, gamma, max_centers)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadoutModel {
+    Ridge(RidgeRegression),
+    ElasticNet(ElasticNetWrapper),
+    KernelRidge(KernelRidge),
+}
+
+impl ReadoutModel {
+    pub fn as_ridge(&self) -> Option<&RidgeRegression> {
+        match self {
+            Self::Ridge(ridge) => Some(ridge),
+            _ => None,
+        }
+    }
+}
+
+impl Readout for ReadoutModel {
+    fn fit(&mut self, x: DMatrix<f64>, y: &DMatrix<f64>) -> Result<(), ReadoutError> {
+        match self {
+            Self::Ridge(readout) => Readout::fit(readout, x, y),
+            Self::ElasticNet(readout) => Readout::fit(readout, x, y),
+            Self::KernelRidge(readout) => Readout::fit(readout, x, y),
+        }
+    }
+
+    fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64> {
+        match self {
+            Self::Ridge(readout) => Readout::predict(readout, x),
+            Self::ElasticNet(readout) => Readout::predict(readout, x),
+            Self::KernelRidge(readout) => Readout::predict(readout, x),
+        }
+    }
+}
+
+// Controllers saved before the readout could be chosen store a bare `RidgeRegression`
+pub fn deserialize_readout<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ReadoutModel, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Readout(ReadoutModel),
+        Ridge(RidgeRegression),
+    }
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Readout(readout) => readout,
+        Stored::Ridge(ridge) => ReadoutModel::Ridge(ridge),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::readout::{Readout, ReadoutError, ReadoutType};
+    use nalgebra::DMatrix;
+
+    #[test]
+    fn every_readout_fits_a_linear_map() {
+        let x = DMatrix::from_fn(100, 3, |i, j| ((i * (j + 2)) as f64 * 0.37).sin());
+        let weights = DMatrix::from_row_slice(3, 2, &[0.5, -0.2, 0.1, 0.3, -0.4, 0.2]);
+        let y = &x * weights;
+        let readout_types = [
+            ReadoutType::Ridge,
+            ReadoutType::ElasticNet {
+                penalty: 1e-5,
+                l1_ratio: 0.5,
+            },
+            ReadoutType::KernelRidge {
+                alpha: 1e-6,
+                gamma: 0.1,
+                max_centers: 50,
+            },
+        ];
+        for readout_type in readout_types {
+            let mut readout = readout_type.untrained(1e-6);
+            readout.fit(x.clone(), &y).unwrap();
+            let mse = (readout.predict(x.clone()) - &y).norm_squared() / y.len() as f64;
+            assert!(mse < 1e-3, "{readout_type:?}: {mse}");
+        }
+    }
+
+    #[test]
+    fn invalid_elastic_net_is_an_error() {
+        let x = DMatrix::from_fn(20, 2, |i, j| (i + j) as f64);
+        let y = DMatrix::from_fn(20, 1, |i, _| i as f64);
+        let mut readout = ReadoutType::ElasticNet {
+            penalty: 1e-3,
+            l1_ratio: 2.,
+        }
+        .untrained(0.);
+        assert!(matches!(
+            readout.fit(x, &y),
+            Err(ReadoutError::ElasticNet(_))
+        ));
+    }
+}
@@ -1,16 +1,17 @@
 use linfa::dataset::DatasetBase;
 use linfa::traits::Fit;
-use linfa_elasticnet::{ElasticNetParams, MultiTaskElasticNetParams};
+use linfa_elasticnet::{ElasticNetError, ElasticNetParams, MultiTaskElasticNetParams};
 use nalgebra::{DMatrix, DVector, RawStorage};
 use ndarray::{Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ENetSol {
     pub coeff: DMatrix<f64>,
     pub intercept: DVector<f64>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ElasticNetWrapper {
     pub penalty: f64,
     pub l1_ratio: f64,
@@ -34,7 +35,12 @@ impl ElasticNetWrapper {
         }
     }
 
-    pub fn fit(&mut self, x: &DMatrix<f64>, y: &DVector<f64>, save: bool) -> (DVector<f64>, f64) {
+    pub fn fit(
+        &mut self,
+        x: &DMatrix<f64>,
+        y: &DVector<f64>,
+        save: bool,
+    ) -> Result<(DVector<f64>, f64), ElasticNetError> {
         let (xa, ya) = (dmatrix_to_array2(x), dvector_to_array1(y));
         let ds = DatasetBase::from((xa, ya));
 
@@ -43,7 +49,7 @@ impl ElasticNetWrapper {
             .l1_ratio(self.l1_ratio)
             .with_intercept(true);
 
-        let fitted = params.fit(&ds).expect("elastic net fit failed");
+        let fitted = params.fit(&ds)?;
 
         let w = fitted.hyperplane().to_owned(); // Array1<f64>
         let b = fitted.intercept(); // f64
@@ -58,14 +64,14 @@ impl ElasticNetWrapper {
             });
         }
 
-        (coeff, intercept)
+        Ok((coeff, intercept))
     }
 
     pub fn fit_multiple(
         &mut self,
         x: &DMatrix<f64>,
         y: &DMatrix<f64>,
-    ) -> (DMatrix<f64>, DVector<f64>) {
+    ) -> Result<(DMatrix<f64>, DVector<f64>), ElasticNetError> {
         let xa = dmatrix_to_array2(x);
         let ya = dmatrix_to_array2(y);
 
@@ -75,7 +81,7 @@ impl ElasticNetWrapper {
             .l1_ratio(self.l1_ratio)
             .with_intercept(true);
 
-        let fitted = params.fit(&ds).expect("multi-task elastic net fit failed");
+        let fitted = params.fit(&ds)?;
 
         let w: Array2<f64> = fitted.hyperplane().to_owned();
         let b: Array1<f64> = fitted.intercept().to_owned();
@@ -87,7 +93,7 @@ impl ElasticNetWrapper {
             coeff: coeff_nm.clone(),
             intercept: intercept_nm.clone(),
         });
-        (coeff_nm, intercept_nm)
+        Ok((coeff_nm, intercept_nm))
     }
 
     pub fn predict(&self, x: DMatrix<f64>) -> DMatrix<f64> {
@@ -0,0 +1,213 @@
+use derive_more::derive::{Display, Error};
+use serde::{Deserialize, Serialize};
+
+// An alpha search that can't be run, reported before any fitting
+#[derive(Debug, Clone, PartialEq, Display, Error)]
+pub enum AlphaSearchError {
+    #[display("alphas must be positive with min <= max, got [{min}, {max}]")]
+    InvalidRange { min: f64, max: f64 },
+    #[display("the alpha grid is empty")]
+    NoAlphas,
+    #[display("k-fold needs at least two folds, got {_0}")]
+    TooFewFolds(#[error(not(source))] usize),
+    // every fold holds at least one episode, so there must be two of them
+    #[display("k-fold needs at least two episodes, got {_0}")]
+    TooFewEpisodes(#[error(not(source))] usize),
+    // every alpha leaves at least as many degrees of freedom as there are samples
+    #[display("gcv is undefined for every alpha with {_0} samples")]
+    TooFewSamples(#[error(not(source))] usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaSelectionMethod {
+    // generalized cross-validation, from a single fit on all of the data
+    Gcv,
+    // k-fold cross-validation with whole episodes in each fold
+    KFold(usize),
+}
+
+// A grid of `count` alphas spaced logarithmically between `min` and `max`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AlphaSearch {
+    pub method: AlphaSelectionMethod,
+    pub min: f64,
+    pub max: f64,
+    pub count: usize,
+}
+
+impl AlphaSearch {
+    pub fn gcv(min: f64, max: f64, count: usize) -> Self {
+        Self {
+            method: AlphaSelectionMethod::Gcv,
+            min,
+            max,
+            count,
+        }
+    }
+
+    pub fn k_fold(folds: usize, min: f64, max: f64, count: usize) -> Self {
+        Self {
+            method: AlphaSelectionMethod::KFold(folds),
+            min,
+            max,
+            count,
+        }
+    }
+
+    // Checks the grid and the folds, the number of episodes is checked when they are known
+    pub fn validate(&self) -> Result<(), AlphaSearchError> {
+        // also rejects NaN
+        if !(self.min > 0. && self.max >= self.min && self.max.is_finite()) {
+            return Err(AlphaSearchError::InvalidRange {
+                min: self.min,
+                max: self.max,
+            });
+        }
+        if self.count == 0 {
+            return Err(AlphaSearchError::NoAlphas);
+        }
+        match self.method {
+            AlphaSelectionMethod::KFold(folds) if folds < 2 => {
+                Err(AlphaSearchError::TooFewFolds(folds))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The folds used for `episodes` episodes, there can't be more folds than episodes
+    pub fn folds(&self, episodes: usize) -> Result<Option<usize>, AlphaSearchError> {
+        self.validate()?;
+        match self.method {
+            AlphaSelectionMethod::Gcv => Ok(None),
+            AlphaSelectionMethod::KFold(_) if episodes < 2 => {
+                Err(AlphaSearchError::TooFewEpisodes(episodes))
+            }
+            AlphaSelectionMethod::KFold(folds) => Ok(Some(folds.min(episodes))),
+        }
+    }
+
+    pub fn alphas(&self) -> Result<Vec<f64>, AlphaSearchError> {
+        self.validate()?;
+        if self.count == 1 {
+            return Ok(vec![self.min]);
+        }
+        let (low, high) = (self.min.ln(), self.max.ln());
+        Ok((0..self.count)
+            .map(|i| (low + (high - low) * i as f64 / (self.count - 1) as f64).exp())
+            .collect())
+    }
+}
+
+// The validation error of every alpha that was tried, kept with the fitted readout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlphaCurve {
+    pub method: AlphaSelectionMethod,
+    pub alphas: Vec<f64>,
+    pub errors: Vec<f64>,
+    pub best_alpha: f64,
+}
+
+impl AlphaCurve {
+    // GCV(α) = MSE(α) / (1 - dof(α) / n)² is only defined while the degrees of freedom stay
+    // below the samples, the alphas without it are left out of the curve
+    pub fn gcv(
+        alphas: &[f64],
+        mse_and_dof: impl IntoIterator<Item = (f64, f64)>,
+        samples: usize,
+    ) -> Result<Self, AlphaSearchError> {
+        let n = samples as f64;
+        let (alphas, errors): (Vec<_>, Vec<_>) = alphas
+            .iter()
+            .zip(mse_and_dof)
+            .filter(|(_, (_, dof))| *dof < n)
+            .map(|(&alpha, (mse, dof))| (alpha, mse / (1. - dof / n).powi(2)))
+            .unzip();
+        if alphas.is_empty() {
+            return Err(AlphaSearchError::TooFewSamples(samples));
+        }
+        Ok(Self::new(AlphaSelectionMethod::Gcv, &alphas, errors))
+    }
+
+    pub fn new(method: AlphaSelectionMethod, alphas: &[f64], errors: Vec<f64>) -> Self {
+        assert_eq!(alphas.len(), errors.len(), "one error per alpha");
+        let best = errors
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .expect("no alphas to select from");
+        Self {
+            method,
+            alphas: alphas.to_vec(),
+            errors,
+            best_alpha: alphas[best],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::selection::{AlphaCurve, AlphaSearch, AlphaSearchError, AlphaSelectionMethod};
+
+    #[test]
+    fn alphas_are_log_spaced_between_the_bounds() {
+        let alphas = AlphaSearch::gcv(1e-4, 1., 5).alphas().unwrap();
+        assert_eq!(alphas.len(), 5);
+        for (alpha, expected) in alphas.iter().zip([1e-4, 1e-3, 1e-2, 1e-1, 1.]) {
+            assert!((alpha / expected - 1.).abs() < 1e-12);
+        }
+        assert_eq!(AlphaSearch::gcv(0.5, 2., 1).alphas().unwrap(), [0.5]);
+    }
+
+    #[test]
+    fn invalid_searches_are_rejected() {
+        let invalid = [
+            (
+                AlphaSearch::gcv(0., 1., 5),
+                AlphaSearchError::InvalidRange { min: 0., max: 1. },
+            ),
+            (
+                AlphaSearch::gcv(-1., 1., 5),
+                AlphaSearchError::InvalidRange { min: -1., max: 1. },
+            ),
+            (
+                AlphaSearch::gcv(2., 1., 5),
+                AlphaSearchError::InvalidRange { min: 2., max: 1. },
+            ),
+            (AlphaSearch::gcv(1e-3, 1., 0), AlphaSearchError::NoAlphas),
+            (
+                AlphaSearch::k_fold(0, 1e-3, 1., 5),
+                AlphaSearchError::TooFewFolds(0),
+            ),
+            (
+                AlphaSearch::k_fold(1, 1e-3, 1., 5),
+                AlphaSearchError::TooFewFolds(1),
+            ),
+        ];
+        for (search, expected) in invalid {
+            assert_eq!(search.alphas(), Err(expected));
+        }
+        let k_fold = AlphaSearch::k_fold(5, 1e-3, 1., 5);
+        assert_eq!(k_fold.folds(1), Err(AlphaSearchError::TooFewEpisodes(1)));
+        assert_eq!(k_fold.folds(3), Ok(Some(3)));
+        assert_eq!(AlphaSearch::gcv(1e-3, 1., 5).folds(1), Ok(None));
+    }
+
+    #[test]
+    fn gcv_leaves_out_alphas_without_enough_samples() {
+        let curve = AlphaCurve::gcv(&[0.1, 1., 10.], [(0., 4.), (1., 3.), (2., 2.)], 4).unwrap();
+        assert_eq!(curve.alphas, [1., 10.]);
+        assert!(curve.errors.iter().all(|e| e.is_finite()));
+        assert_eq!(
+            AlphaCurve::gcv(&[0.1], [(0., 4.)], 4),
+            Err(AlphaSearchError::TooFewSamples(4))
+        );
+    }
+
+    #[test]
+    fn curve_keeps_the_lowest_error() {
+        let curve = AlphaCurve::new(AlphaSelectionMethod::Gcv, &[0.1, 1., 10.], vec![3., 1., 2.]);
+        assert_eq!(curve.best_alpha, 1.);
+    }
+}
@@ -0,0 +1,411 @@
+// Controllers are selected by kind. Every kind knows how to build an `Arc<dyn FlightController>`,
+// either from scratch (betaflight, null) or by deserializing a controller the loader stored under
+// that kind. The loaders, the `SimContext` and the UI only go through the registry, so a new
+// controller only needs a `register` call instead of new enum variants and loader methods.
+
+use bf_controller::BFController;
+use flight_controller::{controllers::null_controller::NullController, FlightController};
+use loaders::{LoaderError, LoaderTrait, IZHIKEVICH_KIND, RESERVOIR_KIND};
+use res_controller::{
+    controllers::{
+        esn::{
+            adapting::{AdaptationSignal, AdaptingDroneRc, RlsParameters},
+            NonAdaptingDroneRc,
+        },
+        izhikevich_controller::IzhikevichController,
+    },
+    input_mapping::ReservoirInput,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{marker::PhantomData, sync::Arc};
+
+pub const BETAFLIGHT_KIND: &str = "betaflight";
+pub const NULL_KIND: &str = "null";
+pub const ADAPTIVE_KIND: &str = "adaptive";
+pub const ADAPTIVE_RATE_KIND: &str = "adaptive_rate";
+
+pub trait ControllerKind: Send + Sync {
+    // Unique name, used as the storage key by the loaders and on the command line
+    fn kind(&self) -> &'static str;
+
+    // Name shown in the UI
+    fn label(&self) -> &'static str;
+
+    // Stored kinds are loaded by id, the others have no parameters and no ids
+    fn is_stored(&self) -> bool;
+
+    // Names of the reservoir inputs the controllers of this kind are trained on, recorded when
+    // saving and checked when loading
+    fn input_features(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // The kind the loader stores the controllers under, when built from another kind's artifacts
+    fn artifact_kind(&self) -> &'static str {
+        self.kind()
+    }
+
+    fn build(
+        &self,
+        loader: &mut dyn LoaderTrait,
+        controller_id: Option<&str>,
+    ) -> Result<Arc<dyn FlightController>, LoaderError>;
+}
+
+// A controller without parameters, built by a constructor
+pub struct BuiltinKind {
+    kind: &'static str,
+    label: &'static str,
+    constructor: fn() -> Arc<dyn FlightController>,
+}
+
+impl BuiltinKind {
+    pub fn new(
+        kind: &'static str,
+        label: &'static str,
+        constructor: fn() -> Arc<dyn FlightController>,
+    ) -> Self {
+        Self {
+            kind,
+            label,
+            constructor,
+        }
+    }
+}
+
+impl ControllerKind for BuiltinKind {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn label(&self) -> &'static str {
+        self.label
+    }
+
+    fn is_stored(&self) -> bool {
+        false
+    }
+
+    fn build(
+        &self,
+        _loader: &mut dyn LoaderTrait,
+        _controller_id: Option<&str>,
+    ) -> Result<Arc<dyn FlightController>, LoaderError> {
+        Ok((self.constructor)())
+    }
+}
+
+// A controller stored by the loader, wrapped in a `ControllerArtifact`
+pub struct StoredKind<T> {
+    kind: &'static str,
+    label: &'static str,
+    input_features: &'static [&'static str],
+    controller: PhantomData<fn() -> T>,
+}
+
+impl<T> StoredKind<T> {
+    pub fn new(kind: &'static str, label: &'static str) -> Self {
+        Self {
+            kind,
+            label,
+            input_features: &[],
+            controller: PhantomData,
+        }
+    }
+
+    pub fn with_input_features(mut self, input_features: &'static [&'static str]) -> Self {
+        self.input_features = input_features;
+        self
+    }
+}
+
+impl<T: FlightController + DeserializeOwned> ControllerKind for StoredKind<T> {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn label(&self) -> &'static str {
+        self.label
+    }
+
+    fn is_stored(&self) -> bool {
+        true
+    }
+
+    fn input_features(&self) -> &'static [&'static str] {
+        self.input_features
+    }
+
+    fn build(
+        &self,
+        loader: &mut dyn LoaderTrait,
+        controller_id: Option<&str>,
+    ) -> Result<Arc<dyn FlightController>, LoaderError> {
+        let controller_id = controller_id.ok_or(LoaderError::NotFound(format!(
+            "{} controller without id",
+            self.kind
+        )))?;
+        let artifact = loader.load_artifact(self.kind, controller_id)?;
+        artifact.check_input_features(self.input_features)?;
+        let controller: T = artifact.controller()?;
+        Ok(Arc::new(controller))
+    }
+}
+
+// Starts from a stored reservoir controller and keeps learning its readout in flight, see
+// `AdaptingDroneRc`. The ids are those of the reservoir controllers.
+pub struct AdaptiveKind {
+    kind: &'static str,
+    label: &'static str,
+    signal: fn() -> AdaptationSignal,
+}
+
+impl AdaptiveKind {
+    pub fn new(kind: &'static str, label: &'static str, signal: fn() -> AdaptationSignal) -> Self {
+        Self {
+            kind,
+            label,
+            signal,
+        }
+    }
+}
+
+impl ControllerKind for AdaptiveKind {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn label(&self) -> &'static str {
+        self.label
+    }
+
+    fn is_stored(&self) -> bool {
+        true
+    }
+
+    fn input_features(&self) -> &'static [&'static str] {
+        &ReservoirInput::FIELD_NAMES
+    }
+
+    fn artifact_kind(&self) -> &'static str {
+        RESERVOIR_KIND
+    }
+
+    fn build(
+        &self,
+        loader: &mut dyn LoaderTrait,
+        controller_id: Option<&str>,
+    ) -> Result<Arc<dyn FlightController>, LoaderError> {
+        let controller_id = controller_id.ok_or(LoaderError::NotFound(format!(
+            "{} controller without id",
+            self.kind
+        )))?;
+        let artifact = loader.load_artifact(RESERVOIR_KIND, controller_id)?;
+        artifact.check_input_features(self.input_features())?;
+        let base: NonAdaptingDroneRc = artifact.controller()?;
+        Ok(Arc::new(AdaptingDroneRc::new(
+            base,
+            (self.signal)(),
+            RlsParameters::default(),
+        )))
+    }
+}
+
+#[derive(Clone)]
+pub struct ControllerRegistry {
+    kinds: Vec<Arc<dyn ControllerKind>>,
+}
+
+impl Default for ControllerRegistry {
+    fn default() -> Self {
+        let mut registry = Self { kinds: vec![] };
+        registry.register(BuiltinKind::new(BETAFLIGHT_KIND, "Betaflight", || {
+            Arc::new(BFController::default())
+        }));
+        registry.register(
+            StoredKind::<NonAdaptingDroneRc>::new(RESERVOIR_KIND, "Reservoir controller")
+                .with_input_features(&ReservoirInput::FIELD_NAMES),
+        );
+        registry.register(
+            StoredKind::<IzhikevichController>::new(IZHIKEVICH_KIND, "Izhikevich controller")
+                .with_input_features(&ReservoirInput::FIELD_NAMES),
+        );
+        registry.register(BuiltinKind::new(NULL_KIND, "Null", || {
+            Arc::new(NullController::default())
+        }));
+        registry.register(AdaptiveKind::new(
+            ADAPTIVE_KIND,
+            "Adaptive reservoir (Betaflight teacher)",
+            || AdaptationSignal::Teacher(Arc::new(BFController::default())),
+        ));
+        registry.register(AdaptiveKind::new(
+            ADAPTIVE_RATE_KIND,
+            "Adaptive reservoir (rate error)",
+            AdaptationSignal::rate_error,
+        ));
+        registry
+    }
+}
+
+impl ControllerRegistry {
+    // Replaces a kind with the same name
+    pub fn register(&mut self, kind: impl ControllerKind + 'static) {
+        self.kinds.retain(|k| k.kind() != kind.kind());
+        self.kinds.push(Arc::new(kind));
+    }
+
+    pub fn get(&self, kind: &str) -> Option<&dyn ControllerKind> {
+        self.kinds.iter().find(|k| k.kind() == kind).map(|k| &**k)
+    }
+
+    // In registration order
+    pub fn kinds(&self) -> impl Iterator<Item = &dyn ControllerKind> {
+        self.kinds.iter().map(|k| &**k)
+    }
+
+    pub fn build(
+        &self,
+        loader: &mut dyn LoaderTrait,
+        controller: &ControllerType,
+    ) -> Result<Arc<dyn FlightController>, LoaderError> {
+        let kind = self
+            .get(&controller.kind)
+            .ok_or_else(|| LoaderError::NotFound(controller.kind.clone()))?;
+        kind.build(loader, controller.id.as_deref())
+    }
+}
+
+// A controller kind and, for stored kinds, the id of the controller. Serialized as written on the
+// command line.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ControllerType {
+    pub kind: String,
+    pub id: Option<String>,
+}
+
+impl Default for ControllerType {
+    fn default() -> Self {
+        Self::builtin(BETAFLIGHT_KIND)
+    }
+}
+
+impl ControllerType {
+    pub fn builtin(kind: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            id: None,
+        }
+    }
+
+    pub fn stored(kind: impl Into<String>, id: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            id: Some(id.into()),
+        }
+    }
+
+    pub fn betaflight() -> Self {
+        Self::builtin(BETAFLIGHT_KIND)
+    }
+
+    pub fn null() -> Self {
+        Self::builtin(NULL_KIND)
+    }
+
+    pub fn reservoir(id: impl Into<String>) -> Self {
+        Self::stored(RESERVOIR_KIND, id)
+    }
+
+    pub fn izhikevich(id: impl Into<String>) -> Self {
+        Self::stored(IZHIKEVICH_KIND, id)
+    }
+}
+
+// `<kind>` or `<kind>:<id>`, as used on the command line
+impl std::str::FromStr for ControllerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s.is_empty() => Err("empty controller".into()),
+            None => Ok(Self::builtin(s)),
+            Some((kind, id)) => Ok(Self::stored(kind, id)),
+        }
+    }
+}
+
+impl TryFrom<String> for ControllerType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ControllerType> for String {
+    fn from(controller: ControllerType) -> Self {
+        controller.to_string()
+    }
+}
+
+impl std::fmt::Display for ControllerType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.id {
+            Some(id) => write!(f, "{}:{id}", self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::controller_registry::{ControllerRegistry, ControllerType, StoredKind};
+    use flight_controller::{FlightController, FlightControllerUpdate, MotorInput};
+    use loaders::{
+        artifact::ControllerArtifact, sqlite_loader::SqliteLoader, LoaderError, LoaderTrait,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+
+    #[derive(Serialize, Deserialize)]
+    struct ConstantController {
+        input: f64,
+    }
+
+    impl FlightController for ConstantController {
+        fn init(&self) {}
+        fn update(&self, _: f64, _: FlightControllerUpdate) -> MotorInput {
+            MotorInput {
+                input: [self.input; 4],
+            }
+        }
+        fn scheduler_delta(&self) -> Duration {
+            Duration::from_millis(1)
+        }
+    }
+
+    #[test]
+    fn registered_kind_is_loaded_by_id() {
+        let mut registry = ControllerRegistry::default();
+        registry.register(StoredKind::<ConstantController>::new(
+            "constant", "Constant",
+        ));
+        let mut loader = SqliteLoader::open_in_memory().unwrap();
+        let artifact =
+            ControllerArtifact::new("constant", &ConstantController { input: 0.5 }).unwrap();
+        loader.insert_artifact("half", &artifact).unwrap();
+
+        assert_eq!(loader.get_controller_ids("constant").unwrap(), ["half"]);
+        let controller: ControllerType = "constant:half".parse().unwrap();
+        let built = registry.build(&mut loader, &controller).unwrap();
+        let update = built.update(0., FlightControllerUpdate::default());
+        assert_eq!(update.input, [0.5; 4]);
+
+        let unknown = ControllerType::stored("unknown", "half");
+        assert!(matches!(
+            registry.build(&mut loader, &unknown),
+            Err(LoaderError::NotFound(kind)) if kind == "unknown"
+        ));
+    }
+}
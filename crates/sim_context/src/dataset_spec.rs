@@ -0,0 +1,297 @@
+// A data set described in toml instead of code. Every group of episodes has its own duration and
+// stick input generators, `test_ratio` and `validation_ratio` of each group are held out:
+//
+//   dataset_id = "chirps"
+//   config = "7in_4s_drone"
+//   controller = "betaflight"
+//   seed = 0
+//   test_ratio = 0.2
+//   validation_ratio = 0.1
+//
+//   [[episodes]]
+//   count = 20
+//   duration = 5.0
+//   generator.throttle = "brownian"
+//   generator.roll = { chirp = { amplitude = 0.5, offset = 0.0, start_hz = 0.1, end_hz = 10.0 } }
+
+use crate::{
+    input_gen::{store_episodes, Episode, InputGenerator, Teacher},
+    ControllerType, LoaderType,
+};
+use loaders::{DataSetIndex, LoaderError, Split};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSetSpec {
+    pub dataset_id: String,
+    // drone config id
+    #[serde(default = "default_config")]
+    pub config: String,
+    // the teacher, `<kind>` or `<kind>:<id>`
+    #[serde(default)]
+    pub controller: ControllerType,
+    #[serde(default)]
+    pub seed: u64,
+    // fractions of every episode group held out for testing and for tuning hyperparameters
+    #[serde(default)]
+    pub test_ratio: f64,
+    #[serde(default)]
+    pub validation_ratio: f64,
+    pub episodes: Vec<EpisodeGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EpisodeGroup {
+    pub count: usize,
+    // seconds
+    pub duration: f64,
+    #[serde(default)]
+    pub generator: InputGenerator,
+}
+
+fn default_config() -> String {
+    "7in_4s_drone".into()
+}
+
+impl DataSetSpec {
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        let spec: Self = toml::from_str(content)?;
+        spec.validate().map_err(serde::de::Error::custom)?;
+        Ok(spec)
+    }
+
+    // Values that parse but can't be built, checked again by `build` for specs made in code
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, ratio) in [
+            ("test_ratio", self.test_ratio),
+            ("validation_ratio", self.validation_ratio),
+        ] {
+            if !(0. ..=1.).contains(&ratio) {
+                return Err(format!("{name} {ratio} is not between 0 and 1"));
+            }
+        }
+        for group in &self.episodes {
+            if group.count == 0 {
+                return Err("episode group without episodes".into());
+            }
+            if let Err(err) = Duration::try_from_secs_f64(group.duration) {
+                return Err(format!("episode duration {}: {err}", group.duration));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    // The episodes with their split, ordered by split. Seeds are drawn group by group, so adding
+    // a group keeps the episodes of the previous ones.
+    pub fn episodes(&self) -> Vec<(Split, Episode)> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut episodes = vec![];
+        let mut counts = HashMap::new();
+        for group in &self.episodes {
+            let count_of = |ratio: f64| (group.count as f64 * ratio).round() as usize;
+            let test_count = count_of(self.test_ratio).min(group.count);
+            let validation_count = count_of(self.validation_ratio).min(group.count - test_count);
+            let training_count = group.count - test_count - validation_count;
+            for ep in 0..group.count {
+                let split = match ep {
+                    ep if ep < training_count => Split::Train,
+                    ep if ep < training_count + validation_count => Split::Validation,
+                    _ => Split::Test,
+                };
+                let position = counts.entry(split).or_insert(0);
+                episodes.push((
+                    split,
+                    Episode {
+                        simulation_id: format!(
+                            "{}/{}{position}",
+                            self.dataset_id,
+                            split.file_prefix()
+                        ),
+                        seed: rng.r#gen(),
+                        duration: Duration::from_secs_f64(group.duration),
+                        generator: group.generator.clone(),
+                    },
+                ));
+                *position += 1;
+            }
+        }
+        episodes.sort_by_key(|(split, _)| *split);
+        episodes
+    }
+
+    // Simulates every episode with the drone and controller resolved through `loader` and stores
+    // them through it as they finish, together with the spec. `progress` is called for every
+    // stored episode, see `store_episodes`.
+    pub fn build(
+        &self,
+        loader: LoaderType,
+        data_root: PathBuf,
+        workers: usize,
+        progress: impl Fn(&str, usize, usize) + Sync,
+    ) -> Result<DataSetIndex, LoaderError> {
+        self.validate().map_err(LoaderError::InvalidSpec)?;
+        let spec = self
+            .to_toml()
+            .map_err(|err| LoaderError::InvalidSpec(err.to_string()))?;
+        let teacher = Teacher {
+            config_id: self.config.clone(),
+            controller: self.controller.clone(),
+            loader,
+            data_root,
+        };
+        store_episodes(
+            &self.dataset_id,
+            &self.episodes(),
+            &teacher,
+            workers,
+            Some(spec),
+            progress,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        dataset_spec::DataSetSpec,
+        input_gen::{InputGenerationMethod, InputGenerator},
+        ControllerType, LoaderType,
+    };
+    use drone::default_drone::default_7in_4s_drone;
+    use loaders::{file_loader::FileLoader, LoaderError, LoaderTrait, Split};
+    use std::{fs, sync::Mutex, time::Duration};
+
+    const SPEC: &str = r#"
+dataset_id = "mixed"
+controller = "null"
+seed = 3
+test_ratio = 0.25
+
+[[episodes]]
+count = 4
+duration = 0.1
+generator.throttle = "brownian"
+generator.roll = { chirp = { amplitude = 0.5, offset = 0.0, start_hz = 0.1, end_hz = 10.0 } }
+
+[[episodes]]
+count = 2
+duration = 0.2
+generator.pitch = { sum = [{ uniform = 0.1 }, { prbs = { amplitude = 0.2, offset = 0.0, hold_ms = 10 } }] }
+"#;
+
+    #[test]
+    fn parses_and_writes_back() {
+        let spec = DataSetSpec::from_toml(SPEC).unwrap();
+        assert_eq!(spec.config, "7in_4s_drone");
+        assert_eq!(spec.controller, ControllerType::null());
+        assert_eq!(spec.episodes.len(), 2);
+        assert_eq!(
+            spec.episodes[0].generator,
+            InputGenerator::default()
+                .set_throttle(InputGenerationMethod::Brownian)
+                .set_roll(InputGenerationMethod::Chirp {
+                    amplitude: 0.5,
+                    offset: 0.,
+                    start_hz: 0.1,
+                    end_hz: 10.,
+                })
+        );
+        let written = spec.to_toml().unwrap();
+        assert_eq!(DataSetSpec::from_toml(&written).unwrap(), spec);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(DataSetSpec::from_toml(&format!("{SPEC}\nepisode_count = 3")).is_err());
+    }
+
+    #[test]
+    fn rejects_specs_that_cant_be_built() {
+        for (from, to) in [
+            ("duration = 0.1", "duration = -0.1"),
+            ("duration = 0.1", "duration = nan"),
+            ("duration = 0.1", "duration = 1e30"),
+            ("count = 4", "count = 0"),
+            ("test_ratio = 0.25", "test_ratio = 1.5"),
+            ("test_ratio = 0.25", "validation_ratio = -0.1"),
+        ] {
+            assert!(
+                DataSetSpec::from_toml(&SPEC.replace(from, to)).is_err(),
+                "{to}"
+            );
+        }
+
+        let mut spec = DataSetSpec::from_toml(SPEC).unwrap();
+        spec.episodes[0].duration = f64::NAN;
+        assert!(matches!(
+            spec.build(LoaderType::File, std::env::temp_dir(), 1, |_, _, _| ()),
+            Err(LoaderError::InvalidSpec(_))
+        ));
+    }
+
+    #[test]
+    fn splits_every_group() {
+        let spec = DataSetSpec::from_toml(&SPEC.replace(
+            "test_ratio = 0.25",
+            "test_ratio = 0.25\nvalidation_ratio = 0.25",
+        ))
+        .unwrap();
+        let ids = |split: Split| {
+            spec.episodes()
+                .into_iter()
+                .filter(|(s, _)| *s == split)
+                .map(|(_, ep)| ep.simulation_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(Split::Train), ["mixed/training_0", "mixed/training_1"]);
+        assert_eq!(
+            ids(Split::Validation),
+            ["mixed/validation_0", "mixed/validation_1"]
+        );
+        assert_eq!(ids(Split::Test), ["mixed/testing_0", "mixed/testing_1"]);
+
+        let root = std::env::temp_dir().join("quad_dataset_spec_build_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("drones")).unwrap();
+        fs::write(
+            root.join("drones/7in_4s_drone.json"),
+            serde_json::to_string(&default_7in_4s_drone()).unwrap(),
+        )
+        .unwrap();
+        let progress = Mutex::new(vec![]);
+        let index = spec
+            .build(LoaderType::File, root.clone(), 2, |_, stored, total| {
+                progress.lock().unwrap().push((stored, total))
+            })
+            .unwrap();
+        let mut progress = progress.into_inner().unwrap();
+        progress.sort();
+        assert_eq!(
+            progress,
+            (1..=6).map(|stored| (stored, 6)).collect::<Vec<_>>()
+        );
+        assert_eq!(index.episodes.len(), 6);
+
+        // every episode was written through the loader
+        let dataset = FileLoader::new(root.clone())
+            .load_data_set("mixed")
+            .unwrap();
+        assert_eq!(dataset.validation_data.len(), 2);
+        assert_eq!(dataset.test_data[1].simulation_id, "mixed/testing_1");
+        // the second group runs twice as long
+        assert!(dataset.test_data[1].steps.len() > dataset.test_data[0].steps.len());
+        let metadata = &dataset.episode_metadata["mixed/testing_1"];
+        assert_eq!(metadata.duration, Some(Duration::from_millis(200)));
+        assert!(metadata.seed.is_some() && metadata.generator.is_some());
+        assert!(dataset.spec.is_some());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,22 +1,36 @@
-use crate::SimContext;
+use crate::{ControllerType, LoaderType, SimContext};
 use flight_controller::Channels;
-use rand::{distributions::Bernoulli, prelude::Distribution, thread_rng};
-use std::time::Duration;
+use loaders::{DataSetIndex, EpisodeMetadata, EpisodeRef, LoaderError, LoaderTrait, Split};
+use loggers::{memory_logger::MemoryLogger, storage::data_root, FlightLog};
+use rand::{distributions::Bernoulli, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
-fn generate_brownian(milisecs: u128) -> Vec<f64> {
+fn generate_brownian(rng: &mut StdRng, milisecs: u128) -> Vec<f64> {
     let bernoulli = Bernoulli::new(0.5).unwrap();
-    let mut rng = thread_rng();
-    let axis = (0..milisecs).fold((0., 0., vec![]), |acc, _| {
+    let axis = (0..milisecs).fold((0_f64, 0., vec![]), |acc, _| {
         let (mut pos, mut vel, mut all_pos) = acc;
-        vel += if bernoulli.sample(&mut rng) {
+        vel += if bernoulli.sample(rng) {
             0.0001
         } else {
             -0.0001
         };
         pos += vel;
+        // stop at the edges, otherwise the stick sticks to them until the velocity turns around
         if !(-1. ..=1.).contains(&pos) {
-            // why do we need this?
-            pos = f64::clamp(pos, -1000., 1000.);
             vel = 0.;
         }
         pos = pos.clamp(-1., 1.);
@@ -26,25 +40,199 @@ fn generate_brownian(milisecs: u128) -> Vec<f64> {
     axis.2
 }
 
+fn generate_steps(
+    rng: &mut StdRng,
+    milisecs: usize,
+    (min, max): (f64, f64),
+    (min_hold_ms, max_hold_ms): (u64, u64),
+) -> Vec<f64> {
+    let mut values = Vec::with_capacity(milisecs);
+    while values.len() < milisecs {
+        let level = rng.gen_range(min..=max);
+        let hold = rng.gen_range(min_hold_ms..=max_hold_ms).max(1);
+        values.extend(std::iter::repeat_n(level, hold as usize));
+    }
+    values.truncate(milisecs);
+    values
+}
+
+fn generate_chirp(
+    milisecs: usize,
+    amplitude: f64,
+    offset: f64,
+    start_hz: f64,
+    end_hz: f64,
+) -> Vec<f64> {
+    let total = milisecs as f64 / 1000.;
+    (0..milisecs)
+        .map(|ms| {
+            let t = ms as f64 / 1000.;
+            let phase = start_hz * t + (end_hz - start_hz) * t * t / (2. * total);
+            offset + amplitude * (2. * PI * phase).sin()
+        })
+        .collect()
+}
+
+// Maximum length sequence of a 15 bit LFSR (x^15 + x^14 + 1), starting from a random state
+fn generate_prbs(
+    rng: &mut StdRng,
+    milisecs: usize,
+    amplitude: f64,
+    offset: f64,
+    hold_ms: u64,
+) -> Vec<f64> {
+    let mut state: u16 = rng.gen_range(1..0x8000);
+    let mut values = Vec::with_capacity(milisecs);
+    while values.len() < milisecs {
+        let bit = ((state >> 14) ^ (state >> 13)) & 1;
+        state = ((state << 1) | bit) & 0x7fff;
+        let level = if bit == 1 {
+            offset + amplitude
+        } else {
+            offset - amplitude
+        };
+        values.extend(std::iter::repeat_n(level, hold_ms.max(1) as usize));
+    }
+    values.truncate(milisecs);
+    values
+}
+
+// Euler-Maruyama with 1ms steps, starting at the mean
+fn generate_ornstein_uhlenbeck(
+    rng: &mut StdRng,
+    milisecs: usize,
+    mean: f64,
+    theta: f64,
+    sigma: f64,
+) -> Vec<f64> {
+    let dt = 0.001;
+    let mut x = mean;
+    (0..milisecs)
+        .map(|_| {
+            let noise: f64 = rng.sample(StandardNormal);
+            x += theta * (mean - x) * dt + sigma * dt.sqrt() * noise;
+            x
+        })
+        .collect()
+}
+
+fn generate_ramp(milisecs: usize, from: f64, to: f64) -> Vec<f64> {
+    let last = milisecs.saturating_sub(1).max(1) as f64;
+    (0..milisecs)
+        .map(|ms| from + (to - from) * ms as f64 / last)
+        .collect()
+}
+
+// Stick input of a single axis, one value per ms. Values leaving [-1, 1] are clamped once all
+// the axes are generated, so methods can be summed and scaled freely.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InputGenerationMethod {
     Uniform(f64),
     Brownian,
+    // Random levels in [min, max], each held for a random time in [min_hold_ms, max_hold_ms]
+    Steps {
+        min: f64,
+        max: f64,
+        min_hold_ms: u64,
+        max_hold_ms: u64,
+    },
+    // Sine sweeping linearly from start_hz to end_hz over the whole episode
+    Chirp {
+        amplitude: f64,
+        offset: f64,
+        start_hz: f64,
+        end_hz: f64,
+    },
+    // Pseudo random binary sequence switching between offset +- amplitude every hold_ms
+    Prbs {
+        amplitude: f64,
+        offset: f64,
+        hold_ms: u64,
+    },
+    // Mean reverting noise, theta and sigma are per second
+    OrnsteinUhlenbeck {
+        mean: f64,
+        theta: f64,
+        sigma: f64,
+    },
+    // Linear from `from` to `to` over the whole episode
+    Ramp {
+        from: f64,
+        to: f64,
+    },
+    // Recorded stick input, one value per ms. The last value is held if the episode is longer.
+    Recorded(Vec<f64>),
+    Sum(Vec<InputGenerationMethod>),
+    Scaled {
+        method: Box<InputGenerationMethod>,
+        gain: f64,
+        offset: f64,
+    },
 }
 
 impl InputGenerationMethod {
-    fn to_values(&self, milisecs: u128) -> Vec<f64> {
+    fn to_values(&self, rng: &mut StdRng, milisecs: u128) -> Vec<f64> {
+        let len = milisecs as usize;
         match self {
-            Self::Uniform(val) => vec![*val; milisecs as usize],
-            Self::Brownian => generate_brownian(milisecs),
+            Self::Uniform(val) => vec![*val; len],
+            Self::Brownian => generate_brownian(rng, milisecs),
+            Self::Steps {
+                min,
+                max,
+                min_hold_ms,
+                max_hold_ms,
+            } => generate_steps(rng, len, (*min, *max), (*min_hold_ms, *max_hold_ms)),
+            Self::Chirp {
+                amplitude,
+                offset,
+                start_hz,
+                end_hz,
+            } => generate_chirp(len, *amplitude, *offset, *start_hz, *end_hz),
+            Self::Prbs {
+                amplitude,
+                offset,
+                hold_ms,
+            } => generate_prbs(rng, len, *amplitude, *offset, *hold_ms),
+            Self::OrnsteinUhlenbeck { mean, theta, sigma } => {
+                generate_ornstein_uhlenbeck(rng, len, *mean, *theta, *sigma)
+            }
+            Self::Ramp { from, to } => generate_ramp(len, *from, *to),
+            Self::Recorded(values) => {
+                let mut values = values.clone();
+                let last = values.last().copied().unwrap_or_default();
+                values.resize(len, last);
+                values
+            }
+            Self::Sum(methods) => methods.iter().fold(vec![0.; len], |mut sum, method| {
+                for (acc, val) in sum.iter_mut().zip(method.to_values(rng, milisecs)) {
+                    *acc += val;
+                }
+                sum
+            }),
+            Self::Scaled {
+                method,
+                gain,
+                offset,
+            } => method
+                .to_values(rng, milisecs)
+                .into_iter()
+                .map(|val| offset + gain * val)
+                .collect(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct InputGenerator {
     throttle: InputGenerationMethod,
     yaw: InputGenerationMethod,
     pitch: InputGenerationMethod,
     roll: InputGenerationMethod,
+    // data set specs seed every episode themselves
+    #[serde(skip)]
+    seed: u64,
 }
 
 impl Default for InputGenerator {
@@ -54,101 +242,490 @@ impl Default for InputGenerator {
             yaw: InputGenerationMethod::Uniform(0.),
             pitch: InputGenerationMethod::Uniform(0.),
             roll: InputGenerationMethod::Uniform(0.),
+            seed: 0,
         }
     }
 }
 
 impl InputGenerator {
-    fn set_throttle(self, throttle: InputGenerationMethod) -> Self {
+    pub fn set_throttle(self, throttle: InputGenerationMethod) -> Self {
         Self { throttle, ..self }
     }
 
-    fn set_yaw(self, yaw: InputGenerationMethod) -> Self {
+    pub fn set_yaw(self, yaw: InputGenerationMethod) -> Self {
         Self { yaw, ..self }
     }
 
-    fn set_pitch(self, pitch: InputGenerationMethod) -> Self {
+    pub fn set_pitch(self, pitch: InputGenerationMethod) -> Self {
         Self { pitch, ..self }
     }
 
-    fn set_roll(self, roll: InputGenerationMethod) -> Self {
+    pub fn set_roll(self, roll: InputGenerationMethod) -> Self {
         Self { roll, ..self }
     }
 
-    fn generate(&self, duration: Duration) -> Vec<Channels> {
+    pub fn set_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    // Replays the stick input of a recorded flight on all axes, resampled to one value per ms
+    pub fn from_flight_log(flight_log: &FlightLog) -> Self {
+        let steps = &flight_log.steps;
+        let milisecs = steps.last().map_or(0, |step| step.duration.as_millis() + 1);
+        let mut idx = 0;
+        let channels = (0..milisecs)
+            .map(|ms| {
+                while idx + 1 < steps.len() && steps[idx + 1].duration.as_millis() <= ms {
+                    idx += 1;
+                }
+                steps[idx].channels
+            })
+            .collect::<Vec<_>>();
+        let axis = |f: fn(&Channels) -> f64| {
+            InputGenerationMethod::Recorded(channels.iter().map(f).collect())
+        };
+        Self {
+            throttle: axis(|c| c.throttle),
+            yaw: axis(|c| c.yaw),
+            pitch: axis(|c| c.pitch),
+            roll: axis(|c| c.roll),
+            seed: 0,
+        }
+    }
+
+    pub fn generate(&self, duration: Duration) -> Vec<Channels> {
+        self.generate_seeded(duration, self.seed)
+    }
+
+    // Same as generate, but overrides the seed so that a single generator can produce many
+    // different episodes
+    pub fn generate_seeded(&self, duration: Duration, seed: u64) -> Vec<Channels> {
+        let mut rng = StdRng::seed_from_u64(seed);
         let milisecs = duration.as_millis();
-        let throttle = self.throttle.to_values(milisecs);
-        let yaw = self.yaw.to_values(milisecs);
-        let pitch = self.pitch.to_values(milisecs);
-        let roll = self.roll.to_values(milisecs);
+        let throttle = self.throttle.to_values(&mut rng, milisecs);
+        let yaw = self.yaw.to_values(&mut rng, milisecs);
+        let pitch = self.pitch.to_values(&mut rng, milisecs);
+        let roll = self.roll.to_values(&mut rng, milisecs);
 
         let mut channels = Vec::with_capacity(milisecs as usize);
         for ms in 0..milisecs {
             let idx = ms as usize;
             channels.push(Channels {
-                throttle: throttle[idx],
-                yaw: yaw[idx],
-                pitch: pitch[idx],
-                roll: roll[idx],
+                throttle: throttle[idx].clamp(-1., 1.),
+                yaw: yaw[idx].clamp(-1., 1.),
+                pitch: pitch[idx].clamp(-1., 1.),
+                roll: roll[idx].clamp(-1., 1.),
             });
         }
         channels
     }
 }
 
+// Every betaflight instance is loaded into its own link map namespace and glibc only has 16 of
+// them, one of which is the main program
+const MAX_WORKERS: usize = 15;
+
+// Everything but the stick input that is shared by the episodes of a data set
+#[derive(Clone)]
+pub struct Teacher {
+    pub config_id: String,
+    pub controller: ControllerType,
+    pub loader: LoaderType,
+    pub data_root: PathBuf,
+}
+
+impl Default for Teacher {
+    fn default() -> Self {
+        Self {
+            config_id: "7in_4s_drone".into(),
+            controller: ControllerType::betaflight(),
+            loader: LoaderType::File,
+            data_root: data_root(),
+        }
+    }
+}
+
+impl Teacher {
+    fn context(&self, seed: u64) -> Result<SimContext, LoaderError> {
+        let mut context = SimContext {
+            seed,
+            config_id: Some(self.config_id.clone()),
+            data_root: self.data_root.clone(),
+            ..Default::default()
+        };
+        context.set_loader(&self.loader)?;
+        context.set_controller(self.controller.clone())?;
+        Ok(context)
+    }
+
+    // Reports a missing drone or controller once instead of panicking in every worker
+    fn check(&self) -> Result<(), LoaderError> {
+        self.context(0)?.load_simulator(&self.config_id)?;
+        Ok(())
+    }
+
+    // The loader the drone and controller are resolved through, which also stores the episodes
+    fn loader(&self) -> Result<Arc<Mutex<dyn LoaderTrait>>, LoaderError> {
+        let mut context = SimContext {
+            data_root: self.data_root.clone(),
+            ..Default::default()
+        };
+        context.set_loader(&self.loader)?;
+        Ok(context.loader)
+    }
+}
+
+// A single episode of a data set, the seed drives both the stick input and the drone noise
+#[derive(Debug, Clone)]
+pub struct Episode {
+    pub simulation_id: String,
+    pub seed: u64,
+    pub duration: Duration,
+    pub generator: InputGenerator,
+}
+
+impl Episode {
+    pub fn metadata(&self) -> EpisodeMetadata {
+        EpisodeMetadata {
+            seed: Some(self.seed),
+            duration: Some(self.duration),
+            generator: serde_json::to_value(&self.generator).ok(),
+        }
+    }
+}
+
+// Every episode gets its own seed drawn from `seed`. Building with the same seed reproduces the
+// same data set, independent of the number of workers. `workers == 0` uses all cores.
 pub fn build_data_set(
     data_set_id: String,
     training_duration: Duration,
     training_size: usize,
     test_size: usize,
-) {
-    let mut context = SimContext::default();
-    context.set_controller(crate::ControllerType::Betafligt);
-    let input_generator = InputGenerator::default()
+    seed: u64,
+    workers: usize,
+) -> Result<(), LoaderError> {
+    let generator = InputGenerator::default()
         .set_throttle(InputGenerationMethod::Brownian)
         .set_yaw(InputGenerationMethod::Brownian)
         .set_pitch(InputGenerationMethod::Brownian)
         .set_roll(InputGenerationMethod::Brownian);
-    context.set_loader(&crate::LoaderType::File);
-    let training_inputs = (0..training_size)
-        .map(|_| input_generator.generate(training_duration))
+    let episodes = data_set_episodes(&data_set_id, training_size, test_size, seed)
+        .into_iter()
+        .enumerate()
+        .map(|(ep, (simulation_id, seed))| {
+            let split = match ep < training_size {
+                true => Split::Train,
+                false => Split::Test,
+            };
+            let episode = Episode {
+                simulation_id,
+                seed,
+                duration: training_duration,
+                generator: generator.clone(),
+            };
+            (split, episode)
+        })
+        .collect::<Vec<_>>();
+    store_episodes(
+        &data_set_id,
+        &episodes,
+        &Teacher::default(),
+        workers,
+        None,
+        |_, _, _| {},
+    )?;
+    Ok(())
+}
+
+// Simulation ids and seeds of the training episodes followed by the test episodes
+fn data_set_episodes(
+    data_set_id: &str,
+    training_size: usize,
+    test_size: usize,
+    seed: u64,
+) -> Vec<(String, u64)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let training_seeds = (0..training_size)
+        .map(|_| rng.r#gen::<u64>())
         .collect::<Vec<_>>();
-    let test_inputs = (0..test_size)
-        .map(|_| input_generator.generate(training_duration))
+    let test_seeds = (0..test_size)
+        .map(|_| rng.r#gen::<u64>())
         .collect::<Vec<_>>();
+    let training = training_seeds
+        .into_iter()
+        .enumerate()
+        .map(|(ep, seed)| (format!("{data_set_id}/training_{ep}"), seed));
+    let test = test_seeds
+        .into_iter()
+        .enumerate()
+        .map(|(ep, seed)| (format!("{data_set_id}/testing_{ep}"), seed));
+    training.chain(test).collect()
+}
 
-    // TODO: do this on multiple cores
-    for (ep, inputs) in training_inputs.into_iter().enumerate() {
-        let simulation_id = format!("{data_set_id}/training_{ep}");
-        context.set_logger(crate::LoggerType::File(simulation_id));
-        let mut simulation = context.try_load_simulator().unwrap();
-        simulation.init();
-        for input in inputs {
-            simulation.simulate_delta(Duration::from_millis(1), input);
-        }
+// Simulates the episodes on `workers` threads. `done` gets the index of every episode with its
+// flight log as soon as the episode finishes, in the order they finish.
+pub fn build_episodes(
+    episodes: &[Episode],
+    teacher: &Teacher,
+    workers: usize,
+    done: impl Fn(usize, FlightLog) -> Result<(), LoaderError> + Sync,
+) -> Result<(), LoaderError> {
+    teacher.check()?;
+    let workers = match workers {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        workers => workers,
     }
+    .min(MAX_WORKERS);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .map_err(io::Error::other)?;
+    pool.install(|| {
+        episodes
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(ep, episode)| done(ep, simulate_episode(episode, teacher)?))
+    })
+}
 
-    for (ep, inputs) in test_inputs.into_iter().enumerate() {
-        let simulation_id = format!("{data_set_id}/testing_{ep}");
-        context.set_logger(crate::LoggerType::File(simulation_id));
-        let mut simulation = context.try_load_simulator().unwrap();
-        simulation.init();
-        for input in inputs {
-            simulation.simulate_delta(Duration::from_millis(1), input);
-        }
+// Simulates the episodes, ordered by split, and stores each one through the teacher's loader as
+// soon as it finishes, so only the episodes being simulated are in memory. The index is stored
+// once all of them are done. `progress` gets the simulation id of every stored episode with the
+// number stored so far and the total.
+pub fn store_episodes(
+    dataset_id: &str,
+    episodes: &[(Split, Episode)],
+    teacher: &Teacher,
+    workers: usize,
+    spec: Option<String>,
+    progress: impl Fn(&str, usize, usize) + Sync,
+) -> Result<DataSetIndex, LoaderError> {
+    let loader = teacher.loader()?;
+    let mut counts = HashMap::new();
+    let positions = episodes
+        .iter()
+        .map(|(split, _)| {
+            let count = counts.entry(*split).or_insert(0);
+            *count += 1;
+            *count - 1
+        })
+        .collect::<Vec<_>>();
+    let (splits, episodes): (Vec<_>, Vec<_>) = episodes.iter().cloned().unzip();
+    let keys = Mutex::new(vec![String::new(); episodes.len()]);
+    let stored = AtomicUsize::new(0);
+    build_episodes(&episodes, teacher, workers, |ep, flight_log| {
+        let key = loader.lock().unwrap().insert_episode(
+            dataset_id,
+            splits[ep],
+            positions[ep],
+            &flight_log,
+        )?;
+        keys.lock().unwrap()[ep] = key;
+        let stored = stored.fetch_add(1, Ordering::Relaxed) + 1;
+        progress(&flight_log.simulation_id, stored, episodes.len());
+        Ok(())
+    })?;
+    let index = DataSetIndex {
+        dataset_id: dataset_id.into(),
+        episodes: splits
+            .into_iter()
+            .zip(keys.into_inner().unwrap())
+            .zip(&episodes)
+            .map(|((split, key), episode)| EpisodeRef {
+                split,
+                key,
+                metadata: Some(episode.metadata()),
+            })
+            .collect(),
+        parents: vec![],
+        spec,
+    };
+    loader.lock().unwrap().insert_data_set_index(&index)?;
+    Ok(index)
+}
+
+// Every episode gets a fresh context and controller, so it doesn't depend on which worker runs it
+// or what that worker simulated before. The simulation runs on a single thread, the drone noise
+// comes from the thread local rng seeded by `init`.
+fn simulate_episode(episode: &Episode, teacher: &Teacher) -> Result<FlightLog, LoaderError> {
+    let mut context = teacher.context(episode.seed)?;
+    let logger = Arc::new(Mutex::new(MemoryLogger::new(episode.simulation_id.clone())));
+    context.logger = logger.clone();
+    let mut simulation = context.load_simulator(&teacher.config_id)?;
+    simulation.init()?;
+    for input in episode
+        .generator
+        .generate_seeded(episode.duration, episode.seed)
+    {
+        simulation.simulate_delta(Duration::from_millis(1), input);
     }
+    let steps = std::mem::take(&mut logger.lock().unwrap().snapshots);
+    Ok(FlightLog::new(episode.simulation_id.clone(), steps))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::input_gen::build_data_set;
-    use std::time::Duration;
+    use crate::{
+        input_gen::{
+            build_data_set, build_episodes, data_set_episodes, Episode, InputGenerationMethod,
+            InputGenerator, Teacher,
+        },
+        ControllerType, LoaderType,
+    };
+    use flight_controller::{BatteryUpdate, Channels, GyroUpdate, MotorInput};
+    use loggers::{FlightLog, SnapShot};
+    use std::{sync::Mutex, time::Duration};
+
+    #[test]
+    fn same_seed_same_inputs() {
+        let generator = InputGenerator::default()
+            .set_throttle(InputGenerationMethod::Brownian)
+            .set_roll(InputGenerationMethod::Brownian)
+            .set_seed(7);
+        let duration = Duration::from_secs(1);
+        assert_eq!(generator.generate(duration), generator.generate(duration));
+        assert_ne!(
+            generator.generate(duration),
+            generator.generate_seeded(duration, 8)
+        );
+    }
+
+    #[test]
+    fn generators_fill_the_duration_in_range() {
+        let methods = vec![
+            InputGenerationMethod::Steps {
+                min: -0.5,
+                max: 0.5,
+                min_hold_ms: 50,
+                max_hold_ms: 200,
+            },
+            InputGenerationMethod::Chirp {
+                amplitude: 0.5,
+                offset: 0.,
+                start_hz: 0.1,
+                end_hz: 10.,
+            },
+            InputGenerationMethod::Prbs {
+                amplitude: 0.3,
+                offset: 0.,
+                hold_ms: 20,
+            },
+            InputGenerationMethod::OrnsteinUhlenbeck {
+                mean: 0.,
+                theta: 2.,
+                sigma: 1.,
+            },
+            InputGenerationMethod::Ramp { from: -1., to: 1. },
+            InputGenerationMethod::Recorded(vec![0.1, 0.2]),
+            InputGenerationMethod::Sum(vec![
+                InputGenerationMethod::Brownian,
+                InputGenerationMethod::Scaled {
+                    method: Box::new(InputGenerationMethod::Brownian),
+                    gain: 3.,
+                    offset: 0.5,
+                },
+            ]),
+        ];
+        let duration = Duration::from_millis(1500);
+        for method in methods {
+            let inputs = InputGenerator::default()
+                .set_roll(method.clone())
+                .generate(duration);
+            assert_eq!(inputs.len(), 1500, "{method:?}");
+            assert!(
+                inputs.iter().all(|c| (-1. ..=1.).contains(&c.roll)),
+                "{method:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn prbs_is_binary() {
+        let inputs = InputGenerator::default()
+            .set_pitch(InputGenerationMethod::Prbs {
+                amplitude: 0.3,
+                offset: 0.1,
+                hold_ms: 10,
+            })
+            .generate(Duration::from_secs(1));
+        assert!(inputs
+            .iter()
+            .all(|c| c.pitch == 0.1 + 0.3 || c.pitch == 0.1 - 0.3));
+        assert!(inputs.iter().any(|c| c.pitch > 0.1));
+        assert!(inputs.iter().any(|c| c.pitch < 0.1));
+    }
+
+    #[test]
+    fn replays_recorded_flight() {
+        let steps = [(0, 0.1), (5, 0.4), (9, -0.2)]
+            .into_iter()
+            .map(|(ms, throttle)| SnapShot {
+                duration: Duration::from_millis(ms),
+                motor_input: MotorInput::default(),
+                battery_update: BatteryUpdate::default(),
+                gyro_update: GyroUpdate::default(),
+                channels: Channels {
+                    throttle,
+                    ..Default::default()
+                },
+                ground_truth: None,
+                shadow_motor_input: None,
+            })
+            .collect();
+        let flight_log = FlightLog::new("recorded".into(), steps);
+        let inputs =
+            InputGenerator::from_flight_log(&flight_log).generate(Duration::from_millis(12));
+        let throttle = inputs.iter().map(|c| c.throttle).collect::<Vec<_>>();
+        assert_eq!(
+            throttle,
+            [0.1, 0.1, 0.1, 0.1, 0.1, 0.4, 0.4, 0.4, 0.4, -0.2, -0.2, -0.2]
+        );
+    }
+
+    #[test]
+    fn workers_do_not_change_the_data_set() {
+        let generator = InputGenerator::default().set_roll(InputGenerationMethod::Brownian);
+        let episodes = data_set_episodes("workers", 3, 2, 11)
+            .into_iter()
+            .map(|(simulation_id, seed)| Episode {
+                simulation_id,
+                seed,
+                duration: Duration::from_millis(200),
+                generator: generator.clone(),
+            })
+            .collect::<Vec<_>>();
+        let teacher = Teacher {
+            controller: ControllerType::null(),
+            loader: LoaderType::DefaultLoader,
+            ..Default::default()
+        };
+        let built_with = |workers: usize| {
+            let built = Mutex::new(vec![String::new(); episodes.len()]);
+            build_episodes(&episodes, &teacher, workers, |ep, fl| {
+                built.lock().unwrap()[ep] = serde_json::to_string(&fl).unwrap();
+                Ok(())
+            })
+            .unwrap();
+            built.into_inner().unwrap()
+        };
+        assert_eq!(built_with(1), built_with(4));
+    }
+
+    #[test]
+    fn missing_teacher_fails_before_simulating() {
+        let teacher = Teacher {
+            controller: ControllerType::stored("missing_kind", "missing"),
+            loader: LoaderType::DefaultLoader,
+            ..Default::default()
+        };
+        assert!(build_episodes(&[], &teacher, 1, |_, _| Ok(())).is_err());
+    }
 
     #[test]
     fn build_50() {
-        build_data_set("5_len".into(), Duration::from_secs(5), 5, 5);
-        build_data_set("10_len".into(), Duration::from_secs(5), 10, 10);
-        build_data_set("25_len".into(), Duration::from_secs(5), 25, 25);
-        build_data_set("50_len".into(), Duration::from_secs(5), 50, 50);
+        build_data_set("5_len".into(), Duration::from_secs(5), 5, 5, 0, 0).unwrap();
+        build_data_set("10_len".into(), Duration::from_secs(5), 10, 10, 0, 0).unwrap();
+        build_data_set("25_len".into(), Duration::from_secs(5), 25, 25, 0, 0).unwrap();
+        build_data_set("50_len".into(), Duration::from_secs(5), 50, 50, 0, 0).unwrap();
     }
 }
@@ -1,19 +1,30 @@
+pub mod controller_registry;
+pub mod dataset_spec;
 pub mod input_gen;
 
-use bf_controller::BFController;
+pub use controller_registry::{ControllerRegistry, ControllerType};
+
+pub use loggers::rerun_logger::RerunSink;
+
 use drone::Drone;
 use flight_controller::{controllers::null_controller::NullController, FlightController};
-use loaders::LoaderTrait;
-use loaders::{default_laoder::DefaultLoader, file_loader::FileLoader};
+use loaders::{artifact::ControllerArtifact, LoaderError, LoaderTrait};
+use loaders::{
+    default_laoder::DefaultLoader, file_loader::FileLoader, sqlite_loader::SqliteLoader,
+};
 use loggers::{
-    empty_logger::EmptyLogger, file_logger::FileLogger, rerun_logger::RerunLogger,
+    empty_logger::EmptyLogger, file_logger::FileLogger, format::LogFormat,
+    rerun_logger::RerunLogger, storage::data_root, streaming_file_logger::StreamingFileLogger,
     Logger as LoggerTrait,
 };
 use loggers::{FlightLog, Logger};
 use res_controller::controllers::esn::NonAdaptingDroneRc;
+use serde::Serialize;
 use simulator::Replayer;
 use simulator::Simulator;
 use std::{
+    collections::HashMap,
+    path::PathBuf,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -21,20 +32,13 @@ use std::{
 #[derive(Default, Eq, PartialEq, Hash, Debug, Clone)]
 pub enum LoggerType {
     File(String),
-    Rerun(String),
+    BinaryFile(String), // same as File, but written in the compact binary format
+    StreamingFile(String), // appends to disk while simulating instead of on drop
+    Rerun(String, RerunSink),
     #[default]
     Empty,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
-pub enum ControllerType {
-    #[default]
-    Betafligt, // no parameters
-    Reservoir(String),  // reservoir controller id
-    Izhikevich(String), // izhikevich controller id
-    NullController,     // no controller
-}
-
 #[derive(Debug)]
 pub enum Loader {
     FileLoader(FileLoader),
@@ -42,21 +46,24 @@ pub enum Loader {
 }
 
 impl Loader {
-    pub fn load_drone(&mut self, config_id: &str) -> Drone {
+    pub fn load_drone(&mut self, config_id: &str) -> Result<Drone, LoaderError> {
         match self {
             Self::FileLoader(loader) => loader.load_drone(config_id),
             Self::DefaultLoader(loader) => loader.load_drone(config_id),
         }
     }
 
-    pub fn load_res_controller(&mut self, controller_id: &str) -> NonAdaptingDroneRc {
+    pub fn load_res_controller(
+        &mut self,
+        controller_id: &str,
+    ) -> Result<NonAdaptingDroneRc, LoaderError> {
         match self {
             Self::FileLoader(loader) => loader.load_res_controller(controller_id),
             Self::DefaultLoader(loader) => loader.load_res_controller(controller_id),
         }
     }
 
-    pub fn load_replay(&mut self, replay_id: &str) -> FlightLog {
+    pub fn load_replay(&mut self, replay_id: &str) -> Result<FlightLog, LoaderError> {
         match self {
             Self::FileLoader(loader) => loader.load_flight_log(replay_id),
             Self::DefaultLoader(loader) => loader.load_flight_log(replay_id),
@@ -73,6 +80,7 @@ impl Default for Loader {
 #[derive(Default, Clone, PartialEq)]
 pub enum LoaderType {
     File,
+    Sqlite, // single database file next to the files of the file loader
     #[default]
     DefaultLoader,
 }
@@ -80,6 +88,8 @@ pub enum LoaderType {
 pub struct SimContext {
     // Logger
     pub flight_controller: Arc<dyn FlightController>,
+    // Logged next to the flight controller without flying, see `Simulator::shadow_controller`
+    pub shadow_controller: Option<Arc<dyn FlightController>>,
     // Controller
     pub logger: Arc<Mutex<dyn Logger>>,
     // Loader
@@ -87,24 +97,30 @@ pub struct SimContext {
 
     // Replay ids
     pub replay_ids: Vec<String>,
-    // Res controller ids
-    pub reservoir_controller_ids: Vec<String>,
-    // Izhikevich controller ids
-    pub izhikevich_controller_ids: Vec<String>,
+    // Controller kinds that can be selected
+    pub controllers: ControllerRegistry,
+    // Ids of the stored controllers, by kind
+    pub controller_ids: HashMap<String, Vec<String>>,
     // Selected replay id
     pub replay_id: Option<String>,
     // Config id
     pub config_id: Option<String>,
+    // Seed of the drone noise, applied to every simulator loaded from the context
+    pub seed: u64,
+    // Root of everything the file loggers and loaders persist, applied by `set_loader` and
+    // `set_logger`. Defaults to `loggers::storage::data_root()`.
+    pub data_root: PathBuf,
 }
 
 impl std::fmt::Debug for SimContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SimContext")
             .field("replay_ids", &self.replay_ids)
-            .field("reservoir_controller_ids", &self.reservoir_controller_ids)
-            .field("izhikevich_controller_ids", &self.izhikevich_controller_ids)
+            .field("controller_ids", &self.controller_ids)
             .field("replay_ids", &self.replay_ids)
             .field("config_id", &self.config_id)
+            .field("seed", &self.seed)
+            .field("data_root", &self.data_root)
             .finish()
     }
 }
@@ -114,26 +130,39 @@ impl Default for SimContext {
         let mut sim_context = SimContext {
             logger: Arc::new(Mutex::new(EmptyLogger::default())),
             flight_controller: Arc::new(NullController::default()),
+            shadow_controller: None,
             loader: Arc::new(Mutex::new(DefaultLoader::default())),
             replay_ids: Default::default(),
-            reservoir_controller_ids: Default::default(),
-            izhikevich_controller_ids: Default::default(),
+            controllers: Default::default(),
+            controller_ids: Default::default(),
             replay_id: Default::default(),
             config_id: Some("7in_4s_drone".into()),
+            seed: 0,
+            data_root: data_root(),
         };
-        sim_context.refresh_cache();
+        sim_context
+            .refresh_cache()
+            .expect("the default loader can always list its ids");
         sim_context
     }
 }
 
 impl SimContext {
-    pub fn set_loader(&mut self, loader_type: &LoaderType) {
+    // Keeps the current loader if the new one can't be opened
+    pub fn set_loader(&mut self, loader_type: &LoaderType) -> Result<(), LoaderError> {
         match loader_type {
-            LoaderType::File => self.loader = Arc::new(Mutex::new(FileLoader::default())),
+            LoaderType::File => {
+                self.loader = Arc::new(Mutex::new(FileLoader::new(self.data_root.clone())))
+            }
+            LoaderType::Sqlite => {
+                let loader = SqliteLoader::open_in_root(&self.data_root)?;
+                self.loader = Arc::new(Mutex::new(loader))
+            }
             LoaderType::DefaultLoader => {
                 self.loader = Arc::new(Mutex::new(DefaultLoader::default()))
             }
         }
+        Ok(())
     }
 
     pub fn set_replay_id(&mut self, replay_id: String) {
@@ -142,91 +171,117 @@ impl SimContext {
 
     pub fn set_logger(&mut self, logger_type: LoggerType) {
         let logger: Arc<Mutex<dyn LoggerTrait>> = match logger_type {
-            LoggerType::Rerun(log_id) => Arc::new(Mutex::new(RerunLogger::new(log_id))),
+            LoggerType::Rerun(log_id, sink) => {
+                Arc::new(Mutex::new(RerunLogger::with_sink(log_id, sink)))
+            }
             LoggerType::Empty => Arc::new(Mutex::new(EmptyLogger::default())),
-            LoggerType::File(log_id) => Arc::new(Mutex::new(FileLogger::new(log_id))),
+            LoggerType::File(log_id) => Arc::new(Mutex::new(
+                FileLogger::new(log_id).with_root(self.data_root.clone()),
+            )),
+            LoggerType::StreamingFile(log_id) => Arc::new(Mutex::new(
+                StreamingFileLogger::new(log_id).with_root(self.data_root.clone()),
+            )),
+            LoggerType::BinaryFile(log_id) => Arc::new(Mutex::new(
+                FileLogger::with_format(log_id, LogFormat::Binary)
+                    .with_root(self.data_root.clone()),
+            )),
         };
         self.logger = logger;
     }
 
-    pub fn set_controller(&mut self, controller: ControllerType) {
-        let flight_controller: Arc<dyn FlightController> = match controller {
-            ControllerType::Betafligt => Arc::new(BFController::default()),
-            ControllerType::Reservoir(res_id) => {
-                let res_controller = self.loader.lock().unwrap().load_res_controller(&res_id);
-                Arc::new(res_controller)
-            }
-            ControllerType::Izhikevich(controller_id) => {
-                let controller = self
-                    .loader
-                    .lock()
-                    .unwrap()
-                    .load_izhikevich_controller(&controller_id);
-                Arc::new(controller)
+    // Keeps the previous controller if the new one fails to load
+    pub fn set_controller(&mut self, controller: ControllerType) -> Result<(), LoaderError> {
+        let mut loader = self.loader.lock().unwrap();
+        let flight_controller = self.controllers.build(&mut *loader, &controller)?;
+        drop(loader);
+        self.flight_controller = flight_controller;
+        Ok(())
+    }
+
+    pub fn set_shadow_controller(
+        &mut self,
+        controller: Option<ControllerType>,
+    ) -> Result<(), LoaderError> {
+        self.shadow_controller = match controller {
+            Some(controller) => {
+                let mut loader = self.loader.lock().unwrap();
+                Some(self.controllers.build(&mut *loader, &controller)?)
             }
-            ControllerType::NullController => Arc::new(NullController::default()),
+            None => None,
         };
-        self.flight_controller = flight_controller;
+        Ok(())
     }
 
-    pub fn load_simulator(&self, config_id: &str) -> Simulator {
-        let drone = self.loader.lock().unwrap().load_drone(config_id);
-        Simulator::with_drone_and_controller_logger(
+    pub fn load_simulator(&self, config_id: &str) -> Result<Simulator, LoaderError> {
+        let drone = self.loader.lock().unwrap().load_drone(config_id)?;
+        let mut simulator = Simulator::with_drone_and_controller_logger(
             drone,
             self.flight_controller.clone(),
             self.logger.clone(),
-        )
+        );
+        simulator.seed = self.seed;
+        simulator.shadow_controller = self.shadow_controller.clone();
+        Ok(simulator)
     }
 
-    pub fn try_load_simulator(&mut self) -> Option<Simulator> {
+    // None if no drone config is selected
+    pub fn try_load_simulator(&mut self) -> Option<Result<Simulator, LoaderError>> {
         let config_id = self.config_id.clone()?;
         Some(self.load_simulator(&config_id))
     }
 
-    pub fn load_replay_ids(&mut self) {
-        let replay_ids = self.loader.lock().unwrap().get_replay_ids();
-        self.replay_ids = replay_ids
-    }
-
-    pub fn load_res_controllers_ids(&mut self) {
-        let reservoir_controler_ids = self.loader.lock().unwrap().get_reservoir_controller_ids();
-        self.reservoir_controller_ids = reservoir_controler_ids
+    pub fn load_replay_ids(&mut self) -> Result<(), LoaderError> {
+        let replay_ids = self.loader.lock().unwrap().get_replay_ids()?;
+        self.replay_ids = replay_ids;
+        Ok(())
     }
 
-    pub fn load_izhikevich_controllers_ids(&mut self) {
-        let controller_ids = self.loader.lock().unwrap().get_izhikevich_controller_ids();
-        self.izhikevich_controller_ids = controller_ids
+    pub fn load_controller_ids(&mut self) -> Result<(), LoaderError> {
+        let mut loader = self.loader.lock().unwrap();
+        let mut controller_ids = HashMap::new();
+        for kind in self.controllers.kinds().filter(|k| k.is_stored()) {
+            let ids = loader.get_controller_ids(kind.artifact_kind())?;
+            controller_ids.insert(kind.kind().into(), ids);
+        }
+        drop(loader);
+        self.controller_ids = controller_ids;
+        Ok(())
     }
 
-    pub fn refresh_cache(&mut self) {
-        self.load_replay_ids();
-        self.load_res_controllers_ids();
-        self.load_izhikevich_controllers_ids();
+    pub fn refresh_cache(&mut self) -> Result<(), LoaderError> {
+        self.load_replay_ids()?;
+        self.load_controller_ids()
     }
 
-    pub fn load_flight_log(&mut self, replay_id: &str) -> FlightLog {
+    pub fn load_flight_log(&mut self, replay_id: &str) -> Result<FlightLog, LoaderError> {
         self.loader.lock().unwrap().load_flight_log(replay_id)
     }
 
-    pub fn load_drone(&mut self) -> Option<Drone> {
+    // None if no drone config is selected
+    pub fn load_drone(&mut self) -> Option<Result<Drone, LoaderError>> {
         let config_id = self.config_id.clone()?;
         Some(self.loader.lock().unwrap().load_drone(&config_id))
     }
 
-    pub fn load_replayer(&mut self, config_id: &str, replay_id: &str) -> Replayer {
-        let drone = self.loader.lock().unwrap().load_drone(config_id);
-        let sim_logs = self.loader.lock().unwrap().load_flight_log(replay_id);
-        Replayer {
+    pub fn load_replayer(
+        &mut self,
+        config_id: &str,
+        replay_id: &str,
+    ) -> Result<Replayer, LoaderError> {
+        let drone = self.loader.lock().unwrap().load_drone(config_id)?;
+        let sim_logs = self.loader.lock().unwrap().load_flight_log(replay_id)?;
+        Ok(Replayer {
             drone,
             time: Duration::new(0, 0),
             time_accu: Duration::new(0, 0),
             time_steps: sim_logs,
             replay_index: 0,
             dt: Duration::from_nanos(5000),
-        }
+        })
     }
 
-    pub fn try_load_replay(&mut self) -> Option<Replayer> {
+    // None if either the drone config or the replay is not selected
+    pub fn try_load_replay(&mut self) -> Option<Result<Replayer, LoaderError>> {
         if let (Some(config_id), Some(replay_id)) = (self.config_id.clone(), self.replay_id.clone())
         {
             Some(self.load_replayer(&config_id, &replay_id))
@@ -235,14 +290,58 @@ impl SimContext {
         }
     }
 
-    pub fn insert_drone_rc(&mut self, controller_id: &str, controller: NonAdaptingDroneRc) {
+    // Stores a controller of any registered kind, recording the input features of the kind
+    pub fn insert_controller<T: Serialize>(
+        &mut self,
+        kind: &str,
+        controller_id: &str,
+        controller: &T,
+    ) -> Result<(), LoaderError> {
+        let artifact = self.new_artifact(kind, controller)?;
+        self.insert_artifact(controller_id, &artifact)
+    }
+
+    // An artifact for a controller of a registered kind, to be completed with the training
+    // parameters, data set and scores before `insert_artifact`
+    pub fn new_artifact<T: Serialize>(
+        &self,
+        kind: &str,
+        controller: &T,
+    ) -> Result<ControllerArtifact, LoaderError> {
+        let controller_kind = self
+            .controllers
+            .get(kind)
+            .ok_or_else(|| LoaderError::NotFound(kind.into()))?;
+        Ok(ControllerArtifact::new(kind, controller)?
+            .with_input_features(controller_kind.input_features()))
+    }
+
+    pub fn insert_artifact(
+        &mut self,
+        controller_id: &str,
+        artifact: &ControllerArtifact,
+    ) -> Result<(), LoaderError> {
+        self.loader
+            .lock()
+            .unwrap()
+            .insert_artifact(controller_id, artifact)
+    }
+
+    pub fn insert_drone_rc(
+        &mut self,
+        controller_id: &str,
+        controller: NonAdaptingDroneRc,
+    ) -> Result<(), LoaderError> {
         self.loader
             .lock()
             .unwrap()
-            .insert_rc_controller(controller_id, controller);
+            .insert_rc_controller(controller_id, controller)
     }
 
-    pub fn load_drone_rc(&mut self, controller_id: &str) -> NonAdaptingDroneRc {
+    pub fn load_drone_rc(
+        &mut self,
+        controller_id: &str,
+    ) -> Result<NonAdaptingDroneRc, LoaderError> {
         self.loader
             .lock()
             .unwrap()
@@ -1,28 +1,22 @@
+pub mod schedule;
+
 use drone::{Drone, SimulationFrame};
 pub use flight_controller::{BatteryUpdate, GyroUpdate, MotorInput};
 use flight_controller::{Channels, FlightController, FlightControllerUpdate};
-use loggers::{FlightLog, Logger, SnapShot};
-use nalgebra::{Rotation3, Vector3, Vector4};
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use loggers::{FlightLog, GroundTruth, Logger, RotorGroundTruth, SnapShot};
+use nalgebra::{Rotation3, UnitQuaternion, Vector3, Vector4};
+use schedule::{Actuation, ScheduleError, TickStats};
 use std::{
-    cell::RefCell,
-    ops::Range,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
+pub use drone::{rng_gen_range, seed_rng};
+
 pub const MAX_EFFECT_SPEED: f64 = 18.0;
 pub const AIR_RHO: f64 = 1.225;
 pub const GRAVITY: f64 = 9.81;
 
-thread_local! {
-    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
-}
-
-pub fn rng_gen_range(range: Range<f64>) -> f64 {
-    RNG.with(|rng| rng.borrow_mut().gen_range(range))
-}
-
 #[derive(Debug, Default)]
 pub struct SimulationObservation {
     pub simulation_time: Duration,
@@ -46,8 +40,23 @@ pub struct Simulator {
     pub time_accu: Duration, // the accumulated time between two steps + the correction from the
     pub dt: Duration,
     pub flight_controller: Arc<dyn FlightController>,
-    pub fc_time_accu: Duration,
+    // Controller period, overriding the controller's own scheduler delta. The periods are checked
+    // by `init`.
+    pub scheduler_delta: Option<Duration>,
+    // Actuator latency and motor hold between the controller and the rotors
+    pub actuation: Actuation,
+    pub tick_stats: TickStats,
+    // scheduled time of the next controller tick, one period after the start by default
+    next_tick: Option<Duration>,
     pub logger: Arc<Mutex<dyn Logger>>, // needs to be mutable
+    pub seed: u64,                      // seeds the drone noise on init
+    pub log_ground_truth: bool,         // add the ground truth to the logged snapshots
+    // Gets the same updates as the flight controller, but ticks on its own scheduler delta and
+    // its motor inputs never reach the drone. Its last motor input is logged with every snapshot
+    // (`SnapShot::shadow_motor_input`).
+    pub shadow_controller: Option<Arc<dyn FlightController>>,
+    shadow_next_tick: Option<Duration>,
+    shadow_motor_input: Option<MotorInput>,
 }
 
 impl Simulator {
@@ -62,8 +71,49 @@ impl Simulator {
             time_accu: Duration::default(),
             time: Duration::new(0, 0),
             dt: Duration::from_nanos(5000),
-            fc_time_accu: Duration::default(),
+            scheduler_delta: None,
+            actuation: Actuation::default(),
+            tick_stats: TickStats::default(),
+            next_tick: None,
             logger,
+            seed: 0,
+            log_ground_truth: true,
+            shadow_controller: None,
+            shadow_next_tick: None,
+            shadow_motor_input: None,
+        }
+    }
+
+    // The period the flight controller ticks at
+    pub fn scheduler_delta(&self) -> Duration {
+        self.scheduler_delta
+            .unwrap_or_else(|| self.flight_controller.scheduler_delta())
+    }
+
+    pub fn with_shadow_controller(mut self, shadow_controller: Arc<dyn FlightController>) -> Self {
+        self.shadow_controller = Some(shadow_controller);
+        self
+    }
+
+    pub fn ground_truth(&self) -> GroundTruth {
+        let current_frame = &self.drone.current_frame;
+        let drone_state = &current_frame.drone_frame_state;
+        let rotation = UnitQuaternion::from_rotation_matrix(&drone_state.rotation);
+        GroundTruth {
+            position: drone_state.position.into(),
+            linear_velocity: drone_state.linear_velocity.into(),
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+            angular_velocity: drone_state.angular_velocity.into(),
+            rotors: current_frame
+                .rotors_state
+                .0
+                .each_ref()
+                .map(|r| RotorGroundTruth {
+                    rpm: r.rpm,
+                    effective_thrust: r.effective_thrust,
+                    current: r.current,
+                }),
+            bat_capacity: current_frame.battery_state.capacity,
         }
     }
 
@@ -102,48 +152,102 @@ impl Simulator {
     }
 
     /// Given a duration (typically 10ms between frames), runs the simulation until the time
-    /// accumlator is less then the simulation's dt. It will also try to
+    /// accumlator is less then the simulation's dt. The flight controller ticks in between on its
+    /// own period, see `schedule`.
     pub fn simulate_delta(&mut self, delta: Duration, channels: Channels) -> SimulationObservation {
         self.time_accu += delta;
-        while self.time_accu > self.dt {
-            self.fc_time_accu += self.dt;
+        while self.time_accu >= self.dt {
             self.drone.update(self.dt.as_secs_f64());
+            self.time_accu -= self.dt;
+            self.time += self.dt;
 
-            let call_fc = self.fc_time_accu > self.flight_controller.scheduler_delta();
-
-            // update the flight controller
-            if call_fc {
-                let motor_input = self.flight_controller.update(
-                    self.fc_time_accu.as_secs_f64(),
-                    FlightControllerUpdate {
-                        battery_update: self.drone.battery_update(),
-                        gyro_update: self.drone.current_frame.gyro_state.gyro_update(),
-                        channels,
-                    },
-                );
-                self.drone.set_motor_pwms(motor_input);
-                self.fc_time_accu -= self.flight_controller.scheduler_delta();
-
-                let mut logger = self.logger.lock().unwrap();
-                let snapshot = SnapShot {
-                    duration: self.time,
-                    motor_input,
-                    battery_update: self.drone.battery_update(),
-                    gyro_update: self.drone.current_frame.gyro_state.gyro_update(),
-                    channels,
-                };
-                logger.log_time_stamp(snapshot);
+            // the shadow first, so a snapshot logged at the same time has its new motor input
+            self.tick_shadow(channels);
+
+            let period = self.nonzero(self.scheduler_delta());
+            let mut next_tick = *self.next_tick.get_or_insert(period);
+            let mut catch_up = false;
+            while next_tick <= self.time {
+                self.tick(period, next_tick, catch_up, channels);
+                next_tick += period;
+                catch_up = true;
             }
+            self.next_tick = Some(next_tick);
 
-            self.time_accu -= self.dt;
-            self.time += self.dt;
+            if let Some(motor_input) = self.actuation.output(self.time, period) {
+                self.drone.set_motor_pwms(motor_input);
+            }
         }
 
         self.simulation_info()
     }
 
-    pub fn init(&mut self) {
+    // A zero period is rejected by `init`, without it the controller ticks every physics step
+    fn nonzero(&self, period: Duration) -> Duration {
+        if period.is_zero() {
+            self.dt
+        } else {
+            period
+        }
+    }
+
+    fn controller_update(&self, channels: Channels) -> FlightControllerUpdate {
+        FlightControllerUpdate {
+            battery_update: self.drone.battery_update(),
+            gyro_update: self.drone.current_frame.gyro_state.gyro_update(),
+            channels,
+        }
+    }
+
+    // Runs the shadow's ticks due by now, on the same schedule as the flight controller's
+    fn tick_shadow(&mut self, channels: Channels) {
+        let Some(shadow) = self.shadow_controller.clone() else {
+            return;
+        };
+        let period = self.nonzero(shadow.scheduler_delta());
+        let mut next_tick = *self.shadow_next_tick.get_or_insert(period);
+        while next_tick <= self.time {
+            let update = self.controller_update(channels);
+            self.shadow_motor_input = Some(shadow.update(period.as_secs_f64(), update));
+            next_tick += period;
+        }
+        self.shadow_next_tick = Some(next_tick);
+    }
+
+    // Updates the flight controller and logs the snapshot at the actual time
+    fn tick(&mut self, period: Duration, scheduled: Duration, catch_up: bool, channels: Channels) {
+        let update = self.controller_update(channels);
+        let motor_input = self.flight_controller.update(period.as_secs_f64(), update);
+        self.actuation.command(self.time, motor_input);
+        self.tick_stats.record(scheduled, self.time, catch_up);
+
+        let snapshot = SnapShot {
+            duration: self.time,
+            motor_input,
+            battery_update: update.battery_update,
+            gyro_update: update.gyro_update,
+            channels,
+            ground_truth: self.log_ground_truth.then(|| self.ground_truth()),
+            shadow_motor_input: self.shadow_motor_input,
+        };
+        self.logger.lock().unwrap().log_time_stamp(snapshot);
+    }
+
+    pub fn init(&mut self) -> Result<(), ScheduleError> {
+        if self.scheduler_delta().is_zero() {
+            return Err(ScheduleError::ZeroPeriod);
+        }
+        if let Some(shadow_controller) = &self.shadow_controller {
+            if shadow_controller.scheduler_delta().is_zero() {
+                return Err(ScheduleError::ZeroShadowPeriod);
+            }
+        }
+        seed_rng(self.seed);
         self.flight_controller.init();
+        if let Some(shadow_controller) = &self.shadow_controller {
+            shadow_controller.init();
+        }
+        Ok(())
     }
 }
 
@@ -240,3 +344,175 @@ impl Replayer {
         self.replay_index = 0;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        schedule::{Actuation, MotorHold, ScheduleError},
+        Simulator,
+    };
+    use drone::default_drone::default_7in_4s_drone;
+    use flight_controller::{Channels, FlightController, FlightControllerUpdate, MotorInput};
+    use loggers::{memory_logger::MemoryLogger, FlightLog};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    // Outputs the same motor input on every tick of its period and counts the ticks
+    struct Constant {
+        period: Duration,
+        output: f64,
+        ticks: AtomicUsize,
+    }
+
+    impl Constant {
+        fn new(period: Duration, output: f64) -> Arc<Self> {
+            Arc::new(Self {
+                period,
+                output,
+                ticks: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    impl FlightController for Constant {
+        fn init(&self) {}
+        fn update(&self, _: f64, _: FlightControllerUpdate) -> MotorInput {
+            self.ticks.fetch_add(1, Ordering::Relaxed);
+            MotorInput {
+                input: [self.output; 4],
+            }
+        }
+        fn scheduler_delta(&self) -> Duration {
+            self.period
+        }
+    }
+
+    fn simulator(controller: Arc<Constant>) -> (Simulator, Arc<Mutex<MemoryLogger>>) {
+        let logger = Arc::new(Mutex::new(MemoryLogger::new("test".into())));
+        let mut simulation = Simulator::with_drone_and_controller_logger(
+            default_7in_4s_drone(),
+            controller,
+            logger.clone(),
+        );
+        simulation.log_ground_truth = false;
+        (simulation, logger)
+    }
+
+    fn snapshot_times(logger: &Mutex<MemoryLogger>) -> Vec<Duration> {
+        let logger = logger.lock().unwrap();
+        logger.snapshots.iter().map(|step| step.duration).collect()
+    }
+
+    #[test]
+    fn periods_that_are_not_a_multiple_of_dt_do_not_drift() {
+        // 333 µs between ticks on a 5 µs physics step
+        let period = Duration::from_micros(333);
+        let (mut simulation, logger) = simulator(Constant::new(period, 0.));
+        simulation.init().unwrap();
+        for _ in 0..100 {
+            simulation.simulate_delta(Duration::from_millis(1), Channels::default());
+        }
+        // every tick runs at the first physics step at or after k * period
+        let expected: Vec<_> = (1..=300u64)
+            .map(|k| Duration::from_micros((k * 333).div_ceil(5) * 5))
+            .collect();
+        assert_eq!(snapshot_times(&logger), expected);
+        assert!(simulation.tick_stats.max_lag < simulation.dt);
+        assert_eq!(simulation.tick_stats.catch_up_ticks, 0);
+    }
+
+    #[test]
+    fn controllers_faster_than_dt_catch_up() {
+        let controller = Constant::new(Duration::from_micros(250), 0.);
+        let (mut simulation, logger) = simulator(controller.clone());
+        simulation.dt = Duration::from_millis(1);
+        simulation.init().unwrap();
+        for _ in 0..10 {
+            simulation.simulate_delta(Duration::from_millis(1), Channels::default());
+        }
+        // four ticks per physics step, the last three of them catching up
+        assert_eq!(controller.ticks.load(Ordering::Relaxed), 40);
+        assert_eq!(simulation.tick_stats.ticks, 40);
+        assert_eq!(simulation.tick_stats.catch_up_ticks, 30);
+        assert_eq!(simulation.tick_stats.max_lag, Duration::from_micros(750));
+        let times = snapshot_times(&logger);
+        assert_eq!(times[..4], [Duration::from_millis(1); 4]);
+    }
+
+    #[test]
+    fn latency_of_whole_steps_delays_the_motor_input_by_as_many_steps() {
+        let dt = Duration::from_micros(5);
+        let mut actuation = Actuation::new(3 * dt, MotorHold::ZeroOrder);
+        let motor_input = MotorInput { input: [0.5; 4] };
+        actuation.command(dt, motor_input);
+        assert!(actuation.output(dt, dt).is_none());
+        assert!(actuation.output(2 * dt, dt).is_none());
+        assert!(actuation.output(3 * dt, dt).is_none());
+        assert_eq!(actuation.output(4 * dt, dt).unwrap().input, [0.5; 4]);
+        // held without a new one
+        assert!(actuation.output(5 * dt, dt).is_none());
+    }
+
+    #[test]
+    fn first_order_hold_reaches_the_target_after_one_period() {
+        let period = Duration::from_millis(1);
+        let mut actuation = Actuation::new(Duration::ZERO, MotorHold::FirstOrder);
+        actuation.command(Duration::ZERO, MotorInput { input: [0.8; 4] });
+        let at = |actuation: &mut Actuation, micros| {
+            actuation
+                .output(Duration::from_micros(micros), period)
+                .unwrap()
+                .input[0]
+        };
+        assert_eq!(at(&mut actuation, 0), 0.);
+        assert!((at(&mut actuation, 250) - 0.2).abs() < 1e-12);
+        assert!((at(&mut actuation, 500) - 0.4).abs() < 1e-12);
+        assert_eq!(at(&mut actuation, 1000), 0.8);
+        // and holds it
+        assert!(actuation
+            .output(Duration::from_micros(1005), period)
+            .is_none());
+    }
+
+    #[test]
+    fn zero_periods_are_rejected() {
+        let (mut simulation, _) = simulator(Constant::new(Duration::from_millis(1), 0.));
+        simulation.scheduler_delta = Some(Duration::ZERO);
+        assert_eq!(simulation.init(), Err(ScheduleError::ZeroPeriod));
+        simulation.scheduler_delta = None;
+        let mut simulation = simulation.with_shadow_controller(Constant::new(Duration::ZERO, 0.));
+        assert_eq!(simulation.init(), Err(ScheduleError::ZeroShadowPeriod));
+    }
+
+    #[test]
+    fn shadow_ticks_on_its_own_period_and_is_logged_held() {
+        let controller = Constant::new(Duration::from_millis(1), 0.2);
+        let shadow = Constant::new(Duration::from_millis(4), 0.6);
+        let (simulation, logger) = simulator(controller.clone());
+        let mut simulation = simulation.with_shadow_controller(shadow.clone());
+        simulation.init().unwrap();
+        for _ in 0..20 {
+            simulation.simulate_delta(Duration::from_millis(1), Channels::default());
+        }
+        assert_eq!(controller.ticks.load(Ordering::Relaxed), 20);
+        assert_eq!(shadow.ticks.load(Ordering::Relaxed), 5);
+
+        let steps = std::mem::take(&mut logger.lock().unwrap().snapshots);
+        let flight_log = FlightLog::new("test".into(), steps);
+        // nothing to log before the first shadow tick at 4 ms, then its output is held
+        for (i, step) in flight_log.steps.iter().enumerate() {
+            assert_eq!(step.duration, Duration::from_millis(i as u64 + 1));
+            match step.shadow_motor_input {
+                Some(shadow) => assert!(i >= 3 && shadow.input == [0.6; 4]),
+                None => assert!(i < 3),
+            }
+        }
+        let mse = flight_log.shadow_mse().unwrap();
+        assert!((mse - 0.16).abs() < 1e-12, "{mse}");
+    }
+}
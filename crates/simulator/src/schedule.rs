@@ -0,0 +1,114 @@
+// Timing between the flight controller and the physics. The controller ticks on its own period,
+// independent of the physics step: a tick runs at the first physics step at or after its
+// scheduled time, and a controller faster than the physics runs several ticks in one step to catch
+// up. Its motor inputs reach the rotors after the actuator latency and are held until the next
+// ones arrive.
+
+use derive_more::derive::{Display, Error};
+use flight_controller::MotorInput;
+use std::{collections::VecDeque, time::Duration};
+
+// A schedule the simulator can't run, reported by `Simulator::init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Error)]
+pub enum ScheduleError {
+    #[display("the flight controller period is zero")]
+    ZeroPeriod,
+    #[display("the shadow controller period is zero")]
+    ZeroShadowPeriod,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MotorHold {
+    // keep the last motor input until the next one arrives
+    #[default]
+    ZeroOrder,
+    // ramp from the motor input at arrival to the new one over one controller period
+    FirstOrder,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TickStats {
+    pub ticks: u64,
+    // ticks run in the same physics step as the previous one, to keep up with a controller
+    // faster than the physics
+    pub catch_up_ticks: u64,
+    // largest delay between the scheduled and the actual time of a tick
+    pub max_lag: Duration,
+    // actual time of the last tick, the one logged in its snapshot
+    pub last_tick: Option<Duration>,
+}
+
+impl TickStats {
+    pub fn record(&mut self, scheduled: Duration, actual: Duration, catch_up: bool) {
+        self.ticks += 1;
+        if catch_up {
+            self.catch_up_ticks += 1;
+        }
+        self.max_lag = self.max_lag.max(actual - scheduled);
+        self.last_tick = Some(actual);
+    }
+}
+
+// Motor inputs on their way to the rotors
+#[derive(Debug, Clone, Default)]
+pub struct Actuation {
+    pub latency: Duration,
+    pub hold: MotorHold,
+    // (arrival time, motor input), in arrival order
+    pending: VecDeque<(Duration, MotorInput)>,
+    from: MotorInput,
+    to: MotorInput,
+    since: Duration,
+    ramp: Duration,
+}
+
+impl Actuation {
+    pub fn new(latency: Duration, hold: MotorHold) -> Self {
+        Self {
+            latency,
+            hold,
+            ..Default::default()
+        }
+    }
+
+    // A motor input computed at `time`
+    pub fn command(&mut self, time: Duration, motor_input: MotorInput) {
+        self.pending.push_back((time + self.latency, motor_input));
+    }
+
+    fn held(&self, time: Duration) -> MotorInput {
+        match self.hold {
+            MotorHold::ZeroOrder => self.to,
+            MotorHold::FirstOrder => {
+                let progress = if self.ramp.is_zero() {
+                    1.
+                } else {
+                    ((time - self.since).as_secs_f64() / self.ramp.as_secs_f64()).min(1.)
+                };
+                MotorInput {
+                    input: [0, 1, 2, 3]
+                        .map(|i| self.from[i] + (self.to[i] - self.from[i]) * progress),
+                }
+            }
+        }
+    }
+
+    // The motor input at `time`, None while the rotors keep the last one. `period` is the
+    // controller period the first order hold ramps over.
+    pub fn output(&mut self, time: Duration, period: Duration) -> Option<MotorInput> {
+        let mut arrived = false;
+        while let Some(&(arrival, motor_input)) = self.pending.front() {
+            if arrival > time {
+                break;
+            }
+            self.pending.pop_front();
+            self.from = self.held(arrival);
+            self.to = motor_input;
+            self.since = arrival;
+            self.ramp = period;
+            arrived = true;
+        }
+        let ramping = self.hold == MotorHold::FirstOrder && time - self.since <= self.ramp;
+        (arrived || ramping).then(|| self.held(time))
+    }
+}
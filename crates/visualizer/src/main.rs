@@ -6,7 +6,7 @@ mod replay;
 mod sim;
 mod ui;
 
-use crate::ui::menu::UIState;
+use crate::ui::menu::{MenuStatus, UIState};
 use bevy::{
     app::{App, PluginGroup, Startup, Update},
     asset::{AssetServer, Assets, Handle},
@@ -111,8 +111,12 @@ pub fn setup(
     let drone_asset = DroneAsset(drone_scene);
     commands.insert_resource(drone_asset);
     commands.insert_resource(UIState::default());
+    let mut menu_status = MenuStatus::default();
     let mut context = SimContext::default();
-    context.refresh_cache();
+    if let Err(err) = context.refresh_cache() {
+        menu_status.error = Some(format!("failed to list files: {err}"));
+    }
+    commands.insert_resource(menu_status);
     commands.insert_resource(Context(context));
 }
 
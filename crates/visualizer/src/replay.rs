@@ -45,7 +45,10 @@ pub fn replay_loop(
 }
 
 pub fn enter_replay(mut context: ResMut<Context>, mut commands: Commands) {
-    let replay = context.try_load_replay().unwrap();
+    let replay = context
+        .try_load_replay()
+        .unwrap()
+        .expect("the replay is checked when applying the menu");
     commands.insert_resource(Replay(replay));
 }
 
@@ -111,8 +111,11 @@ pub fn sim_loop(
 }
 
 pub fn enter_simulation(mut commands: Commands, mut context: ResMut<Context>) {
-    let mut simulation = context.try_load_simulator().unwrap();
-    simulation.init();
+    let mut simulation = context
+        .try_load_simulator()
+        .unwrap()
+        .expect("the drone config is checked when applying the menu");
+    simulation.init().unwrap();
     commands.insert_resource(Simulation(simulation));
     commands.insert_resource(SimulationData::default());
 }
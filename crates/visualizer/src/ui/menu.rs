@@ -10,12 +10,16 @@ use bevy_egui::{
     },
     EguiContexts,
 };
-use sim_context::{ControllerType, LoaderType, LoggerType, SimContext};
+use sim_context::{ControllerType, LoaderType, LoggerType, RerunSink, SimContext};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum LoggerArchType {
     File,
+    BinaryFile,
+    StreamingFile,
     Rerun,
+    RerunFile,
     #[default]
     Empty,
 }
@@ -24,12 +28,24 @@ impl LoggerArchType {
     fn to_logger_type(&self, simulation_name: String) -> LoggerType {
         match self {
             Self::File => LoggerType::File(simulation_name),
-            Self::Rerun => LoggerType::Rerun(simulation_name),
+            Self::BinaryFile => LoggerType::BinaryFile(simulation_name),
+            Self::StreamingFile => LoggerType::StreamingFile(simulation_name),
+            Self::Rerun => LoggerType::Rerun(simulation_name, RerunSink::Spawn),
+            Self::RerunFile => {
+                let path = PathBuf::from(format!("{simulation_name}.rrd"));
+                LoggerType::Rerun(simulation_name, RerunSink::File(path))
+            }
             Self::Empty => LoggerType::Empty,
         }
     }
 }
 
+// Feedback for the last menu action, e.g. a controller that failed to load
+#[derive(Resource, Default, Debug)]
+pub struct MenuStatus {
+    pub error: Option<String>,
+}
+
 #[derive(Resource, Clone, PartialEq)]
 pub enum UIState {
     Replay {
@@ -95,6 +111,7 @@ impl UIState {
 pub fn main_menu_toggle(
     ui: &mut Ui,
     ui_state: &mut UIState,
+    menu_status: &mut MenuStatus,
     context: &mut SimContext,
     mut next_visualizer_state: ResMut<NextState<VisualizerState>>,
 ) {
@@ -132,26 +149,28 @@ pub fn main_menu_toggle(
         ui.label("Controller:");
         match ui_state {
             UIState::Simulation { controller, .. } => {
-                let label = format!("{controller:?}");
+                let label = controller.to_string();
                 egui::ComboBox::from_id_salt("Controller selector")
                     .selected_text(label)
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(controller, ControllerType::Betafligt, "Betaflight");
-                        for res_id in context.reservoir_controller_ids.iter() {
-                            ui.selectable_value(
-                                controller,
-                                ControllerType::Reservoir(res_id.into()),
-                                format!("Resrevoid controller {res_id}"),
-                            );
-                        }
-                        for controller_id in context.izhikevich_controller_ids.iter() {
-                            ui.selectable_value(
-                                controller,
-                                ControllerType::Izhikevich(controller_id.into()),
-                                format!("Izhikevich controller {controller_id}"),
-                            );
+                        for kind in context.controllers.kinds() {
+                            if !kind.is_stored() {
+                                ui.selectable_value(
+                                    controller,
+                                    ControllerType::builtin(kind.kind()),
+                                    kind.label(),
+                                );
+                                continue;
+                            }
+                            let ids = context.controller_ids.get(kind.kind());
+                            for controller_id in ids.into_iter().flatten() {
+                                ui.selectable_value(
+                                    controller,
+                                    ControllerType::stored(kind.kind(), controller_id),
+                                    format!("{} {controller_id}", kind.label()),
+                                );
+                            }
                         }
-                        ui.selectable_value(controller, ControllerType::NullController, "Null");
                     });
             }
             UIState::Replay { .. } => {
@@ -169,7 +188,10 @@ pub fn main_menu_toggle(
                 .selected_text(label)
                 .show_ui(ui, |ui| {
                     ui.selectable_value(logger, LoggerArchType::File, "File");
+                    ui.selectable_value(logger, LoggerArchType::BinaryFile, "File (binary)");
+                    ui.selectable_value(logger, LoggerArchType::StreamingFile, "File (streaming)");
                     ui.selectable_value(logger, LoggerArchType::Rerun, "Rerun");
+                    ui.selectable_value(logger, LoggerArchType::RerunFile, "Rerun (.rrd file)");
                     ui.selectable_value(logger, LoggerArchType::Empty, "None");
                 });
         } else {
@@ -220,29 +242,33 @@ pub fn main_menu_toggle(
         };
         let label: String = match loader {
             LoaderType::File => "File".into(),
+            LoaderType::Sqlite => "SQLite".into(),
             LoaderType::DefaultLoader => "Default".into(),
         };
         egui::ComboBox::from_id_salt("Loader selector")
             .selected_text(label)
             .show_ui(ui, |ui| {
-                if ui
-                    .selectable_value(loader, LoaderType::File, "File")
-                    .clicked()
-                {
-                    context.set_loader(&LoaderType::File);
-                    context.refresh_cache();
-                };
-                if ui
-                    .selectable_value(loader, LoaderType::DefaultLoader, "Default")
-                    .clicked()
-                {
-                    context.set_loader(&LoaderType::DefaultLoader);
-                    context.refresh_cache();
-                };
+                for (loader_type, label) in [
+                    (LoaderType::File, "File"),
+                    (LoaderType::Sqlite, "SQLite"),
+                    (LoaderType::DefaultLoader, "Default"),
+                ] {
+                    if ui
+                        .selectable_value(loader, loader_type.clone(), label)
+                        .clicked()
+                    {
+                        if let Err(err) = context.set_loader(&loader_type) {
+                            menu_status.error = Some(format!("failed to open loader: {err}"));
+                        } else if let Err(err) = context.refresh_cache() {
+                            menu_status.error = Some(format!("failed to list files: {err}"));
+                        }
+                    };
+                }
             });
     });
 
     if ui.button("Apply").clicked() {
+        menu_status.error = None;
         match &ui_state {
             UIState::Simulation {
                 logger,
@@ -250,33 +276,50 @@ pub fn main_menu_toggle(
                 loader,
                 simulation_name,
             } => {
-                context.set_loader(loader);
                 // needs to be created
                 context.set_logger(logger.to_logger_type(simulation_name.to_owned()));
-                // needs to be created
-                context.set_controller(controller.clone());
-                next_visualizer_state.set(VisualizerState::Simulation);
+                if let Err(err) = context.set_loader(loader) {
+                    menu_status.error = Some(format!("failed to open loader: {err}"));
+                } else if let Err(err) = context.set_controller(controller.clone()) {
+                    menu_status.error =
+                        Some(format!("controller {controller:?} failed to load: {err}"));
+                } else if let Some(Err(err)) = context.load_drone() {
+                    menu_status.error = Some(format!("drone config failed to load: {err}"));
+                } else {
+                    next_visualizer_state.set(VisualizerState::Simulation);
+                }
             }
             UIState::Replay {
                 replay_id: Some(replay_id),
                 loader,
                 ..
             } => {
-                context.set_loader(loader);
                 context.set_replay_id(replay_id.to_owned());
                 // context.set_controller(controller.clone());
-                next_visualizer_state.set(VisualizerState::Replay);
+                // parse the replay here, so that a broken file is reported instead of panicking
+                // when entering the replay
+                if let Err(err) = context.set_loader(loader) {
+                    menu_status.error = Some(format!("failed to open loader: {err}"));
+                } else if let Err(err) = context.load_flight_log(replay_id) {
+                    menu_status.error = Some(format!("replay {replay_id} failed to load: {err}"));
+                } else {
+                    next_visualizer_state.set(VisualizerState::Replay);
+                }
             }
             _ => {
                 // We should warn of incomplete config
             }
         }
     }
+    if let Some(error) = &menu_status.error {
+        ui.colored_label(Color32::from_rgb(255, 80, 80), error);
+    }
 }
 
 pub fn menu_ui(
     mut egui_ctx: EguiContexts,
     ui_state: &mut UIState,
+    menu_status: &mut MenuStatus,
     context: &mut SimContext,
     next_visualizer_state: ResMut<NextState<VisualizerState>>,
 ) {
@@ -308,7 +351,7 @@ pub fn menu_ui(
             );
             let info_box = Rect::from_min_max(info_box_min, info_box_max);
             ui.allocate_new_ui(UiBuilder::new().max_rect(info_box), |ui| {
-                main_menu_toggle(ui, ui_state, context, next_visualizer_state);
+                main_menu_toggle(ui, ui_state, menu_status, context, next_visualizer_state);
             });
         });
 }
@@ -4,7 +4,7 @@ pub mod simulation;
 
 use crate::{
     sim::SimulationData,
-    ui::menu::{menu_ui, UIState},
+    ui::menu::{menu_ui, MenuStatus, UIState},
     Context, VisualizerState,
 };
 use bevy::prelude::{NextState, Res, ResMut, State};
@@ -16,6 +16,7 @@ pub fn draw_ui(
     ctx: EguiContexts,
     mut context: ResMut<Context>,
     mut ui_state: ResMut<UIState>,
+    mut menu_status: ResMut<MenuStatus>,
     state: Res<State<VisualizerState>>,
     sim_data: Option<Res<SimulationData>>,
     next_visualizer_state: ResMut<NextState<VisualizerState>>,
@@ -27,6 +28,7 @@ pub fn draw_ui(
             menu_ui(
                 ctx,
                 ui_state.as_mut(),
+                menu_status.as_mut(),
                 context.as_mut(),
                 next_visualizer_state,
             );